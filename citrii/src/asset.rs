@@ -1,9 +1,12 @@
+use crate::error::*;
+#[cfg(feature = "opengl")]
 use crate::model::*;
+#[cfg(feature = "opengl")]
 use crate::texture::*;
 use nom::number::complete::*;
 use nom::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TextureFormat {
     I4,
     I8,
@@ -20,7 +23,7 @@ pub enum TextureFormat {
 }
 
 impl TextureFormat {
-    fn from_code(code: u8) -> Option<TextureFormat> {
+    pub fn from_code(code: u8) -> Option<TextureFormat> {
         match code {
             0 => Some(TextureFormat::I4),
             1 => Some(TextureFormat::I8),
@@ -38,7 +41,24 @@ impl TextureFormat {
         }
     }
 
-    fn bit_per_pixel(&self) -> usize {
+    pub fn code(&self) -> u8 {
+        match self {
+            TextureFormat::I4 => 0,
+            TextureFormat::I8 => 1,
+            TextureFormat::A4 => 2,
+            TextureFormat::A8 => 3,
+            TextureFormat::IA4 => 4,
+            TextureFormat::IA8 => 5,
+            TextureFormat::RG8 => 6,
+            TextureFormat::RGB565 => 7,
+            TextureFormat::RGB8 => 8,
+            TextureFormat::RGB5A1 => 9,
+            TextureFormat::RGBA4 => 10,
+            TextureFormat::RGBA8 => 11,
+        }
+    }
+
+    pub fn bit_per_pixel(&self) -> usize {
         match self {
             TextureFormat::I4 => 4,
             TextureFormat::I8 => 8,
@@ -55,7 +75,7 @@ impl TextureFormat {
         }
     }
 
-    fn decode_pixel_pair(&self, source: &[u8]) -> [u8; 8] {
+    pub fn decode_pixel_pair(&self, source: &[u8]) -> [u8; 8] {
         let convert1 = |v: u8| -> u8 { v * 255 };
         let convert4 = |v: u8| -> u8 { (v << 4) | v };
         let convert5 = |v: u8| -> u8 { (v << 3) | (v >> 2) };
@@ -151,10 +171,70 @@ impl TextureFormat {
             ],
         }
     }
+
+    // The reverse of decode_pixel_pair. Channels that the format doesn't store are dropped, and
+    // intensity is the average of RGB.
+    pub fn encode_pixel_pair(&self, source: &[u8; 8], dest: &mut [u8]) {
+        let convert = |v: u8, bits: u32| -> u16 {
+            let max = (1u32 << bits) - 1;
+            ((v as u32 * max + 127) / 255) as u16
+        };
+        let intensity =
+            |p: &[u8]| -> u8 { ((p[0] as u32 + p[1] as u32 + p[2] as u32 + 1) / 3) as u8 };
+        let (x, y) = (&source[0..4], &source[4..8]);
+        let mut write16 = |f: &dyn Fn(&[u8]) -> u16| {
+            dest[0..2].copy_from_slice(&f(x).to_le_bytes());
+            dest[2..4].copy_from_slice(&f(y).to_le_bytes());
+        };
+        match self {
+            TextureFormat::I4 => {
+                dest[0] = (convert(intensity(x), 4) | convert(intensity(y), 4) << 4) as u8
+            }
+            TextureFormat::I8 => dest[0..2].copy_from_slice(&[intensity(x), intensity(y)]),
+            TextureFormat::A4 => dest[0] = (convert(x[3], 4) | convert(y[3], 4) << 4) as u8,
+            TextureFormat::A8 => dest[0..2].copy_from_slice(&[x[3], y[3]]),
+            TextureFormat::IA4 => {
+                dest[0] = (convert(x[3], 4) | convert(intensity(x), 4) << 4) as u8;
+                dest[1] = (convert(y[3], 4) | convert(intensity(y), 4) << 4) as u8;
+            }
+            TextureFormat::IA8 => {
+                dest[0..4].copy_from_slice(&[x[3], intensity(x), y[3], intensity(y)])
+            }
+            TextureFormat::RG8 => dest[0..4].copy_from_slice(&[x[1], x[0], y[1], y[0]]),
+            TextureFormat::RGB565 => {
+                write16(&|p| convert(p[0], 5) << 11 | convert(p[1], 6) << 5 | convert(p[2], 5))
+            }
+            TextureFormat::RGB8 => {
+                dest[0..6].copy_from_slice(&[x[2], x[1], x[0], y[2], y[1], y[0]])
+            }
+            TextureFormat::RGB5A1 => write16(&|p| {
+                convert(p[0], 5) << 11
+                    | convert(p[1], 5) << 6
+                    | convert(p[2], 5) << 1
+                    | convert(p[3], 1)
+            }),
+            TextureFormat::RGBA4 => write16(&|p| {
+                convert(p[0], 4) << 12
+                    | convert(p[1], 4) << 8
+                    | convert(p[2], 4) << 4
+                    | convert(p[3], 4)
+            }),
+            TextureFormat::RGBA8 => {
+                dest[0..8].copy_from_slice(&[x[3], x[2], x[1], x[0], y[3], y[2], y[1], y[0]])
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    Edge,
+    Mirror,
+    Repeat,
 }
 
 impl WrapMode {
-    fn from_code(code: u8) -> Option<WrapMode> {
+    pub fn from_code(code: u8) -> Option<WrapMode> {
         match code {
             0 => Some(WrapMode::Edge),
             1 => Some(WrapMode::Repeat),
@@ -162,6 +242,14 @@ impl WrapMode {
             _ => None,
         }
     }
+
+    pub fn code(self) -> u8 {
+        match self {
+            WrapMode::Edge => 0,
+            WrapMode::Repeat => 1,
+            WrapMode::Mirror => 2,
+        }
+    }
 }
 
 fn padded_size(s: u16) -> usize {
@@ -173,7 +261,7 @@ fn padded_size(s: u16) -> usize {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RawTexture {
     pub width: u16,
     pub height: u16,
@@ -183,23 +271,59 @@ pub struct RawTexture {
     pub pixels: Vec<u8>,
 }
 
+// Index of the pixel pair starting at (x, y) in the tiled layout, with the first row at the top
+fn pixel_pair_index(x: u16, y: u16, width: u16, height: u16) -> usize {
+    let xlut = [0x00, 0x01, 0x04, 0x05, 0x10, 0x11, 0x14, 0x15];
+    let ylut = [0x00, 0x02, 0x08, 0x0a, 0x20, 0x22, 0x28, 0x2a];
+    let padded_width = padded_size(width);
+    let my = height - y - 1;
+    let cx = x / 8;
+    let cy = my / 8;
+    let fx = x % 8;
+    let fy = my % 8;
+    let fo = xlut[fx as usize] + ylut[fy as usize];
+    (((cx as usize) + (cy as usize) * padded_width / 8) * 64 + fo) / 2
+}
+
 impl RawTexture {
-    fn decode(&self) -> Vec<u8> {
+    // Converts RGBA pixels into the tiled layout. The width must be even.
+    pub fn encode(
+        width: u16,
+        height: u16,
+        format: TextureFormat,
+        wrap_u: WrapMode,
+        wrap_v: WrapMode,
+        rgba: &[u8],
+    ) -> RawTexture {
+        let pair_size = format.bit_per_pixel() * 2 / 8;
+        let mut pixels =
+            vec![0; padded_size(width) * padded_size(height) * format.bit_per_pixel() / 8];
+        for y in 0..height {
+            for x in (0..width).step_by(2) {
+                let begin = (y as usize * width as usize + x as usize) * 4;
+                let mut pair = [0; 8];
+                pair.copy_from_slice(&rgba[begin..begin + 8]);
+                let o = pixel_pair_index(x, y, width, height);
+                format.encode_pixel_pair(&pair, &mut pixels[pair_size * o..pair_size * (o + 1)]);
+            }
+        }
+        RawTexture {
+            width,
+            height,
+            format,
+            wrap_u,
+            wrap_v,
+            pixels,
+        }
+    }
+
+    pub fn decode(&self) -> Vec<u8> {
         let mut result =
             Vec::<u8>::with_capacity((self.width as usize) * (self.height as usize) * 4);
-        let padded_width = padded_size(self.width);
         let pair_size = self.format.bit_per_pixel() * 2 / 8;
-        let xlut = [0x00, 0x01, 0x04, 0x05, 0x10, 0x11, 0x14, 0x15];
-        let ylut = [0x00, 0x02, 0x08, 0x0a, 0x20, 0x22, 0x28, 0x2a];
         for y in 0..self.height {
             for x in (0..self.width).step_by(2) {
-                let my = self.height - y - 1;
-                let cx = x / 8;
-                let cy = my / 8;
-                let fx = x % 8;
-                let fy = my % 8;
-                let fo = xlut[fx as usize] + ylut[fy as usize];
-                let o = (((cx as usize) + (cy as usize) * padded_width / 8) * 64 + fo) / 2;
+                let o = pixel_pair_index(x, y, self.width, self.height);
                 result.extend_from_slice(
                     &self
                         .format
@@ -210,7 +334,23 @@ impl RawTexture {
         result
     }
 
-    fn bake(&self) -> Texture {
+    // The inverse of parse_texture
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![];
+        result.extend_from_slice(&self.width.to_le_bytes());
+        result.extend_from_slice(&self.height.to_le_bytes());
+        result.extend_from_slice(&[
+            1,
+            self.format.code(),
+            self.wrap_u.code(),
+            self.wrap_v.code(),
+        ]);
+        result.extend_from_slice(&self.pixels);
+        result
+    }
+
+    #[cfg(feature = "opengl")]
+    pub fn bake(&self) -> Texture {
         let decoded = self.decode();
         Texture::new(
             self.width as usize,
@@ -222,7 +362,7 @@ impl RawTexture {
     }
 }
 
-named!(parse_texture<&[u8], RawTexture>,
+named!(pub parse_texture<&[u8], RawTexture>,
     do_parse!(
         width: le_u16 >>
         height: le_u16 >>
@@ -235,7 +375,7 @@ named!(parse_texture<&[u8], RawTexture>,
     )
 );
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AttributeMode {
     None,
     Common,
@@ -254,7 +394,7 @@ impl AttributeMode {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RawModel {
     pub normal_mode: AttributeMode,
     pub texcoord_mode: AttributeMode,
@@ -262,9 +402,130 @@ pub struct RawModel {
     pub default_normal: (i16, i16, i16),
     pub default_texcoord: (i16, i16),
     pub index_list: Vec<u8>,
+
+    // Data of unknown meaning around the model in some sections, kept for repacking: hair
+    // models are preceded by 0x48 bytes, and face canvas models are followed by two bytes per
+    // triangle.
+    pub header: Vec<u8>,
+    pub trailer: Vec<u8>,
+}
+
+// A vertex in the units used by the shader. Attributes missing from the model are None.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelVertex {
+    pub coord: (f32, f32, f32),
+    pub normal: Option<(f32, f32, f32)>,
+    pub texcoord: Option<(f32, f32)>,
 }
 
 impl RawModel {
+    pub fn vertices(&self) -> Vec<ModelVertex> {
+        let read = |data: &[u8], i: usize| i16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
+        let normal_len = match self.normal_mode {
+            AttributeMode::Individual => 3,
+            _ => 0,
+        };
+        let texcoord_len = match self.texcoord_mode {
+            AttributeMode::Individual => 2,
+            _ => 0,
+        };
+        let stride = (3 + normal_len + texcoord_len) * 2;
+        self.vertex_list
+            .chunks_exact(stride)
+            .map(|data| {
+                let normal = match self.normal_mode {
+                    AttributeMode::Individual => {
+                        Some((read(data, 3), read(data, 4), read(data, 5)))
+                    }
+                    AttributeMode::Common => Some(self.default_normal),
+                    AttributeMode::None => None,
+                };
+                let texcoord = match self.texcoord_mode {
+                    AttributeMode::Individual => {
+                        Some((read(data, 3 + normal_len), read(data, 4 + normal_len)))
+                    }
+                    AttributeMode::Common => Some(self.default_texcoord),
+                    AttributeMode::None => None,
+                };
+                ModelVertex {
+                    coord: (
+                        read(data, 0) as f32 / 256.0,
+                        read(data, 1) as f32 / 256.0,
+                        read(data, 2) as f32 / 256.0,
+                    ),
+                    normal: normal
+                        .map(|n| (n.0 as f32 / 256.0, n.1 as f32 / 256.0, n.2 as f32 / 256.0)),
+                    texcoord: texcoord.map(|t| (t.0 as f32 / 8192.0, t.1 as f32 / 8192.0)),
+                }
+            })
+            .collect()
+    }
+
+    pub fn stride(&self) -> usize {
+        let mut stride = 6;
+        if self.normal_mode == AttributeMode::Individual {
+            stride += 6;
+        }
+        if self.texcoord_mode == AttributeMode::Individual {
+            stride += 4;
+        }
+        stride
+    }
+
+    // The inverse of parse_model, including header and trailer
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let vertex_count = (self.vertex_list.len() / self.stride()) as u16;
+        let attribute_count = |mode: AttributeMode| -> u16 {
+            match mode {
+                AttributeMode::None => 0,
+                AttributeMode::Common => 1,
+                AttributeMode::Individual => vertex_count.max(2),
+            }
+        };
+        let index_list_count = if self.index_list.is_empty() { 0 } else { 1 };
+
+        let mut result = self.header.clone();
+        for v in &[
+            vertex_count,
+            attribute_count(self.normal_mode),
+            attribute_count(self.texcoord_mode),
+            index_list_count,
+        ] {
+            result.extend_from_slice(&v.to_le_bytes());
+        }
+        result.extend_from_slice(&self.vertex_list);
+        if self.normal_mode == AttributeMode::Common {
+            let n = self.default_normal;
+            for v in &[n.0, n.1, n.2] {
+                result.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        if self.texcoord_mode == AttributeMode::Common {
+            let t = self.default_texcoord;
+            for v in &[t.0, t.1] {
+                result.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        if index_list_count == 1 {
+            result.extend_from_slice(&[4, 0]);
+            result.extend_from_slice(&(self.index_list.len() as u16).to_le_bytes());
+            result.extend_from_slice(&self.index_list);
+        }
+        result.extend_from_slice(&self.trailer);
+        result
+    }
+
+    // Triangles with indices out of range are skipped.
+    pub fn triangles(&self) -> Vec<[usize; 3]> {
+        let vertex_count = self.vertices().len();
+        self.index_list
+            .chunks_exact(3)
+            .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+            .filter(|t| t.iter().all(|&i| i < vertex_count))
+            .collect()
+    }
+
+    #[cfg(feature = "opengl")]
     pub fn bake(&self) -> Model {
         let mut attribute_map = vec![(
             0u32,
@@ -323,7 +584,7 @@ impl RawModel {
     }
 }
 
-named!(parse_model<&[u8], RawModel>,
+named!(pub parse_model<&[u8], RawModel>,
     do_parse!(
         vertex_count: le_u16 >>
         normal_count: le_u16 >>
@@ -357,19 +618,21 @@ named!(parse_model<&[u8], RawModel>,
             vertex_list,
             default_normal,
             default_texcoord,
-            index_list
+            index_list,
+            header: vec!(),
+            trailer: vec!()
         })
     )
 );
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FaceConfig {
     pub hair_pos: (f32, f32, f32),
     pub nose_pos: (f32, f32, f32),
     pub beard_pos: (f32, f32, f32),
 }
 
-named!(parse_face_config<&[u8], FaceConfig>,
+named!(pub parse_face_config<&[u8], FaceConfig>,
     do_parse!(
         hair_pos: tuple!(le_f32, le_f32, le_f32) >>
         nose_pos: tuple!(le_f32, le_f32, le_f32) >>
@@ -378,6 +641,19 @@ named!(parse_face_config<&[u8], FaceConfig>,
     )
 );
 
+impl FaceConfig {
+    // The inverse of parse_face_config
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(36);
+        for pos in &[self.hair_pos, self.nose_pos, self.beard_pos] {
+            for v in &[pos.0, pos.1, pos.2] {
+                result.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        result
+    }
+}
+
 #[derive(Debug)]
 struct AssetHeader {
     pub version: u16,
@@ -395,82 +671,324 @@ named!(parse_header<&[u8], AssetHeader>,
 
 #[derive(Debug)]
 struct SectionHeader {
+    pub buffer_size: u16,
     pub item_offsets: Vec<u32>,
 }
 
 named!(parse_section_header<&[u8], SectionHeader>,
     do_parse!(
         item_count: le_u16 >>
-        take!(2) >> // skip buffer size
+        buffer_size: le_u16 >>
         item_offsets: count!(le_u32, (item_count + 1) as usize) >>
-        (SectionHeader{item_offsets})
+        (SectionHeader{buffer_size, item_offsets})
     )
 );
 
+const SECTION_COUNT: usize = 20;
+const HAIR_HEADER_LEN: usize = 0x48;
+
+// Item offsets have 22 bits. The upper 10 bits redirect to another item of the section.
+const OFFSET_MASK: u32 = 0x3FFFFF;
+const MAX_REDIRECT: usize = 0x3FF;
+
+// Model sections 0-8 in the order they appear in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelPart {
+    Beard,
+    Accessory,
+    Face,
+    Scalp,
+    Glass,
+    Hair,
+    FaceCanvas,
+    NoseCanvas,
+    Nose,
+}
+
+// Texture sections 9-19 in the order they appear in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TexturePart {
+    Accessory,
+    Eye,
+    Eyebrow,
+    Beard,
+    Wrinkle,
+    Makeup,
+    Glass,
+    Mole,
+    Lip,
+    Mustache,
+    Nose,
+}
+
+impl ModelPart {
+    pub const ALL: [ModelPart; 9] = [
+        ModelPart::Beard,
+        ModelPart::Accessory,
+        ModelPart::Face,
+        ModelPart::Scalp,
+        ModelPart::Glass,
+        ModelPart::Hair,
+        ModelPart::FaceCanvas,
+        ModelPart::NoseCanvas,
+        ModelPart::Nose,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ModelPart::Beard => "beard",
+            ModelPart::Accessory => "accessory",
+            ModelPart::Face => "face",
+            ModelPart::Scalp => "scalp",
+            ModelPart::Glass => "glass",
+            ModelPart::Hair => "hair",
+            ModelPart::FaceCanvas => "face_canvas",
+            ModelPart::NoseCanvas => "nose_canvas",
+            ModelPart::Nose => "nose",
+        }
+    }
+}
+
+impl TexturePart {
+    pub const ALL: [TexturePart; 11] = [
+        TexturePart::Accessory,
+        TexturePart::Eye,
+        TexturePart::Eyebrow,
+        TexturePart::Beard,
+        TexturePart::Wrinkle,
+        TexturePart::Makeup,
+        TexturePart::Glass,
+        TexturePart::Mole,
+        TexturePart::Lip,
+        TexturePart::Mustache,
+        TexturePart::Nose,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TexturePart::Accessory => "accessory",
+            TexturePart::Eye => "eye",
+            TexturePart::Eyebrow => "eyebrow",
+            TexturePart::Beard => "beard",
+            TexturePart::Wrinkle => "wrinkle",
+            TexturePart::Makeup => "makeup",
+            TexturePart::Glass => "glass",
+            TexturePart::Mole => "mole",
+            TexturePart::Lip => "lip",
+            TexturePart::Mustache => "mustache",
+            TexturePart::Nose => "nose",
+        }
+    }
+}
+
+// The content of CFL_Res.dat, with models and textures in a form chosen by the renderer.
 #[derive(Debug)]
-pub struct Asset {
+pub struct AssetData<M, T> {
     pub version: u16,
+    // The second field of each section header, kept as read for repacking. It might be the
+    // size of the buffer the game allocates for the largest item.
+    pub buffer_sizes: Vec<u16>,
 
-    pub beard_models: Vec<Option<Model>>,
-    pub accessory_models: Vec<Option<Model>>,
-    pub face_models: Vec<Option<Model>>,
-    pub scalp_models: Vec<Option<Model>>,
-    pub glass_models: Vec<Option<Model>>,
-    pub hair_models: Vec<Option<Model>>,
-    pub face_canvas_models: Vec<Option<Model>>,
-    pub nose_canvas_models: Vec<Option<Model>>,
-    pub nose_models: Vec<Option<Model>>,
-
-    pub accessory_textures: Vec<Option<Texture>>,
-    pub eye_textures: Vec<Option<Texture>>,
-    pub eyebrow_textures: Vec<Option<Texture>>,
-    pub beard_textures: Vec<Option<Texture>>,
-    pub wrinkle_textures: Vec<Option<Texture>>,
-    pub makeup_textures: Vec<Option<Texture>>,
-    pub glass_textures: Vec<Option<Texture>>,
-    pub mole_textures: Vec<Option<Texture>>,
-    pub lip_textures: Vec<Option<Texture>>,
-    pub mustache_textures: Vec<Option<Texture>>,
-    pub nose_textures: Vec<Option<Texture>>,
+    pub beard_models: Vec<Option<M>>,
+    pub accessory_models: Vec<Option<M>>,
+    pub face_models: Vec<Option<M>>,
+    pub scalp_models: Vec<Option<M>>,
+    pub glass_models: Vec<Option<M>>,
+    pub hair_models: Vec<Option<M>>,
+    pub face_canvas_models: Vec<Option<M>>,
+    pub nose_canvas_models: Vec<Option<M>>,
+    pub nose_models: Vec<Option<M>>,
+
+    pub accessory_textures: Vec<Option<T>>,
+    pub eye_textures: Vec<Option<T>>,
+    pub eyebrow_textures: Vec<Option<T>>,
+    pub beard_textures: Vec<Option<T>>,
+    pub wrinkle_textures: Vec<Option<T>>,
+    pub makeup_textures: Vec<Option<T>>,
+    pub glass_textures: Vec<Option<T>>,
+    pub mole_textures: Vec<Option<T>>,
+    pub lip_textures: Vec<Option<T>>,
+    pub mustache_textures: Vec<Option<T>>,
+    pub nose_textures: Vec<Option<T>>,
 
     pub face_configs: Vec<Option<FaceConfig>>,
 }
 
-impl Asset {
-    pub fn from_bytes(bytes: &[u8]) -> Option<Asset> {
+#[cfg(feature = "opengl")]
+pub type Asset = AssetData<Model, Texture>;
+
+impl<M, T> AssetData<M, T> {
+    pub fn models(&self, part: ModelPart) -> &[Option<M>] {
+        match part {
+            ModelPart::Beard => &self.beard_models,
+            ModelPart::Accessory => &self.accessory_models,
+            ModelPart::Face => &self.face_models,
+            ModelPart::Scalp => &self.scalp_models,
+            ModelPart::Glass => &self.glass_models,
+            ModelPart::Hair => &self.hair_models,
+            ModelPart::FaceCanvas => &self.face_canvas_models,
+            ModelPart::NoseCanvas => &self.nose_canvas_models,
+            ModelPart::Nose => &self.nose_models,
+        }
+    }
+
+    pub fn textures(&self, part: TexturePart) -> &[Option<T>] {
+        match part {
+            TexturePart::Accessory => &self.accessory_textures,
+            TexturePart::Eye => &self.eye_textures,
+            TexturePart::Eyebrow => &self.eyebrow_textures,
+            TexturePart::Beard => &self.beard_textures,
+            TexturePart::Wrinkle => &self.wrinkle_textures,
+            TexturePart::Makeup => &self.makeup_textures,
+            TexturePart::Glass => &self.glass_textures,
+            TexturePart::Mole => &self.mole_textures,
+            TexturePart::Lip => &self.lip_textures,
+            TexturePart::Mustache => &self.mustache_textures,
+            TexturePart::Nose => &self.nose_textures,
+        }
+    }
+
+    pub fn models_mut(&mut self, part: ModelPart) -> &mut Vec<Option<M>> {
+        match part {
+            ModelPart::Beard => &mut self.beard_models,
+            ModelPart::Accessory => &mut self.accessory_models,
+            ModelPart::Face => &mut self.face_models,
+            ModelPart::Scalp => &mut self.scalp_models,
+            ModelPart::Glass => &mut self.glass_models,
+            ModelPart::Hair => &mut self.hair_models,
+            ModelPart::FaceCanvas => &mut self.face_canvas_models,
+            ModelPart::NoseCanvas => &mut self.nose_canvas_models,
+            ModelPart::Nose => &mut self.nose_models,
+        }
+    }
+
+    pub fn textures_mut(&mut self, part: TexturePart) -> &mut Vec<Option<T>> {
+        match part {
+            TexturePart::Accessory => &mut self.accessory_textures,
+            TexturePart::Eye => &mut self.eye_textures,
+            TexturePart::Eyebrow => &mut self.eyebrow_textures,
+            TexturePart::Beard => &mut self.beard_textures,
+            TexturePart::Wrinkle => &mut self.wrinkle_textures,
+            TexturePart::Makeup => &mut self.makeup_textures,
+            TexturePart::Glass => &mut self.glass_textures,
+            TexturePart::Mole => &mut self.mole_textures,
+            TexturePart::Lip => &mut self.lip_textures,
+            TexturePart::Mustache => &mut self.mustache_textures,
+            TexturePart::Nose => &mut self.nose_textures,
+        }
+    }
+
+    pub fn model(&self, part: ModelPart, index: usize) -> Option<&M> {
+        self.models(part).get(index).and_then(|o| o.as_ref())
+    }
+
+    pub fn texture(&self, part: TexturePart, index: usize) -> Option<&T> {
+        self.textures(part).get(index).and_then(|o| o.as_ref())
+    }
+
+    // Converts each model and texture, such as uploading them to the GPU.
+    pub fn map<M2, T2>(
+        &self,
+        bake_model: impl Fn(&M) -> M2,
+        bake_texture: impl Fn(&T) -> T2,
+    ) -> AssetData<M2, T2> {
+        let models = |list: &[Option<M>]| -> Vec<Option<M2>> {
+            list.iter().map(|m| m.as_ref().map(&bake_model)).collect()
+        };
+        let textures = |list: &[Option<T>]| -> Vec<Option<T2>> {
+            list.iter().map(|t| t.as_ref().map(&bake_texture)).collect()
+        };
+        AssetData {
+            version: self.version,
+            buffer_sizes: self.buffer_sizes.clone(),
+            beard_models: models(&self.beard_models),
+            accessory_models: models(&self.accessory_models),
+            face_models: models(&self.face_models),
+            scalp_models: models(&self.scalp_models),
+            glass_models: models(&self.glass_models),
+            hair_models: models(&self.hair_models),
+            face_canvas_models: models(&self.face_canvas_models),
+            nose_canvas_models: models(&self.nose_canvas_models),
+            nose_models: models(&self.nose_models),
+
+            accessory_textures: textures(&self.accessory_textures),
+            eye_textures: textures(&self.eye_textures),
+            eyebrow_textures: textures(&self.eyebrow_textures),
+            beard_textures: textures(&self.beard_textures),
+            wrinkle_textures: textures(&self.wrinkle_textures),
+            makeup_textures: textures(&self.makeup_textures),
+            glass_textures: textures(&self.glass_textures),
+            mole_textures: textures(&self.mole_textures),
+            lip_textures: textures(&self.lip_textures),
+            mustache_textures: textures(&self.mustache_textures),
+            nose_textures: textures(&self.nose_textures),
+
+            face_configs: self.face_configs.clone(),
+        }
+    }
+}
+
+// The asset file decoded without touching the GPU
+pub type DecodedAsset = AssetData<RawModel, RawTexture>;
+
+impl DecodedAsset {
+    pub fn from_bytes(bytes: &[u8]) -> Result<DecodedAsset, Error> {
         let header = match parse_header(bytes) {
-            Err(_) => return None,
+            Err(_) => return Err(Error::AssetHeaderCorrupt),
             Ok((_, header)) => header,
         };
+        if header.section_offsets.len() < SECTION_COUNT {
+            return Err(Error::AssetHeaderCorrupt);
+        }
 
-        #[derive(Debug)]
         enum Item {
-            Model(Model),
-            Texture(Texture),
+            Model(RawModel),
+            Texture(RawTexture),
         }
-
         let mut section_list =
             Vec::<Vec<Option<Item>>>::with_capacity(header.section_offsets.len());
         let mut face_configs = Vec::<Option<FaceConfig>>::new();
+        let mut buffer_sizes = Vec::<u16>::with_capacity(header.section_offsets.len());
         for section in 0..header.section_offsets.len() {
-            let (item_data_chunk, section_header) =
-                match parse_section_header(&bytes[header.section_offsets[section] as usize..]) {
-                    Err(_) => return None,
-                    Ok(result) => result,
-                };
+            let section_corrupt = Error::AssetSectionCorrupt {
+                section,
+                item: None,
+            };
+            let section_data = match bytes.get(header.section_offsets[section] as usize..) {
+                None => return Err(section_corrupt),
+                Some(data) => data,
+            };
+            let (item_data_chunk, section_header) = match parse_section_header(section_data) {
+                Err(_) => return Err(section_corrupt),
+                Ok(result) => result,
+            };
+            buffer_sizes.push(section_header.buffer_size);
             let item_count = section_header.item_offsets.len() - 1;
             let mut item_list = Vec::<Option<Item>>::with_capacity(item_count);
             for item in 0..item_count {
+                let corrupt = || Error::AssetSectionCorrupt {
+                    section,
+                    item: Some(item),
+                };
                 let mut item_k = item;
                 let mut begin = section_header.item_offsets[item_k];
                 let redirect = begin >> 22;
                 if redirect != 0 {
                     item_k = (redirect - 1) as usize;
-                    begin = section_header.item_offsets[item_k];
+                    begin = match section_header.item_offsets.get(item_k) {
+                        None => return Err(corrupt()),
+                        Some(begin) => *begin,
+                    };
                 }
-                begin &= 0x3FFFFF;
-                let end = section_header.item_offsets[item_k + 1] & 0x3FFFFF;
-                let item_data = &item_data_chunk[begin as usize..end as usize];
+                begin &= OFFSET_MASK;
+                let end = match section_header.item_offsets.get(item_k + 1) {
+                    None => return Err(corrupt()),
+                    Some(end) => end & OFFSET_MASK,
+                };
+                let item_data = match item_data_chunk.get(begin as usize..end as usize) {
+                    None => return Err(corrupt()),
+                    Some(data) => data,
+                };
 
                 if item_data.is_empty() {
                     item_list.push(None);
@@ -482,45 +1000,54 @@ impl Asset {
 
                 if section >= 9 {
                     let (rest, texture) = match parse_texture(item_data) {
-                        Err(_) => return None,
+                        Err(_) => return Err(corrupt()),
                         Ok(t) => t,
                     };
                     if rest.len() >= 4 {
-                        return None;
+                        return Err(corrupt());
                     }
-                    item_list.push(Some(Item::Texture(texture.bake())));
+                    item_list.push(Some(Item::Texture(texture)));
                 } else {
+                    let mut header: &[u8] = &[];
                     let model_data = match section {
                         2 => {
                             let (model_data, face_config) = match parse_face_config(item_data) {
-                                Err(_) => return None,
+                                Err(_) => return Err(corrupt()),
                                 Ok(c) => c,
                             };
                             face_configs.push(Some(face_config));
                             model_data
                         }
-                        5 => &item_data[0x48..],
+                        5 => {
+                            if item_data.len() < HAIR_HEADER_LEN {
+                                return Err(corrupt());
+                            }
+                            header = &item_data[..HAIR_HEADER_LEN];
+                            &item_data[HAIR_HEADER_LEN..]
+                        }
                         _ => item_data,
                     };
-                    let (rest, model) = match parse_model(model_data) {
-                        Err(_) => return None,
+                    let (rest, mut model) = match parse_model(model_data) {
+                        Err(_) => return Err(corrupt()),
                         Ok(m) => m,
                     };
+                    model.header = header.to_vec();
                     if section == 6 {
                         let cover_data_len = model.index_list.len() / 3 * 2;
                         if rest.len() < cover_data_len || rest.len() >= cover_data_len + 4 {
-                            return None;
+                            return Err(corrupt());
                         }
+                        model.trailer = rest[..cover_data_len].to_vec();
                     } else if rest.len() >= 4 {
-                        return None;
+                        return Err(corrupt());
                     }
-                    item_list.push(Some(Item::Model(model.bake())));
+                    item_list.push(Some(Item::Model(model)));
                 }
             }
             section_list.push(item_list);
         }
 
-        let unpack_model = |item: Option<Item>| -> Option<Model> {
+        let unpack_model = |item: Option<Item>| -> Option<RawModel> {
             match item {
                 None => None,
                 Some(Item::Model(model)) => Some(model),
@@ -528,7 +1055,7 @@ impl Asset {
             }
         };
 
-        let unpack_texture = |item: Option<Item>| -> Option<Texture> {
+        let unpack_texture = |item: Option<Item>| -> Option<RawTexture> {
             match item {
                 None => None,
                 Some(Item::Texture(texture)) => Some(texture),
@@ -536,8 +1063,9 @@ impl Asset {
             }
         };
 
-        let asset = Asset {
+        let asset = AssetData {
             version: header.version,
+            buffer_sizes,
             beard_models: section_list[0].drain(..).map(unpack_model).collect(),
             accessory_models: section_list[1].drain(..).map(unpack_model).collect(),
             face_models: section_list[2].drain(..).map(unpack_model).collect(),
@@ -562,6 +1090,266 @@ impl Asset {
 
             face_configs,
         };
-        Some(asset)
+        Ok(asset)
+    }
+
+    // Creates the OpenGL objects. Requires a current OpenGL context.
+    #[cfg(feature = "opengl")]
+    pub fn upload(&self) -> Asset {
+        self.map(RawModel::bake, RawTexture::bake)
     }
+
+    fn model_item(&self, part: ModelPart, index: usize) -> Result<Vec<u8>, Error> {
+        let model = match self.model(part, index) {
+            None => return Ok(vec![]),
+            Some(model) => model,
+        };
+        let invalid = || Error::AssetItemInvalid {
+            section: part as usize,
+            item: index,
+        };
+        let header_len = if part == ModelPart::Hair {
+            HAIR_HEADER_LEN
+        } else {
+            0
+        };
+        let trailer_len = if part == ModelPart::FaceCanvas {
+            model.index_list.len() / 3 * 2
+        } else {
+            0
+        };
+        if model.header.len() != header_len
+            || model.trailer.len() != trailer_len
+            || model.vertex_list.len() % model.stride() != 0
+            || model.vertex_list.len() / model.stride() > u16::MAX as usize
+            || model.index_list.len() > u16::MAX as usize
+        {
+            return Err(invalid());
+        }
+
+        let mut result = vec![];
+        if part == ModelPart::Face {
+            match self.face_configs.get(index) {
+                Some(Some(face_config)) => result = face_config.to_bytes(),
+                _ => return Err(invalid()),
+            }
+        }
+        result.extend_from_slice(&model.to_bytes());
+        Ok(result)
+    }
+
+    // The inverse of from_bytes. Identical items in a section are stored once and redirected to.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut sections = Vec::<Vec<u8>>::with_capacity(SECTION_COUNT);
+        for section in 0..SECTION_COUNT {
+            let items = if section < ModelPart::ALL.len() {
+                let part = ModelPart::ALL[section];
+                (0..self.models(part).len())
+                    .map(|i| self.model_item(part, i))
+                    .collect::<Result<Vec<_>, _>>()?
+            } else {
+                let part = TexturePart::ALL[section - ModelPart::ALL.len()];
+                self.textures(part)
+                    .iter()
+                    .map(|t| t.as_ref().map_or(vec![], RawTexture::to_bytes))
+                    .collect()
+            };
+            let buffer_size = self.buffer_sizes.get(section).cloned().unwrap_or(0);
+            sections.push(
+                pack_section(&items, buffer_size).ok_or(Error::AssetSectionTooLarge(section))?,
+            );
+        }
+
+        let mut result = vec![];
+        result.extend_from_slice(&(SECTION_COUNT as u16).to_le_bytes());
+        result.extend_from_slice(&self.version.to_le_bytes());
+        let mut offset = 4 + SECTION_COUNT * 4;
+        for section in &sections {
+            result.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += section.len();
+        }
+        for section in &sections {
+            result.extend_from_slice(section);
+        }
+        Ok(result)
+    }
+}
+
+// Builds a section with its header. Items are padded to four bytes.
+fn pack_section(items: &[Vec<u8>], buffer_size: u16) -> Option<Vec<u8>> {
+    if items.len() > u16::MAX as usize {
+        return None;
+    }
+    let mut item_offsets = Vec::<u32>::with_capacity(items.len() + 1);
+    let mut item_data = vec![];
+    for (i, item) in items.iter().enumerate() {
+        let offset = item_data.len() as u32;
+        let original = if item.is_empty() {
+            None
+        } else {
+            items[..i].iter().position(|other| other == item)
+        };
+        match original {
+            Some(k) if k < MAX_REDIRECT => item_offsets.push(((k as u32 + 1) << 22) | offset),
+            _ => {
+                item_offsets.push(offset);
+                item_data.extend_from_slice(item);
+                item_data.resize((item_data.len() + 3) / 4 * 4, 0);
+            }
+        }
+    }
+    if item_data.len() > OFFSET_MASK as usize {
+        return None;
+    }
+    item_offsets.push(item_data.len() as u32);
+
+    let mut result = vec![];
+    result.extend_from_slice(&(items.len() as u16).to_le_bytes());
+    result.extend_from_slice(&buffer_size.to_le_bytes());
+    for offset in item_offsets {
+        result.extend_from_slice(&offset.to_le_bytes());
+    }
+    result.extend_from_slice(&item_data);
+    Some(result)
+}
+
+#[cfg(feature = "opengl")]
+impl Asset {
+    // Requires a current OpenGL context
+    pub fn from_bytes(bytes: &[u8]) -> Result<Asset, Error> {
+        Ok(DecodedAsset::from_bytes(bytes)?.upload())
+    }
+}
+
+// One face with a single triangle and one eye texture referenced twice
+#[cfg(test)]
+fn test_asset_bytes() -> Vec<u8> {
+    let mut face = vec![];
+    for v in &[0.0f32, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0] {
+        face.extend_from_slice(&v.to_le_bytes());
+    }
+    for v in &[3u16, 1, 0, 1] {
+        face.extend_from_slice(&v.to_le_bytes());
+    }
+    for v in &[0i16, 0, 0, 256, 0, 0, 0, 256, 0, 0, 0, 256] {
+        face.extend_from_slice(&v.to_le_bytes());
+    }
+    face.extend_from_slice(&[4, 0, 3, 0, 0, 1, 2]);
+
+    let mut eye = vec![8, 0, 8, 0, 1, 11, 0, 0];
+    eye.extend_from_slice(&[0xFF; 8 * 8 * 4]);
+
+    let mut sections: Vec<Vec<u8>> = vec![];
+    for section in 0..SECTION_COUNT {
+        let mut data = vec![];
+        let (item_offsets, item_data): (Vec<u32>, &[u8]) = match section {
+            2 => (vec![0, face.len() as u32], &face),
+            10 => {
+                let len = eye.len() as u32;
+                (vec![0, (1 << 22) | len, len], &eye)
+            }
+            _ => (vec![0], &[]),
+        };
+        data.extend_from_slice(&(item_offsets.len() as u16 - 1).to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+        for offset in item_offsets {
+            data.extend_from_slice(&offset.to_le_bytes());
+        }
+        data.extend_from_slice(item_data);
+        sections.push(data);
+    }
+    let mut bytes = vec![];
+    bytes.extend_from_slice(&(SECTION_COUNT as u16).to_le_bytes());
+    bytes.extend_from_slice(&7u16.to_le_bytes());
+    let mut offset = 4 + SECTION_COUNT * 4;
+    for section in &sections {
+        bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += section.len();
+    }
+    for section in &sections {
+        bytes.extend_from_slice(section);
+    }
+    bytes
+}
+
+#[test]
+fn decode_test() {
+    let bytes = test_asset_bytes();
+    let asset = DecodedAsset::from_bytes(&bytes).unwrap();
+    assert_eq!(asset.version, 7);
+    assert_eq!(asset.face_configs.len(), 1);
+    assert_eq!(
+        asset.face_configs[0].as_ref().unwrap().beard_pos,
+        (6.0, 7.0, 8.0)
+    );
+    assert_eq!(
+        asset.model(ModelPart::Face, 0).unwrap().index_list,
+        vec![0, 1, 2]
+    );
+    assert_eq!(asset.eye_textures.len(), 2);
+    let eye = asset.texture(TexturePart::Eye, 1).unwrap();
+    assert_eq!((eye.width, eye.height), (8, 8));
+    assert_eq!(eye.decode(), vec![0xFF; 8 * 8 * 4]);
+    assert!(asset.texture(TexturePart::Eye, 2).is_none());
+
+    assert!(matches!(
+        DecodedAsset::from_bytes(&bytes[0..bytes.len() - 1]),
+        Err(Error::AssetSectionCorrupt {
+            section: 19,
+            item: None
+        })
+    ));
+}
+
+#[test]
+fn repack_test() {
+    let mut asset = DecodedAsset::from_bytes(&test_asset_bytes()).unwrap();
+    let formats = (0..12).map(|code| TextureFormat::from_code(code).unwrap());
+    let rgba: Vec<u8> = (0..16 * 8 * 4).map(|i| (i * 17 % 256) as u8).collect();
+    for format in formats {
+        let texture = RawTexture::encode(16, 8, format, WrapMode::Mirror, WrapMode::Edge, &rgba);
+        let decoded = texture.decode();
+        let again = RawTexture::encode(16, 8, format, WrapMode::Mirror, WrapMode::Edge, &decoded);
+        assert_eq!(again.pixels, texture.pixels, "{:?}", format);
+        if format == TextureFormat::RGBA8 {
+            assert_eq!(decoded, rgba);
+        }
+        asset.mole_textures.push(Some(texture));
+    }
+
+    let bytes = asset.to_bytes().unwrap();
+    let repacked = DecodedAsset::from_bytes(&bytes).unwrap();
+    assert_eq!(repacked.to_bytes().unwrap(), bytes);
+    assert_eq!(
+        repacked.face_configs[0].as_ref().unwrap().beard_pos,
+        (6.0, 7.0, 8.0)
+    );
+    assert_eq!(
+        repacked.model(ModelPart::Face, 0).unwrap().index_list,
+        vec![0, 1, 2]
+    );
+    assert_eq!(repacked.eye_textures.len(), 2);
+    assert_eq!(repacked.mole_textures.len(), 12);
+    for (a, b) in asset.mole_textures.iter().zip(&repacked.mole_textures) {
+        let (a, b) = (a.as_ref().unwrap(), b.as_ref().unwrap());
+        assert_eq!(
+            (a.format, a.wrap_u, a.pixels.clone()),
+            (b.format, b.wrap_u, b.pixels.clone())
+        );
+    }
+
+    // The second eye texture is a redirect to the first one
+    let read_u32 = |d: &[u8]| u32::from_le_bytes([d[0], d[1], d[2], d[3]]);
+    let section = &bytes[read_u32(&bytes[4 + 10 * 4..]) as usize..];
+    let second_offset = read_u32(&section[8..]);
+    assert_eq!(second_offset >> 22, 1);
+
+    asset.face_configs[0] = None;
+    assert!(matches!(
+        asset.to_bytes(),
+        Err(Error::AssetItemInvalid {
+            section: 2,
+            item: 0
+        })
+    ));
 }
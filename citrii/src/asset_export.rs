@@ -0,0 +1,353 @@
+// Converts decoded assets into common formats: textures into PNG, models into Wavefront OBJ and
+// glTF, and face configurations into JSON.
+//
+// Coordinates are in the units of the shader. The texture coordinate t = 0 is the first row of
+// the exported PNG, so OBJ files store 1 - t as usual for that format.
+
+use crate::asset::*;
+use crate::error::*;
+use serde_json::json;
+use std::fmt::Write;
+
+pub fn texture_to_image(texture: &RawTexture) -> image::RgbaImage {
+    image::RgbaImage::from_raw(
+        texture.width as u32,
+        texture.height as u32,
+        texture.decode(),
+    )
+    .unwrap()
+}
+
+fn write_face_config_comment(result: &mut String, face_config: &FaceConfig) {
+    for (name, pos) in &[
+        ("hair_pos", face_config.hair_pos),
+        ("nose_pos", face_config.nose_pos),
+        ("beard_pos", face_config.beard_pos),
+    ] {
+        writeln!(result, "# {} {} {} {}", name, pos.0, pos.1, pos.2).unwrap();
+    }
+}
+
+pub fn model_to_obj(model: &RawModel, face_config: Option<&FaceConfig>) -> String {
+    let vertices = model.vertices();
+    let mut result = String::new();
+    if let Some(face_config) = face_config {
+        write_face_config_comment(&mut result, face_config);
+    }
+    for v in &vertices {
+        writeln!(result, "v {} {} {}", v.coord.0, v.coord.1, v.coord.2).unwrap();
+    }
+    for v in &vertices {
+        if let Some(t) = v.texcoord {
+            writeln!(result, "vt {} {}", t.0, 1.0 - t.1).unwrap();
+        }
+    }
+    for v in &vertices {
+        if let Some(n) = v.normal {
+            writeln!(result, "vn {} {} {}", n.0, n.1, n.2).unwrap();
+        }
+    }
+    let has_texcoord = vertices.first().map_or(false, |v| v.texcoord.is_some());
+    let has_normal = vertices.first().map_or(false, |v| v.normal.is_some());
+    for triangle in model.triangles() {
+        result.push('f');
+        for i in &triangle {
+            let i = i + 1;
+            match (has_texcoord, has_normal) {
+                (true, true) => write!(result, " {}/{}/{}", i, i, i),
+                (true, false) => write!(result, " {}/{}", i, i),
+                (false, true) => write!(result, " {}//{}", i, i),
+                (false, false) => write!(result, " {}", i),
+            }
+            .unwrap();
+        }
+        result.push('\n');
+    }
+    result
+}
+
+// JSON has no representation for infinities and NaN
+fn json_vec3(name: &'static str, v: (f32, f32, f32)) -> Result<serde_json::Value, Error> {
+    if !(v.0.is_finite() && v.1.is_finite() && v.2.is_finite()) {
+        return Err(Error::NotFinite(name));
+    }
+    Ok(json!([v.0, v.1, v.2]))
+}
+
+fn face_config_to_json(
+    c: &FaceConfig,
+) -> Result<serde_json::Map<String, serde_json::Value>, Error> {
+    let mut result = serde_json::Map::new();
+    result.insert("hair_pos".to_string(), json_vec3("hair_pos", c.hair_pos)?);
+    result.insert("nose_pos".to_string(), json_vec3("nose_pos", c.nose_pos)?);
+    result.insert(
+        "beard_pos".to_string(),
+        json_vec3("beard_pos", c.beard_pos)?,
+    );
+    Ok(result)
+}
+
+// A self-contained glTF 2.0 file with the buffer embedded. Returns None for models without
+// triangles, which glTF can't represent.
+pub fn model_to_gltf(
+    model: &RawModel,
+    face_config: Option<&FaceConfig>,
+) -> Result<Option<String>, Error> {
+    const FLOAT: u32 = 5126;
+    const UNSIGNED_SHORT: u32 = 5123;
+    const ARRAY_BUFFER: u32 = 34962;
+    const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+    let vertices = model.vertices();
+    let triangles = model.triangles();
+    if triangles.is_empty() {
+        return Ok(None);
+    }
+
+    let mut buffer: Vec<u8> = vec![];
+    let mut buffer_views = vec![];
+    let mut accessors = vec![];
+    let mut attributes = serde_json::Map::new();
+    let mut add_view = |buffer: &mut Vec<u8>, data: Vec<u8>, target: u32| {
+        buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": buffer.len(),
+            "byteLength": data.len(),
+            "target": target,
+        }));
+        buffer.extend_from_slice(&data);
+        while buffer.len() % 4 != 0 {
+            buffer.push(0);
+        }
+        buffer_views.len() - 1
+    };
+
+    let min = vertices
+        .iter()
+        .fold((f32::MAX, f32::MAX, f32::MAX), |m, v| {
+            (m.0.min(v.coord.0), m.1.min(v.coord.1), m.2.min(v.coord.2))
+        });
+    let max = vertices
+        .iter()
+        .fold((f32::MIN, f32::MIN, f32::MIN), |m, v| {
+            (m.0.max(v.coord.0), m.1.max(v.coord.1), m.2.max(v.coord.2))
+        });
+    let data = vertices
+        .iter()
+        .flat_map(|v| [v.coord.0, v.coord.1, v.coord.2])
+        .flat_map(f32::to_le_bytes)
+        .collect();
+    let view = add_view(&mut buffer, data, ARRAY_BUFFER);
+    accessors.push(json!({
+        "bufferView": view,
+        "componentType": FLOAT,
+        "count": vertices.len(),
+        "type": "VEC3",
+        "min": json_vec3("POSITION", min)?,
+        "max": json_vec3("POSITION", max)?,
+    }));
+    attributes.insert("POSITION".to_string(), json!(accessors.len() - 1));
+
+    if vertices.iter().all(|v| v.normal.is_some()) {
+        // glTF requires unit normals
+        let data = vertices
+            .iter()
+            .flat_map(|v| {
+                let n = v.normal.unwrap();
+                let len = (n.0 * n.0 + n.1 * n.1 + n.2 * n.2).sqrt();
+                if len > 0.0 {
+                    [n.0 / len, n.1 / len, n.2 / len]
+                } else {
+                    [0.0, 0.0, 1.0]
+                }
+            })
+            .flat_map(f32::to_le_bytes)
+            .collect();
+        let view = add_view(&mut buffer, data, ARRAY_BUFFER);
+        accessors.push(json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": vertices.len(),
+            "type": "VEC3",
+        }));
+        attributes.insert("NORMAL".to_string(), json!(accessors.len() - 1));
+    }
+
+    if vertices.iter().all(|v| v.texcoord.is_some()) {
+        let data = vertices
+            .iter()
+            .flat_map(|v| {
+                let t = v.texcoord.unwrap();
+                [t.0, t.1]
+            })
+            .flat_map(f32::to_le_bytes)
+            .collect();
+        let view = add_view(&mut buffer, data, ARRAY_BUFFER);
+        accessors.push(json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": vertices.len(),
+            "type": "VEC2",
+        }));
+        attributes.insert("TEXCOORD_0".to_string(), json!(accessors.len() - 1));
+    }
+
+    let data = triangles
+        .iter()
+        .flatten()
+        .flat_map(|&i| (i as u16).to_le_bytes())
+        .collect();
+    let view = add_view(&mut buffer, data, ELEMENT_ARRAY_BUFFER);
+    accessors.push(json!({
+        "bufferView": view,
+        "componentType": UNSIGNED_SHORT,
+        "count": triangles.len() * 3,
+        "type": "SCALAR",
+    }));
+    let indices = accessors.len() - 1;
+
+    let mut node = json!({"mesh": 0});
+    if let Some(c) = face_config {
+        node["extras"] = face_config_to_json(c)?.into();
+    }
+
+    let gltf = json!({
+        "asset": {"version": "2.0", "generator": "citrii"},
+        "scene": 0,
+        "scenes": [{"nodes": [0]}],
+        "nodes": [node],
+        "meshes": [{"primitives": [{"attributes": attributes, "indices": indices}]}],
+        "buffers": [{
+            "byteLength": buffer.len(),
+            "uri": format!("data:application/octet-stream;base64,{}", base64::encode(&buffer)),
+        }],
+        "bufferViews": buffer_views,
+        "accessors": accessors,
+    });
+    Ok(Some(serde_json::to_string_pretty(&gltf).unwrap() + "\n"))
+}
+
+pub fn face_configs_to_json(face_configs: &[Option<FaceConfig>]) -> Result<String, Error> {
+    let mut items = vec![];
+    for (face, c) in face_configs.iter().enumerate() {
+        if let Some(c) = c {
+            let mut item = serde_json::Map::new();
+            item.insert("face".to_string(), json!(face));
+            item.extend(face_config_to_json(c)?);
+            items.push(serde_json::Value::Object(item));
+        }
+    }
+    Ok(serde_json::to_string_pretty(&items).unwrap() + "\n")
+}
+
+// Writes textures/[part]/[index].png, models/[part]/[index].obj and .gltf, and
+// face_configs.json into the directory. Returns the number of files written.
+pub fn extract(asset: &DecodedAsset, directory: &std::path::Path) -> Result<usize, Error> {
+    let mut count = 0;
+    for &part in TexturePart::ALL.iter() {
+        let part_directory = directory.join("textures").join(part.name());
+        std::fs::create_dir_all(&part_directory)?;
+        for (index, texture) in asset.textures(part).iter().enumerate() {
+            if let Some(texture) = texture {
+                texture_to_image(texture).save(part_directory.join(format!("{:03}.png", index)))?;
+                count += 1;
+            }
+        }
+    }
+
+    for &part in ModelPart::ALL.iter() {
+        let part_directory = directory.join("models").join(part.name());
+        std::fs::create_dir_all(&part_directory)?;
+        for (index, model) in asset.models(part).iter().enumerate() {
+            let model = match model {
+                Some(model) => model,
+                None => continue,
+            };
+            let face_config = match part {
+                ModelPart::Face => asset.face_configs.get(index).and_then(|c| c.as_ref()),
+                _ => None,
+            };
+            std::fs::write(
+                part_directory.join(format!("{:03}.obj", index)),
+                model_to_obj(model, face_config),
+            )?;
+            count += 1;
+            if let Some(gltf) = model_to_gltf(model, face_config)? {
+                std::fs::write(part_directory.join(format!("{:03}.gltf", index)), gltf)?;
+                count += 1;
+            }
+        }
+    }
+
+    std::fs::write(
+        directory.join("face_configs.json"),
+        face_configs_to_json(&asset.face_configs)?,
+    )?;
+    Ok(count + 1)
+}
+
+#[test]
+fn export_test() {
+    let mut vertex_list = vec![];
+    for v in &[
+        [0i16, 0, 0, 0, 0],
+        [256, 0, 0, 8192, 0],
+        [0, 512, 0, 0, 4096],
+    ] {
+        for x in v {
+            vertex_list.extend_from_slice(&x.to_le_bytes());
+        }
+    }
+    let model = RawModel {
+        normal_mode: AttributeMode::Common,
+        texcoord_mode: AttributeMode::Individual,
+        vertex_list,
+        default_normal: (0, 0, 256),
+        default_texcoord: (0, 0),
+        index_list: vec![0, 1, 2],
+        header: vec![],
+        trailer: vec![],
+    };
+    let face_config = FaceConfig {
+        hair_pos: (1.0, 2.0, 3.0),
+        nose_pos: (0.0, 0.0, 0.0),
+        beard_pos: (0.0, 0.0, 0.0),
+    };
+    let obj = model_to_obj(&model, Some(&face_config));
+    let lines: Vec<&str> = obj.lines().collect();
+    assert_eq!(lines[0], "# hair_pos 1 2 3");
+    assert_eq!(lines[4], "v 1 0 0");
+    assert_eq!(lines[5], "v 0 2 0");
+    assert_eq!(lines[7], "vt 1 1");
+    assert_eq!(lines[8], "vt 0 0.5");
+    assert_eq!(lines[9], "vn 0 0 1");
+    assert_eq!(lines[12], "f 1/1/1 2/2/2 3/3/3");
+
+    let gltf = model_to_gltf(&model, Some(&face_config)).unwrap().unwrap();
+    let gltf: serde_json::Value = serde_json::from_str(&gltf).unwrap();
+    assert_eq!(
+        gltf["meshes"][0]["primitives"][0]["attributes"],
+        json!({"POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2})
+    );
+    assert_eq!(gltf["accessors"][0]["min"], json!([0.0, 0.0, 0.0]));
+    assert_eq!(gltf["accessors"][0]["max"], json!([1.0, 2.0, 0.0]));
+    assert_eq!(
+        gltf["nodes"][0]["extras"]["hair_pos"],
+        json!([1.0, 2.0, 3.0])
+    );
+    // 36 + 36 + 24 + 6 bytes with padding
+    assert_eq!(gltf["buffers"][0]["byteLength"], 104);
+    let uri = gltf["buffers"][0]["uri"].as_str().unwrap();
+    let buffer = base64::decode(uri.trim_start_matches("data:application/octet-stream;base64,"));
+    assert_eq!(&buffer.unwrap()[12..16], &1.0f32.to_le_bytes());
+
+    let face_config = FaceConfig {
+        hair_pos: (f32::NAN, 0.0, 0.0),
+        ..face_config
+    };
+    assert!(matches!(
+        model_to_gltf(&model, Some(&face_config)),
+        Err(Error::NotFinite("hair_pos"))
+    ));
+    assert!(face_configs_to_json(&[None, Some(face_config)]).is_err());
+}
@@ -0,0 +1,372 @@
+// The reverse of asset_export: replaces textures and models of a decoded asset with PNG and
+// Wavefront OBJ files, so that the asset can be repacked with DecodedAsset::to_bytes.
+//
+// Imported items keep the format, wrap modes and extra data of the items they replace.
+
+use crate::asset::WrapMode;
+use crate::asset::*;
+use crate::asset_export::*;
+use crate::error::*;
+
+// Models index their vertices with single bytes
+const MAX_VERTEX_COUNT: usize = 256;
+
+pub fn image_to_texture(
+    image: &image::RgbaImage,
+    template: Option<&RawTexture>,
+) -> Result<RawTexture, Error> {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 || width % 2 != 0 || width > 1024 || height > 1024 {
+        return Err(Error::BadTextureSize { width, height });
+    }
+    let (format, wrap_u, wrap_v) = match template {
+        Some(t) => (t.format, t.wrap_u, t.wrap_v),
+        None => (TextureFormat::RGBA8, WrapMode::Edge, WrapMode::Edge),
+    };
+    Ok(RawTexture::encode(
+        width as u16,
+        height as u16,
+        format,
+        wrap_u,
+        wrap_v,
+        image.as_raw(),
+    ))
+}
+
+fn quantize(v: f32, scale: f32) -> i16 {
+    (v * scale)
+        .round()
+        .max(i16::MIN as f32)
+        .min(i16::MAX as f32) as i16
+}
+
+// Picks the attribute mode for values given per vertex. A model that used a common value keeps
+// doing so as long as all vertices agree.
+fn attribute_mode<V: Copy + PartialEq>(
+    values: &[Option<V>],
+    template_mode: AttributeMode,
+    template_default: V,
+) -> (AttributeMode, V) {
+    if values.is_empty() || values.iter().any(|v| v.is_none()) {
+        return match template_mode {
+            AttributeMode::Common => (AttributeMode::Common, template_default),
+            _ => (AttributeMode::None, template_default),
+        };
+    }
+    let first = values[0].unwrap();
+    if template_mode == AttributeMode::Common && values.iter().all(|v| *v == Some(first)) {
+        (AttributeMode::Common, first)
+    } else {
+        (AttributeMode::Individual, template_default)
+    }
+}
+
+// Reads an OBJ file as written by model_to_obj. Polygons are split into triangle fans, and face
+// configurations are read from comments. Returns the model and the face configuration if the
+// file has one.
+pub fn model_from_obj(
+    text: &str,
+    part: ModelPart,
+    template: Option<&RawModel>,
+) -> Result<(RawModel, Option<FaceConfig>), Error> {
+    let mut coords = Vec::<(f32, f32, f32)>::new();
+    let mut texcoords = Vec::<(f32, f32)>::new();
+    let mut normals = Vec::<(f32, f32, f32)>::new();
+    // Indices into coords, texcoords and normals for each vertex of the model
+    let mut vertex_keys = Vec::<(usize, Option<usize>, Option<usize>)>::new();
+    let mut index_list = Vec::<u8>::new();
+    let mut face_config_fields = [None; 3];
+
+    for (line_index, line) in text.lines().enumerate() {
+        let corrupt = || Error::ObjCorrupt(line_index + 1);
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            None => continue,
+            Some(keyword) => keyword,
+        };
+        let rest: Vec<&str> = tokens.collect();
+        let floats = |count: usize| -> Result<Vec<f32>, Error> {
+            if rest.len() < count {
+                return Err(corrupt());
+            }
+            rest[0..count]
+                .iter()
+                .map(|t| t.parse::<f32>().map_err(|_| corrupt()))
+                .collect()
+        };
+        match keyword {
+            "v" => {
+                let v = floats(3)?;
+                coords.push((v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let v = floats(2)?;
+                texcoords.push((v[0], v[1]));
+            }
+            "vn" => {
+                let v = floats(3)?;
+                normals.push((v[0], v[1], v[2]));
+            }
+            "f" => {
+                let resolve = |t: Option<&str>, len: usize| -> Result<Option<usize>, Error> {
+                    let t = match t {
+                        None | Some("") => return Ok(None),
+                        Some(t) => t,
+                    };
+                    let i = t.parse::<i64>().map_err(|_| corrupt())?;
+                    let i = if i < 0 { len as i64 + i } else { i - 1 };
+                    if i < 0 || i >= len as i64 {
+                        return Err(corrupt());
+                    }
+                    Ok(Some(i as usize))
+                };
+                let mut polygon = Vec::<u8>::with_capacity(rest.len());
+                for vertex in &rest {
+                    let mut fields = vertex.split('/');
+                    let key = (
+                        resolve(fields.next(), coords.len())?.ok_or_else(corrupt)?,
+                        resolve(fields.next(), texcoords.len())?,
+                        resolve(fields.next(), normals.len())?,
+                    );
+                    let index = match vertex_keys.iter().position(|k| *k == key) {
+                        Some(index) => index,
+                        None => {
+                            if vertex_keys.len() == MAX_VERTEX_COUNT {
+                                return Err(Error::ModelTooLarge);
+                            }
+                            vertex_keys.push(key);
+                            vertex_keys.len() - 1
+                        }
+                    };
+                    polygon.push(index as u8);
+                }
+                if polygon.len() < 3 {
+                    return Err(corrupt());
+                }
+                for i in 1..polygon.len() - 1 {
+                    index_list.extend_from_slice(&[polygon[0], polygon[i], polygon[i + 1]]);
+                }
+            }
+            "#" => {
+                let field = match rest.first() {
+                    Some(&"hair_pos") => 0,
+                    Some(&"nose_pos") => 1,
+                    Some(&"beard_pos") => 2,
+                    _ => continue,
+                };
+                let v = rest[1..]
+                    .iter()
+                    .map(|t| t.parse::<f32>().map_err(|_| corrupt()))
+                    .collect::<Result<Vec<f32>, Error>>()?;
+                if v.len() != 3 {
+                    return Err(corrupt());
+                }
+                face_config_fields[field] = Some((v[0], v[1], v[2]));
+            }
+            _ => (),
+        }
+    }
+
+    let normal_values: Vec<Option<(i16, i16, i16)>> = vertex_keys
+        .iter()
+        .map(|k| {
+            k.2.map(|i| {
+                let n = normals[i];
+                (
+                    quantize(n.0, 256.0),
+                    quantize(n.1, 256.0),
+                    quantize(n.2, 256.0),
+                )
+            })
+        })
+        .collect();
+    let texcoord_values: Vec<Option<(i16, i16)>> = vertex_keys
+        .iter()
+        .map(|k| {
+            k.1.map(|i| {
+                let t = texcoords[i];
+                (quantize(t.0, 8192.0), quantize(1.0 - t.1, 8192.0))
+            })
+        })
+        .collect();
+    let (normal_mode, default_normal) = attribute_mode(
+        &normal_values,
+        template.map_or(AttributeMode::None, |t| t.normal_mode),
+        template.map_or((0, 0, 0), |t| t.default_normal),
+    );
+    let (texcoord_mode, default_texcoord) = attribute_mode(
+        &texcoord_values,
+        template.map_or(AttributeMode::None, |t| t.texcoord_mode),
+        template.map_or((0, 0), |t| t.default_texcoord),
+    );
+
+    let mut vertex_list = vec![];
+    for (i, key) in vertex_keys.iter().enumerate() {
+        let c = coords[key.0];
+        let mut values = vec![
+            quantize(c.0, 256.0),
+            quantize(c.1, 256.0),
+            quantize(c.2, 256.0),
+        ];
+        if normal_mode == AttributeMode::Individual {
+            let n = normal_values[i].unwrap();
+            values.extend_from_slice(&[n.0, n.1, n.2]);
+        }
+        if texcoord_mode == AttributeMode::Individual {
+            let t = texcoord_values[i].unwrap();
+            values.extend_from_slice(&[t.0, t.1]);
+        }
+        for v in values {
+            vertex_list.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+
+    let header = match (part, template) {
+        (ModelPart::Hair, Some(t)) => t.header.clone(),
+        (ModelPart::Hair, None) => vec![0; 0x48],
+        _ => vec![],
+    };
+    let mut trailer = match (part, template) {
+        (ModelPart::FaceCanvas, Some(t)) => t.trailer.clone(),
+        _ => vec![],
+    };
+    if part == ModelPart::FaceCanvas {
+        trailer.resize(index_list.len() / 3 * 2, 0);
+    }
+
+    let face_config = match face_config_fields {
+        [Some(hair_pos), Some(nose_pos), Some(beard_pos)] => Some(FaceConfig {
+            hair_pos,
+            nose_pos,
+            beard_pos,
+        }),
+        _ => None,
+    };
+
+    let model = RawModel {
+        normal_mode,
+        texcoord_mode,
+        vertex_list,
+        default_normal,
+        default_texcoord,
+        index_list,
+        header,
+        trailer,
+    };
+    Ok((model, face_config))
+}
+
+// Reads the files written by asset_export::extract back from the directory. Only files that
+// differ from what extract would write replace their items; glTF files and face_configs.json
+// are not read. Returns the number of replaced items.
+pub fn import(asset: &mut DecodedAsset, directory: &std::path::Path) -> Result<usize, Error> {
+    let mut count = 0;
+    for &part in TexturePart::ALL.iter() {
+        let part_directory = directory.join("textures").join(part.name());
+        for index in 0..asset.textures(part).len() {
+            let path = part_directory.join(format!("{:03}.png", index));
+            if !path.exists() {
+                continue;
+            }
+            let image = image::open(&path)?.to_rgba8();
+            let original = asset.texture(part, index);
+            if original.map_or(false, |t| texture_to_image(t) == image) {
+                continue;
+            }
+            let texture = image_to_texture(&image, original)?;
+            asset.textures_mut(part)[index] = Some(texture);
+            count += 1;
+        }
+    }
+
+    for &part in ModelPart::ALL.iter() {
+        let part_directory = directory.join("models").join(part.name());
+        for index in 0..asset.models(part).len() {
+            let path = part_directory.join(format!("{:03}.obj", index));
+            if !path.exists() {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path)?;
+            let original = asset.model(part, index);
+            let original_face_config = match part {
+                ModelPart::Face => asset.face_configs.get(index).and_then(|c| c.as_ref()),
+                _ => None,
+            };
+            if original.map_or(false, |m| model_to_obj(m, original_face_config) == text) {
+                continue;
+            }
+            let (model, face_config) = model_from_obj(&text, part, original)?;
+            if part == ModelPart::Face {
+                if let Some(face_config) = face_config.or_else(|| original_face_config.cloned()) {
+                    if asset.face_configs.len() <= index {
+                        asset.face_configs.resize(index + 1, None);
+                    }
+                    asset.face_configs[index] = Some(face_config);
+                }
+            }
+            asset.models_mut(part)[index] = Some(model);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[test]
+fn import_test() {
+    let mut vertex_list = vec![];
+    for v in &[
+        [0i16, 0, 0, 0, 0],
+        [256, 0, 0, 8192, 0],
+        [0, 512, 0, 0, 4096],
+    ] {
+        for x in v {
+            vertex_list.extend_from_slice(&x.to_le_bytes());
+        }
+    }
+    let model = RawModel {
+        normal_mode: AttributeMode::Common,
+        texcoord_mode: AttributeMode::Individual,
+        vertex_list,
+        default_normal: (0, 0, 256),
+        default_texcoord: (0, 0),
+        index_list: vec![0, 1, 2],
+        header: vec![],
+        trailer: vec![],
+    };
+    let face_config = FaceConfig {
+        hair_pos: (1.0, 2.0, 3.0),
+        nose_pos: (0.0, 0.0, 0.0),
+        beard_pos: (0.0, 0.5, 0.0),
+    };
+    let obj = model_to_obj(&model, Some(&face_config));
+    let (imported, imported_config) = model_from_obj(&obj, ModelPart::Face, Some(&model)).unwrap();
+    assert_eq!(imported.to_bytes(), model.to_bytes());
+    assert_eq!(imported_config.unwrap().beard_pos, (0.0, 0.5, 0.0));
+
+    // A quad without normals becomes two triangles
+    let quad = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n";
+    let (imported, _) = model_from_obj(quad, ModelPart::FaceCanvas, None).unwrap();
+    assert_eq!(imported.index_list, vec![0, 1, 2, 0, 2, 3]);
+    assert_eq!(imported.normal_mode, AttributeMode::None);
+    assert_eq!(imported.trailer.len(), 4);
+    assert!(matches!(
+        model_from_obj("v 0 0 0\nf 1 2 3\n", ModelPart::Nose, None),
+        Err(Error::ObjCorrupt(2))
+    ));
+
+    let image =
+        image::RgbaImage::from_fn(8, 4, |x, y| image::Rgba([x as u8 * 30, y as u8, 0, 255]));
+    let template = RawTexture::encode(
+        8,
+        8,
+        TextureFormat::RGB8,
+        WrapMode::Repeat,
+        WrapMode::Mirror,
+        &[0; 8 * 8 * 4],
+    );
+    let texture = image_to_texture(&image, Some(&template)).unwrap();
+    assert_eq!((texture.width, texture.height), (8, 4));
+    assert_eq!(texture.format, TextureFormat::RGB8);
+    assert_eq!(texture.wrap_u, WrapMode::Repeat);
+    assert_eq!(texture_to_image(&texture), image);
+}
@@ -0,0 +1,1034 @@
+use citrii::asset;
+use citrii::asset_export;
+use citrii::asset_import;
+use citrii::database;
+use citrii::diff;
+use citrii::head_renderer;
+use citrii::name;
+use citrii::ncch;
+use citrii::qr;
+use citrii::random;
+use citrii::romfs;
+use citrii::save;
+use citrii::schema;
+use citrii::software_renderer;
+use citrii::validation;
+
+fn print_usage() {
+    println!("Usage: citrii-cli [Path to CFL_DB.dat] [Command]");
+    println!("       citrii-cli extract-assets [Asset file] [Directory]");
+    println!("       citrii-cli pack-assets [Asset file] [Directory] [Output file]");
+    println!();
+    println!("extract-assets writes all textures as PNG, all models as OBJ and glTF, and the");
+    println!("face configurations as JSON. pack-assets reads the PNG and OBJ files that were");
+    println!("changed in such a directory and writes a copy of the asset file with them.");
+    println!();
+    println!("Commands:");
+    println!("    validate                      Check the database for errors");
+    println!("    repair                        Fix errors in the database and its CRCs");
+    println!("    list <invited|encountered>");
+    println!("                                  List all owned profiles, or the invited or");
+    println!("                                  encountered ones in order");
+    println!("    show [Slot]                   Show all fields of a profile");
+    println!("    export [Source] [File]        Export a profile to a profile file");
+    println!("    import [File] <Slot> <--asset [Asset file]>");
+    println!("                                  Import a profile file into a free slot");
+    println!("    copy [Source] <Slot>          Copy a profile into a free slot");
+    println!("    qr-export [Source] [Key file] [File]");
+    println!("                                  Export a profile to a QR code PNG");
+    println!("    qr-import [File] [Key file] <Slot>");
+    println!("                                  Import a profile from a QR code image made by");
+    println!("                                  qr-export or an upright screenshot of one");
+    println!("    render [Source] [Asset file] [File] [Options]");
+    println!("                                  Render the head of a profile to a PNG");
+    println!("    render-all [Asset file] [Directory] [Options]");
+    println!("                                  Render all owned profiles to [Slot].png");
+    println!("    delete [Slot]                 Delete a profile");
+    println!("    move [Slot] [Slot]            Move a profile to another slot");
+    println!("    move invited:[Index] [Position]");
+    println!("                                  Move an invited profile in the invited order");
+    println!("    set-field [Slot] [Field] [Value]");
+    println!("                                  Change a field of a profile");
+    println!("    randomize [Slot] [Options]    Give a profile a random appearance, or create a");
+    println!("                                  random profile in an empty slot");
+    println!("    diff [Database]               Show the changes from this database to another");
+    println!("    merge [Base] [Theirs] <--theirs> <--fail-on-conflict>");
+    println!("                                  Merge the changes from Base to Theirs into this");
+    println!("                                  database");
+    println!();
+    println!("Commands that change the database overwrite it. With --backup, the previous");
+    println!("database is kept next to it as [File].[Time].bak, and only the newest backups.");
+    println!();
+    println!("Slots are numbered 0-99 as in the editor. The source of export and copy can");
+    println!("also be invited:[Index] or cfhe:[Index] for the invited or encountered list.");
+    println!("show, delete and set-field also accept invited:[Index] to edit the invited list.");
+    println!("show and delete also accept cfhe:[Index] to browse the encountered list.");
+    println!("As on the 3DS, copy only copies an encountered profile that allows copying.");
+    println!();
+    println!("Profile files are .cfsd (3DS), .ffsd (Wii U), .mii, .rcd or .rfl (Wii),");
+    println!(".charinfo (Switch, import only), .json or .toml. Parts that the Wii doesn't have");
+    println!("are approximated on export to the Wii, and parts and colors that the 3DS doesn't");
+    println!("have on import from the Switch. Each approximation is listed. With --asset, styles");
+    println!("from the Switch are also checked against the parts in the asset file. The Switch");
+    println!(".storedata format and DS Miis are not supported.");
+    println!();
+    println!("The .json and .toml files contain every field of the profile, including the");
+    println!("padding, and are rejected on import if a value is out of range.");
+    println!();
+    println!("diff and merge match profiles by their ID. Conflicting changes are resolved in");
+    println!("favor of this database, or of Theirs with --theirs. With --fail-on-conflict, merge");
+    println!("still writes the result but exits with an error if there was a conflict. merge");
+    println!("works as a git merge driver with: citrii-cli %A merge %O %B --fail-on-conflict");
+    println!();
+    println!("QR codes are encrypted with a key that is not included. The key file contains");
+    println!("the 16-byte key either in raw bytes or in hexadecimal. qr-import only reads clean,");
+    println!("upright images; photos of a QR code taken with a camera are not supported.");
+    println!();
+    println!("The asset file is the RomFS of the Mii Maker, such as 00000000.app.romfs in Citra,");
+    println!("the decrypted content file 00000000.app, or CFL_Res.dat itself. pack-assets writes");
+    println!("a RomFS image when given a content file.");
+    println!("Options of render:");
+    println!("    --size [Pixels]               Width and height of the image (default 256)");
+    println!("    --yaw [Degrees]               Turn the head to its left");
+    println!("    --pitch [Degrees]             Tilt the head down");
+    println!("    --background [RRGGBB]         Background color (default transparent)");
+    println!("    --software                    Render on the CPU instead of with OpenGL");
+    println!("The CPU renderer is also used when OpenGL is not available.");
+    println!();
+    println!("Options of randomize:");
+    println!("    --seed [Number]               Seed of the random values (default from the time)");
+    println!("    --sex [male|female]           Keep the sex fixed");
+    println!("    --skin [Color]                Keep the skin color fixed (0-5)");
+    println!("    --no-glasses                  Never add glasses");
+    println!("The same seed and options always give the same appearance.");
+    println!();
+    println!("Names and authors are at most 10 UTF-16 units. set-field rejects characters that");
+    println!("the font of the character set (char_set) of the profile can't display, import");
+    println!("warns about them, and list and show name them after the name.");
+    println!();
+    println!("Fields accepted by set-field, with their ranges:");
+    println!("    name, author");
+    for param in schema::PARAMS {
+        let range = format!("{}-{}", param.min, param.max);
+        println!("    {:<20}{:<10}{}", param.field, range, param.name);
+    }
+}
+
+fn parse_slot(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(slot) if slot < 100 => Ok(slot),
+        _ => Err(format!("Invalid slot: {}", s)),
+    }
+}
+
+// The slot of an owned profile, or its raw page and slot if they are out of range
+fn slot_label(profile: &database::Profile) -> String {
+    match profile.try_get_slot() {
+        Some(slot) => format!("{:02}", slot),
+        None => format!("page{}-slot{}", profile.header.page, profile.header.slot),
+    }
+}
+
+fn slot_to_index(database: &database::Database, s: &str) -> Result<usize, String> {
+    let slot = parse_slot(s)?;
+    database
+        .owned_slot_to_index(slot)
+        .ok_or_else(|| format!("Slot {} is empty", slot))
+}
+
+// Source is either an owned slot, "invited:[Index]" or "cfhe:[Index]"
+fn get_source_profile(
+    database: &database::Database,
+    source: &str,
+) -> Result<database::ProfileFull, String> {
+    let parse_index = |s: &str, len: usize| match s.parse::<usize>() {
+        Ok(index) if index < len => Ok(index),
+        _ => Err(format!("Invalid index: {}", s)),
+    };
+    let profile = if let Some(index) = source.strip_prefix("invited:") {
+        let index = parse_index(index, database.invited.len())?;
+        database::ProfileFull::from_profile(database.invited[index].clone())
+    } else if let Some(index) = source.strip_prefix("cfhe:") {
+        let index = parse_index(index, database.cfhe_profiles.len())?;
+        database::ProfileFull::from_profile(database.cfhe_profiles[index].main.clone())
+    } else {
+        database.owned[slot_to_index(database, source)?].clone()
+    };
+    if profile.main.is_null() {
+        return Err(format!("{} is empty", source));
+    }
+    Ok(profile)
+}
+
+// Index of the invited profile named by "invited:[Index]", or None for other sources
+fn invited_index(database: &database::Database, source: &str) -> Result<Option<usize>, String> {
+    let index = match source.strip_prefix("invited:") {
+        None => return Ok(None),
+        Some(index) => index,
+    };
+    match index.parse::<usize>() {
+        Ok(i) if i < database.invited.len() && !database.invited[i].is_null() => Ok(Some(i)),
+        Ok(i) if i < database.invited.len() => Err(format!("{} is empty", source)),
+        _ => Err(format!("Invalid index: {}", index)),
+    }
+}
+
+// Node of the encountered profile named by "cfhe:[Index]", or None for other sources
+fn cfhe_index(database: &database::Database, source: &str) -> Result<Option<usize>, String> {
+    let index = match source.strip_prefix("cfhe:") {
+        None => return Ok(None),
+        Some(index) => index,
+    };
+    match index.parse::<usize>() {
+        Ok(i) if database.cfhe_order().contains(&i) => Ok(Some(i)),
+        Ok(i) if i < database.cfhe_profiles.len() => Err(format!("{} is empty", source)),
+        _ => Err(format!("Invalid index: {}", index)),
+    }
+}
+
+fn read_database(file: &str) -> Result<database::Database, String> {
+    let data = std::fs::read(file).map_err(|e| format!("Unable to read {}: {}", file, e))?;
+    database::Database::from_bytes(&data[..]).map_err(|e| format!("{}: {}", file, e))
+}
+
+fn get_arg(args: &[String], i: usize) -> Result<&str, String> {
+    args.get(i)
+        .map(|s| s.as_str())
+        .ok_or_else(|| "Missing argument".to_string())
+}
+
+fn get_arg_str<'a>(args: &[&'a str], i: usize) -> Result<&'a str, String> {
+    args.get(i)
+        .copied()
+        .ok_or_else(|| "Missing argument".to_string())
+}
+
+fn read_qr_key(file: &str) -> Result<qr::QrKey, String> {
+    let data = std::fs::read(file).map_err(|e| format!("Unable to read {}: {}", file, e))?;
+    qr::parse_key(&data[..]).map_err(|e| format!("{}: {}", file, e))
+}
+
+const STOREDATA_UNSUPPORTED: &str =
+    "Switch .storedata is not supported. Export the Mii as .charinfo instead";
+
+// Format of a profile file by its extension, defaulting to .cfsd
+fn file_format(file: &str) -> Result<&'static str, String> {
+    let format = std::path::Path::new(file)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    match format.as_deref() {
+        Some("mii") | Some("rcd") | Some("rfl") => Ok("rfl"),
+        Some("cfsd") => Ok("cfsd"),
+        Some("ffsd") => Ok("ffsd"),
+        Some("json") => Ok("json"),
+        Some("toml") => Ok("toml"),
+        Some("charinfo") => Ok("charinfo"),
+        Some("storedata") => Ok("storedata"),
+        _ => Err(format!("{}: unknown profile format", file)),
+    }
+}
+
+fn import_profile(
+    database: &mut database::Database,
+    profile: database::ProfileFull,
+    slot: Option<&String>,
+) -> Result<(), String> {
+    let slot = match slot {
+        Some(s) => Some(parse_slot(s)?),
+        None => None,
+    };
+    for (field, issue) in profile.name_issues() {
+        println!("Warning: {}: {}", field, issue);
+    }
+    let slot = database
+        .import_owned(profile, slot)
+        .map_err(|e| e.to_string())?;
+    println!("Imported to slot {}", slot);
+    Ok(())
+}
+
+struct RenderOptions {
+    params: head_renderer::OffscreenParams,
+    software: bool,
+}
+
+// Splits the arguments into positional ones and render options
+fn parse_render_options(args: &[String]) -> Result<(Vec<&str>, RenderOptions), String> {
+    let mut params = head_renderer::OffscreenParams {
+        width: 256,
+        height: 256,
+        yaw: 0.0,
+        pitch: 0.0,
+        background: None,
+    };
+    let mut software = false;
+    let mut positional = vec![];
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        if !arg.starts_with("--") {
+            positional.push(arg);
+            i += 1;
+            continue;
+        }
+        if arg == "--software" {
+            software = true;
+            i += 1;
+            continue;
+        }
+        let value = get_arg(args, i + 1)?;
+        let invalid = || format!("Invalid value for {}: {}", arg, value);
+        match arg {
+            "--size" => {
+                let size = value.parse::<u32>().map_err(|_| invalid())?;
+                if size == 0 || size > 4096 {
+                    return Err(invalid());
+                }
+                params.width = size;
+                params.height = size;
+            }
+            "--yaw" => params.yaw = value.parse::<f32>().map_err(|_| invalid())?,
+            "--pitch" => params.pitch = value.parse::<f32>().map_err(|_| invalid())?,
+            "--background" => {
+                let rgb = u32::from_str_radix(value.trim_start_matches('#'), 16)
+                    .map_err(|_| invalid())?;
+                if value.trim_start_matches('#').len() != 6 {
+                    return Err(invalid());
+                }
+                params.background = Some((
+                    (rgb >> 16) as f32 / 255.0,
+                    ((rgb >> 8) & 0xFF) as f32 / 255.0,
+                    (rgb & 0xFF) as f32 / 255.0,
+                ));
+            }
+            _ => return Err(format!("Unknown option: {}", arg)),
+        }
+        i += 2;
+    }
+    Ok((positional, RenderOptions { params, software }))
+}
+
+#[cfg(feature = "opengl")]
+struct GlContext {
+    _context: glutin::Context<glutin::PossiblyCurrent>,
+    _event_loop: glutin::event_loop::EventLoop<()>,
+}
+
+#[cfg(feature = "opengl")]
+fn create_gl_context() -> Result<GlContext, String> {
+    #[cfg(target_family = "unix")]
+    use glutin::platform::unix::EventLoopExtUnix;
+    #[cfg(target_family = "windows")]
+    use glutin::platform::windows::EventLoopExtWindows;
+
+    // winit aborts instead of returning an error when there is no display
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+        return Err("Unable to connect to a display".to_string());
+    }
+    let event_loop = glutin::event_loop::EventLoop::new_any_thread();
+    let context = glutin::ContextBuilder::new()
+        .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
+        .with_gl_profile(glutin::GlProfile::Core)
+        .build_headless(&event_loop, glutin::dpi::PhysicalSize::new(1, 1))
+        .map_err(|e| format!("Unable to create an OpenGL context: {}", e))?;
+    let context = unsafe { context.make_current() }
+        .map_err(|(_, e)| format!("Unable to create an OpenGL context: {}", e))?;
+    gl::load_with(|ptr| context.get_proc_address(ptr) as *const _);
+    Ok(GlContext {
+        _context: context,
+        _event_loop: event_loop,
+    })
+}
+
+fn asset_path() -> Vec<String> {
+    vec!["CFL_Res.dat".to_string()]
+}
+
+// Accepts a decrypted NCCH .app, a RomFS containing CFL_Res.dat, or CFL_Res.dat itself. Also
+// returns the RomFS image in the first two cases.
+fn read_asset_file(file: &str) -> Result<(asset::DecodedAsset, Option<Vec<u8>>), String> {
+    let data = std::fs::read(file).map_err(|e| format!("Unable to read {}: {}", file, e))?;
+    let romfs_data = ncch::find_romfs(&data).map_err(|e| format!("{}: {}", file, e))?;
+    let asset_data = match romfs::get_romfs_file(romfs_data, &asset_path()) {
+        Ok(asset_data) => asset_data,
+        Err(romfs_error) => {
+            return match asset::DecodedAsset::from_bytes(&data) {
+                Ok(asset) => Ok((asset, None)),
+                Err(_) => Err(format!("{}: {}", file, romfs_error)),
+            }
+        }
+    };
+    let asset =
+        asset::DecodedAsset::from_bytes(asset_data).map_err(|e| format!("{}: {}", file, e))?;
+    Ok((asset, Some(romfs_data.to_vec())))
+}
+
+fn read_asset(file: &str) -> Result<asset::DecodedAsset, String> {
+    read_asset_file(file).map(|(asset, _)| asset)
+}
+
+fn extract_assets(args: &[String]) -> Result<(), String> {
+    let asset = read_asset(get_arg(args, 2)?)?;
+    let directory = std::path::Path::new(get_arg(args, 3)?);
+    let count = asset_export::extract(&asset, directory).map_err(|e| e.to_string())?;
+    println!("Wrote {} files to {}", count, directory.display());
+    Ok(())
+}
+
+fn pack_assets(args: &[String]) -> Result<(), String> {
+    let file = get_arg(args, 2)?;
+    let (mut asset, romfs) = read_asset_file(file)?;
+    let directory = std::path::Path::new(get_arg(args, 3)?);
+    let output = get_arg(args, 4)?;
+    let count = asset_import::import(&mut asset, directory).map_err(|e| e.to_string())?;
+    let asset_data = asset.to_bytes().map_err(|e| e.to_string())?;
+    let data = match romfs {
+        None => asset_data,
+        Some(romfs) => romfs::replace_romfs_file(&romfs, &asset_path(), &asset_data)
+            .map_err(|e| format!("{}: {}", file, e))?,
+    };
+    std::fs::write(output, data).map_err(|e| format!("Unable to write {}: {}", output, e))?;
+    println!("Replaced {} items", count);
+    Ok(())
+}
+
+enum Renderer {
+    #[cfg(feature = "opengl")]
+    Gl {
+        head_renderer: head_renderer::HeadRenderer,
+        // Keeps the context alive while rendering
+        _gl_context: Box<GlContext>,
+    },
+    Software(software_renderer::SoftwareHeadRenderer),
+}
+
+impl Renderer {
+    // Without OpenGL, the CPU renderer is always used
+    #[cfg_attr(not(feature = "opengl"), allow(unused_variables))]
+    fn load(file: &str, software: bool) -> Result<Renderer, String> {
+        let decoded = read_asset(file)?;
+        #[cfg(feature = "opengl")]
+        if !software {
+            match create_gl_context() {
+                Ok(gl_context) => {
+                    return Ok(Renderer::Gl {
+                        head_renderer: head_renderer::HeadRenderer::with_asset(decoded.upload()),
+                        _gl_context: Box::new(gl_context),
+                    })
+                }
+                Err(e) => println!("{}. Falling back to the CPU renderer", e),
+            }
+        }
+        Ok(Renderer::Software(
+            software_renderer::SoftwareHeadRenderer::with_asset(
+                software_renderer::SoftwareAsset::from_decoded(&decoded),
+            ),
+        ))
+    }
+
+    fn render_to_file(
+        &self,
+        profile: &database::ProfileFull,
+        params: &head_renderer::OffscreenParams,
+        file: &std::path::Path,
+    ) -> Result<(), String> {
+        let info = profile.main.to_render_info();
+        let image = match self {
+            #[cfg(feature = "opengl")]
+            Renderer::Gl { head_renderer, .. } => head_renderer
+                .render_head_offscreen(&info, params)
+                .ok_or_else(|| "Unable to create an offscreen framebuffer".to_string())?,
+            Renderer::Software(head_renderer) => head_renderer.render_head_offscreen(&info, params),
+        };
+        image
+            .save(file)
+            .map_err(|e| format!("Unable to write {}: {}", file.display(), e))
+    }
+}
+
+fn render(database: &database::Database, args: &[String]) -> Result<(), String> {
+    let (positional, options) = parse_render_options(&args[1..])?;
+    match args[0].as_str() {
+        "render" => {
+            let profile = get_source_profile(database, get_arg_str(&positional, 0)?)?;
+            let renderer = Renderer::load(get_arg_str(&positional, 1)?, options.software)?;
+            let file = std::path::Path::new(get_arg_str(&positional, 2)?);
+            renderer.render_to_file(&profile, &options.params, file)?;
+        }
+        _ => {
+            let directory = std::path::Path::new(get_arg_str(&positional, 1)?);
+            std::fs::create_dir_all(directory)
+                .map_err(|e| format!("Unable to create {}: {}", directory.display(), e))?;
+            let renderer = Renderer::load(get_arg_str(&positional, 0)?, options.software)?;
+            for profile in database.owned.iter().filter(|p| !p.main.is_null()) {
+                let file = directory.join(format!("{}.png", slot_label(&profile.main)));
+                renderer.render_to_file(profile, &options.params, &file)?;
+                println!("Rendered {}", file.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn list(database: &database::Database) {
+    // Profiles with an invalid slot are listed last
+    let mut slot_map: Vec<(usize, usize)> = database
+        .owned
+        .iter()
+        .enumerate()
+        .filter(|(_, profile)| !profile.main.is_null())
+        .map(|(i, profile)| (profile.main.try_get_slot().unwrap_or(usize::MAX), i))
+        .collect();
+    slot_map.sort_unstable();
+    for (_, i) in slot_map {
+        let profile = &database.owned[i];
+        let author = name::MiiName(profile.author).describe(name::CharSet::of(&profile.main));
+        println!(
+            "{:2}  {:10}  {}",
+            slot_label(&profile.main),
+            display_name(&profile.main),
+            author
+        );
+    }
+}
+
+fn display_name(profile: &database::Profile) -> String {
+    name::MiiName(profile.name).describe(name::CharSet::of(profile))
+}
+
+fn list_invited(database: &database::Database) {
+    for (position, index) in database.invited_list().into_iter().enumerate() {
+        println!(
+            "{:2}  invited:{:<2}  {}",
+            position,
+            index,
+            display_name(&database.invited[index])
+        );
+    }
+}
+
+fn list_encountered(database: &database::Database) {
+    for (position, node) in database.cfhe_order().into_iter().enumerate() {
+        println!(
+            "{:4}  cfhe:{:<4}  {}  {}",
+            position,
+            node,
+            database.cfhe_time(node).format("%Y-%m-%d %H:%M:%S"),
+            display_name(&database.cfhe_profiles[node].main)
+        );
+    }
+}
+
+// Only owned profiles have an author
+fn show(profile: &database::Profile, author: Option<&[u16; 10]>) {
+    println!("name: {}", display_name(profile));
+    if let Some(author) = author {
+        let author = name::MiiName(*author).describe(name::CharSet::of(profile));
+        println!("author: {}", author);
+    }
+    println!("creation_date: {}", profile.id.low.creation_date);
+    println!(
+        "mac: {}",
+        profile
+            .id
+            .mac
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(":")
+    );
+    for field in database::PROFILE_FIELDS {
+        println!("{}: {}", field, profile.get_field(field).unwrap());
+    }
+}
+
+// Names are rejected rather than changed if they don't fit or can't be displayed
+fn parse_name(profile: &database::ProfileFull, value: &str) -> Result<[u16; 10], String> {
+    let (name, issues) = name::MiiName::parse(value, name::CharSet::of(&profile.main));
+    if issues.is_empty() {
+        Ok(name.0)
+    } else {
+        let issues: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+        Err(format!("Invalid name: {}", issues.join(", ")))
+    }
+}
+
+fn set_field(profile: &mut database::ProfileFull, field: &str, value: &str) -> Result<(), String> {
+    match field {
+        "name" => profile.main.name = parse_name(profile, value)?,
+        "author" => profile.author = parse_name(profile, value)?,
+        _ => {
+            let param = schema::param(field).ok_or(format!("Unknown field: {}", field))?;
+            let value = value
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid value: {}", value))?;
+            if !param.contains(value) {
+                return Err(format!(
+                    "Value {} is out of range for {} ({}): {}-{}",
+                    value, field, param.name, param.min, param.max
+                ));
+            }
+            param.set(&mut profile.main, value);
+        }
+    }
+    Ok(())
+}
+
+// Returns whether the database has been modified
+fn run_command(database: &mut database::Database, args: &[String]) -> Result<bool, String> {
+    match get_arg(args, 0)? {
+        "list" => {
+            match args.get(1).map(|s| s.as_str()) {
+                None => list(database),
+                Some("invited") => list_invited(database),
+                Some("encountered") => list_encountered(database),
+                Some(list) => return Err(format!("Unknown list: {}", list)),
+            }
+            Ok(false)
+        }
+        "show" => {
+            let source = get_arg(args, 1)?;
+            if let Some(index) = invited_index(database, source)? {
+                let list = database.invited_list();
+                let position = list.iter().position(|&i| i == index).unwrap();
+                println!("position: {}", position);
+                show(&database.invited[index], None);
+            } else if let Some(node) = cfhe_index(database, source)? {
+                let order = database.cfhe_order();
+                let position = order.iter().position(|&n| n == node).unwrap();
+                println!("position: {}", position);
+                println!(
+                    "met: {}",
+                    database.cfhe_time(node).format("%Y-%m-%d %H:%M:%S")
+                );
+                show(&database.cfhe_profiles[node].main, None);
+            } else {
+                let profile = &database.owned[slot_to_index(database, source)?];
+                println!("slot: {}", slot_label(&profile.main));
+                show(&profile.main, Some(&profile.author));
+            }
+            Ok(false)
+        }
+        "export" => {
+            let profile = get_source_profile(database, get_arg(args, 1)?)?;
+            let file = get_arg(args, 2)?;
+            let data = match file_format(file)? {
+                "json" => profile.to_json().into_bytes(),
+                "toml" => profile.to_toml().into_bytes(),
+                "ffsd" => profile.to_ffsd(),
+                "charinfo" => return Err("Export to .charinfo is not supported".to_string()),
+                "storedata" => return Err(STOREDATA_UNSUPPORTED.to_string()),
+                "rfl" => {
+                    let (data, approximations) = profile.to_rfl();
+                    for approximation in approximations {
+                        println!("{}", approximation);
+                    }
+                    data
+                }
+                _ => profile.to_cfsd(),
+            };
+            std::fs::write(file, data).map_err(|e| format!("Unable to write {}: {}", file, e))?;
+            Ok(false)
+        }
+        "import" => {
+            let file = get_arg(args, 1)?;
+            let format = file_format(file)?;
+            let mut slot = None;
+            let mut asset_file = None;
+            let mut options = args[2..].iter();
+            while let Some(arg) = options.next() {
+                if arg == "--asset" {
+                    asset_file = Some(options.next().ok_or("Missing argument")?);
+                } else {
+                    slot = Some(arg);
+                }
+            }
+            // Only the Switch has parts that need to be checked against the asset
+            let asset = match asset_file {
+                Some(_) if format != "charinfo" => {
+                    return Err("--asset is only used with .charinfo files".to_string())
+                }
+                Some(asset_file) => Some(read_asset(asset_file)?),
+                None => None,
+            };
+            let data =
+                std::fs::read(file).map_err(|e| format!("Unable to read {}: {}", file, e))?;
+            let profile = match format {
+                "cfsd" => database::ProfileFull::from_cfsd(&data[..]),
+                "ffsd" => database::ProfileFull::from_ffsd(&data[..]),
+                "rfl" => database::ProfileFull::from_rfl(&data[..]),
+                "storedata" => return Err(STOREDATA_UNSUPPORTED.to_string()),
+                "charinfo" => database::ProfileFull::from_charinfo(&data[..], asset.as_ref()).map(
+                    |(profile, approximations)| {
+                        for approximation in approximations {
+                            println!("{}", approximation);
+                        }
+                        profile
+                    },
+                ),
+                format => {
+                    let text = String::from_utf8(data)
+                        .map_err(|_| format!("{}: file is not valid UTF-8", file))?;
+                    if format == "json" {
+                        database::ProfileFull::from_json(&text)
+                    } else {
+                        database::ProfileFull::from_toml(&text)
+                    }
+                }
+            }
+            .map_err(|e| format!("{}: {}", file, e))?;
+            import_profile(database, profile, slot)?;
+            Ok(true)
+        }
+        "copy" => {
+            let source = get_arg(args, 1)?;
+            let profile = get_source_profile(database, source)?;
+            if source.starts_with("cfhe:") && profile.main.header.allow_copying == 0 {
+                return Err(format!(
+                    "{}: {}",
+                    source,
+                    citrii::error::Error::CopyingNotAllowed
+                ));
+            }
+            import_profile(database, profile, args.get(2))?;
+            Ok(true)
+        }
+        "qr-export" => {
+            let profile = get_source_profile(database, get_arg(args, 1)?)?;
+            let key = read_qr_key(get_arg(args, 2)?)?;
+            let file = get_arg(args, 3)?;
+            qr::render_profile(&profile, &key, 4)
+                .save(file)
+                .map_err(|e| format!("Unable to write {}: {}", file, e))?;
+            Ok(false)
+        }
+        "qr-import" => {
+            let file = get_arg(args, 1)?;
+            let key = read_qr_key(get_arg(args, 2)?)?;
+            let image = image::open(file)
+                .map_err(|e| format!("Unable to read {}: {}", file, e))?
+                .into_luma8();
+            let profile =
+                qr::decode_profile(&image, &key).map_err(|e| format!("{}: {}", file, e))?;
+            import_profile(database, profile, args.get(3))?;
+            Ok(true)
+        }
+        "render" | "render-all" => {
+            render(database, args)?;
+            Ok(false)
+        }
+        "delete" => {
+            let source = get_arg(args, 1)?;
+            if let Some(index) = invited_index(database, source)? {
+                database.remove_invited(index).map_err(|e| e.to_string())?;
+                return Ok(true);
+            }
+            if let Some(node) = cfhe_index(database, source)? {
+                database.remove_cfhe(node).map_err(|e| e.to_string())?;
+                return Ok(true);
+            }
+            let index = slot_to_index(database, source)?;
+            if index == 0 {
+                return Err("The personal profile can't be deleted".to_string());
+            }
+            database.owned[index] = database::ProfileFull::default();
+            Ok(true)
+        }
+        "move" => {
+            if let Some(index) = invited_index(database, get_arg(args, 1)?)? {
+                let position = get_arg(args, 2)?;
+                let position = position
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid position: {}", position))?;
+                database
+                    .move_invited(index, position)
+                    .map_err(|e| e.to_string())?;
+                return Ok(true);
+            }
+            let slot = parse_slot(get_arg(args, 1)?)?;
+            let other_slot = parse_slot(get_arg(args, 2)?)?;
+            if database.owned_slot_to_index(slot).is_none() {
+                return Err(format!("Slot {} is empty", slot));
+            }
+            database.swap_owned_slots(slot, other_slot);
+            Ok(true)
+        }
+        "randomize" => {
+            let slot = parse_slot(get_arg(args, 1)?)?;
+            let mut seed = None;
+            let mut constraints = random::RandomConstraints::default();
+            let mut options = args[2..].iter().map(|s| s.as_str());
+            while let Some(arg) = options.next() {
+                if arg == "--no-glasses" {
+                    constraints.no_glasses = true;
+                    continue;
+                }
+                let value = options.next().ok_or("Missing argument")?;
+                let invalid = || format!("Invalid value for {}: {}", arg, value);
+                match arg {
+                    "--seed" => seed = Some(value.parse::<u64>().map_err(|_| invalid())?),
+                    "--sex" => {
+                        constraints.sex = Some(match value {
+                            "male" => 0,
+                            "female" => 1,
+                            _ => return Err(invalid()),
+                        })
+                    }
+                    "--skin" => match value.parse::<u32>() {
+                        Ok(color) if color < 6 => constraints.skin_color = Some(color),
+                        _ => return Err(invalid()),
+                    },
+                    _ => return Err(format!("Unknown option: {}", arg)),
+                }
+            }
+            let seed = seed.unwrap_or_else(|| {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or(0)
+            });
+
+            let index = match database.owned_slot_to_index(slot) {
+                Some(index) => index,
+                None => {
+                    let index = database.free_owned_index().ok_or("There is no free slot")?;
+                    let mac = database.owned[0].main.id.mac;
+                    let system_id = database.owned[0].main.system_id;
+                    let now = chrono::Local::now().naive_local();
+                    database.owned[index].main = database::Profile::new(mac, system_id, now, slot);
+                    index
+                }
+            };
+            random::randomize(&mut database.owned[index].main, seed, &constraints);
+            println!("Randomized slot {} with seed {}", slot, seed);
+            Ok(true)
+        }
+        "set-field" => {
+            let source = get_arg(args, 1)?;
+            if let Some(index) = invited_index(database, source)? {
+                let field = get_arg(args, 2)?;
+                if field == "author" {
+                    return Err("Invited profiles have no author".to_string());
+                }
+                let mut profile =
+                    database::ProfileFull::from_profile(database.invited[index].clone());
+                set_field(&mut profile, field, get_arg(args, 3)?)?;
+                database.invited[index] = profile.main;
+                return Ok(true);
+            }
+            let index = slot_to_index(database, source)?;
+            set_field(
+                &mut database.owned[index],
+                get_arg(args, 2)?,
+                get_arg(args, 3)?,
+            )?;
+            Ok(true)
+        }
+        "diff" => {
+            let other = read_database(get_arg(args, 1)?)?;
+            let changes = diff::diff(database, &other);
+            for change in &changes {
+                println!("{}", change);
+            }
+            if changes.is_empty() {
+                println!("No difference found");
+            }
+            Ok(false)
+        }
+        command => Err(format!("Unknown command: {}", command)),
+    }
+}
+
+fn validate(database_data: &[u8]) -> Result<(), String> {
+    let issues = validation::validate_bytes(database_data).map_err(|e| e.to_string())?;
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    match issues.len() {
+        0 => println!("No issue found"),
+        n if issues.iter().all(|issue| issue.is_repairable()) => {
+            println!(
+                "{} issue(s) found, all of which can be fixed with repair",
+                n
+            )
+        }
+        n => println!("{} issue(s) found", n),
+    }
+    Ok(())
+}
+
+// Returns the number of conflicts that are left to the user with --fail-on-conflict
+fn merge(database: &mut database::Database, args: &[String]) -> Result<usize, String> {
+    let base = read_database(get_arg(args, 1)?)?;
+    let theirs = read_database(get_arg(args, 2)?)?;
+    let mut prefer = diff::Side::Ours;
+    let mut fail_on_conflict = false;
+    for option in &args[3..] {
+        match option.as_str() {
+            "--theirs" => prefer = diff::Side::Theirs,
+            "--fail-on-conflict" => fail_on_conflict = true,
+            option => return Err(format!("Unknown option: {}", option)),
+        }
+    }
+    let (merged, conflicts) =
+        diff::merge(&base, database, &theirs, prefer).map_err(|e| e.to_string())?;
+    for conflict in &conflicts {
+        println!("Conflict: {}", conflict);
+    }
+    if !conflicts.is_empty() {
+        println!(
+            "{} conflict(s) resolved in favor of {}",
+            conflicts.len(),
+            if prefer == diff::Side::Ours {
+                "ours"
+            } else {
+                "theirs"
+            }
+        );
+    }
+    *database = merged;
+    Ok(if fail_on_conflict { conflicts.len() } else { 0 })
+}
+
+// Returns the repaired database, or None if nothing needs to be repaired
+fn repair(database_data: &[u8]) -> Result<Option<database::Database>, String> {
+    let issues = validation::validate_bytes(database_data).map_err(|e| e.to_string())?;
+    if issues.is_empty() {
+        println!("No issue found");
+        return Ok(None);
+    }
+    let mut database =
+        database::Database::from_bytes_unchecked(database_data).map_err(|e| e.to_string())?;
+    let fixed = database.repair();
+    for issue in &issues {
+        // CRCs are recomputed when the database is written
+        let crc = matches!(issue, validation::Issue::BadCrc { .. });
+        if crc || fixed.contains(issue) {
+            println!("Fixed: {}", issue);
+        } else {
+            println!("Not fixed: {}", issue);
+        }
+    }
+    Ok(Some(database))
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    // --backup can be given anywhere after the command
+    let backup = args.iter().any(|arg| arg == "--backup");
+    let args: Vec<String> = args
+        .iter()
+        .filter(|arg| *arg != "--backup")
+        .cloned()
+        .collect();
+    let command = get_arg(&args, 2)?;
+    let database_path = std::path::Path::new(&args[1]);
+    let database_data = std::fs::read(database_path)
+        .map_err(|e| format!("Unable to read {}: {}", database_path.display(), e))?;
+    let mut unresolved = 0;
+    let database = match command {
+        "validate" => return validate(&database_data[..]),
+        "repair" => repair(&database_data[..])?,
+        _ => {
+            let mut database =
+                database::Database::from_bytes(&database_data[..]).map_err(|e| e.to_string())?;
+            let modified = if command == "merge" {
+                unresolved = merge(&mut database, &args[2..])?;
+                true
+            } else {
+                run_command(&mut database, &args[2..])?
+            };
+            if modified {
+                Some(database)
+            } else {
+                None
+            }
+        }
+    };
+    if let Some(database) = database {
+        let write_error =
+            |e: citrii::error::Error| format!("Unable to write {}: {}", database_path.display(), e);
+        if backup {
+            let backup =
+                save::save_with_backup(database_path, &database.to_bytes(), save::BACKUP_COUNT)
+                    .map_err(write_error)?;
+            if let Some(backup) = backup {
+                println!("Previous database backed up to {}", backup.display());
+            }
+        } else {
+            save::write_atomic(database_path, &database.to_bytes()).map_err(write_error)?;
+        }
+    }
+    if unresolved > 0 {
+        return Err(format!(
+            "{} conflict(s) need to be resolved by hand",
+            unresolved
+        ));
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        print_usage();
+        return;
+    }
+    let result = match args[1].as_str() {
+        "extract-assets" => extract_assets(&args),
+        "pack-assets" => pack_assets(&args),
+        _ => run(&args),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[test]
+fn cli_test() {
+    let root = std::env::temp_dir().join(format!("citrii-cli-test-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let path = root.join("CFL_DB.dat");
+    let file = |name: &str| root.join(name).to_string_lossy().to_string();
+    let data = vec![0u8; database::Database::BYTE_LEN];
+    let mut database = database::Database::from_bytes_unchecked(&data[..]).unwrap();
+    let time = chrono::NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0);
+    database.owned[0].main = database::Profile::new([1; 6], [2; 8], time, 0);
+    database.owned[1].main = database::Profile::new([3; 6], [4; 8], time, 1);
+    std::fs::write(&path, database.to_bytes()).unwrap();
+    let run_args = |args: &[&str]| {
+        let mut all = vec!["citrii-cli".to_string(), file("CFL_DB.dat")];
+        all.extend(args.iter().map(|arg| arg.to_string()));
+        run(&all)
+    };
+
+    run_args(&["list"]).unwrap();
+    run_args(&["set-field", "1", "hair.style", "12"]).unwrap();
+    run_args(&["set-field", "1", "name", "Tester"]).unwrap();
+    assert!(run_args(&["set-field", "1", "hair.style", "1000"]).is_err());
+    assert!(run_args(&["set-field", "1", "name", "\u{1F600}"]).is_err());
+    run_args(&["export", "1", &file("a.json")]).unwrap();
+    run_args(&["delete", "1"]).unwrap();
+    run_args(&["import", &file("a.json"), "5"]).unwrap();
+
+    std::fs::copy(file("a.json"), file("a.bin")).unwrap();
+    let error = run_args(&["import", &file("a.bin")]).unwrap_err();
+    assert!(error.contains("unknown profile format"), "{}", error);
+    let error = run_args(&["import", &file("a.json"), "--asset", &file("x.app")]).unwrap_err();
+    assert!(error.contains("--asset"), "{}", error);
+    assert!(run_args(&["import"]).is_err());
+    assert!(run_args(&["unknown"]).is_err());
+
+    let database = database::Database::from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+    assert!(database.owned_slot_to_index(1).is_none());
+    let profile = &database.owned[database.owned_slot_to_index(5).unwrap()].main;
+    assert_eq!(profile.hair.style, 12);
+    assert_eq!(name::MiiName(profile.name).to_string(), "Tester");
+    // Backups are only made with --backup
+    assert_eq!(save::list_backups(&path), Vec::<std::path::PathBuf>::new());
+    run_args(&["delete", "5", "--backup"]).unwrap();
+    assert_eq!(save::list_backups(&path).len(), 1);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,119 @@
+// Browsing of the encountered (CFHE) Miis, such as those met through StreetPass. Each node of
+// cfhe_objects is linked to the previous and the next node, and the profile of node i is
+// cfhe_profiles[i].
+
+use crate::database::*;
+use crate::error::*;
+use chrono::*;
+
+// Time stamps of encountered Miis count seconds from 2000-01-01
+pub fn timestamp_to_time(timestamp: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0) + Duration::seconds(timestamp as i64)
+}
+
+impl Database {
+    pub fn cfhe_time(&self, node: usize) -> NaiveDateTime {
+        timestamp_to_time(self.cfhe_profiles[node].timestamp)
+    }
+
+    // Links the nodes in `order` from cfhe_head to cfhe_tail. Other nodes are left as they are.
+    pub(crate) fn set_cfhe_order(&mut self, order: &[usize]) {
+        for (i, &node) in order.iter().enumerate() {
+            let list_node = &mut self.cfhe_objects[node].list_node;
+            match i.checked_sub(1).map(|p| order[p]) {
+                Some(prev) => {
+                    list_node.prev = prev as u32;
+                    list_node.pf = 1;
+                }
+                None => {
+                    list_node.prev = 0;
+                    list_node.pf = 0;
+                }
+            }
+            match order.get(i + 1) {
+                Some(&next) => {
+                    list_node.next = next as u32;
+                    list_node.nf = 1;
+                }
+                None => {
+                    list_node.next = 0;
+                    list_node.nf = 0;
+                }
+            }
+        }
+
+        let (head, tail) = match (order.first(), order.last()) {
+            (Some(&head), Some(&tail)) => (head, tail),
+            _ => (0, 0),
+        };
+        self.cfhe_head = head as u16;
+        self.cfhe_tail = tail as u16;
+    }
+
+    // Copies an encountered Mii into `slot`, or the first free slot if not specified, and returns
+    // the slot. Like on the 3DS, a Mii can only be copied if its creator allowed it.
+    pub fn copy_cfhe_to_owned(&mut self, node: usize, slot: Option<usize>) -> Result<usize, Error> {
+        let profile = &self.cfhe_profiles.get(node).ok_or(Error::NullProfile)?.main;
+        if profile.is_null() {
+            return Err(Error::NullProfile);
+        }
+        if profile.header.allow_copying == 0 {
+            return Err(Error::CopyingNotAllowed);
+        }
+        self.import_owned(ProfileFull::from_profile(profile.clone()), slot)
+    }
+
+    // Removes an encountered Mii and links its neighbors to each other
+    pub fn remove_cfhe(&mut self, node: usize) -> Result<(), Error> {
+        let mut order = self.cfhe_order();
+        let position = order
+            .iter()
+            .position(|&n| n == node)
+            .ok_or(Error::NullProfile)?;
+        order.remove(position);
+        self.cfhe_objects[node] = CFHEObject::default();
+        self.cfhe_profiles[node] = ProfileAlt::default();
+        self.set_cfhe_order(&order);
+        Ok(())
+    }
+}
+
+#[test]
+fn cfhe_test() {
+    let mut database = test_database();
+    database.owned[0].main = test_profile(1, 0, 0);
+    for (i, &node) in [4, 9, 2].iter().enumerate() {
+        let profile = test_profile(3, i as i64, 0);
+        database.cfhe_objects[node].profile_id = profile.id.clone();
+        database.cfhe_profiles[node].main = profile;
+        database.cfhe_profiles[node].timestamp = 86400 * (i as u32 + 1);
+    }
+    database.set_cfhe_order(&[4, 9, 2]);
+    assert_eq!(database.cfhe_order(), vec![4, 9, 2]);
+    assert_eq!(
+        database.cfhe_time(9),
+        NaiveDate::from_ymd(2000, 1, 3).and_hms(0, 0, 0)
+    );
+    assert!(database.validate().is_empty());
+
+    database.remove_cfhe(4).unwrap();
+    assert_eq!(database.cfhe_order(), vec![9, 2]);
+    database.remove_cfhe(2).unwrap();
+    assert_eq!(database.cfhe_order(), vec![9]);
+    assert!(database.validate().is_empty());
+    assert!(matches!(database.remove_cfhe(2), Err(Error::NullProfile)));
+
+    assert!(matches!(
+        database.copy_cfhe_to_owned(9, None),
+        Err(Error::CopyingNotAllowed)
+    ));
+    database.cfhe_profiles[9].main.header.allow_copying = 1;
+    assert_eq!(database.copy_cfhe_to_owned(9, None).unwrap(), 1);
+    assert!(matches!(
+        database.copy_cfhe_to_owned(9, None),
+        Err(Error::DuplicateProfile { slot: Some(1) })
+    ));
+    database.remove_cfhe(9).unwrap();
+    assert!(database.cfhe_order().is_empty());
+    assert!(database.validate().is_empty());
+}
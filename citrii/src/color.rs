@@ -68,3 +68,16 @@ pub fn convert_color((r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
 pub fn get_color(table: &[(u8, u8, u8)], index: usize) -> (f32, f32, f32) {
     convert_color(*table.get(index).unwrap_or(&INVALID_COLOR))
 }
+
+// Index of the color in `table` closest to `color`
+pub fn nearest_color(table: &[(u8, u8, u8)], (r, g, b): (u8, u8, u8)) -> usize {
+    let distance = |&(tr, tg, tb): &(u8, u8, u8)| {
+        [(r, tr), (g, tg), (b, tb)]
+            .iter()
+            .map(|&(a, b)| (i32::from(a) - i32::from(b)).pow(2))
+            .sum::<i32>()
+    };
+    (0..table.len())
+        .min_by_key(|&i| distance(&table[i]))
+        .unwrap_or(0)
+}
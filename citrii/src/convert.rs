@@ -0,0 +1,954 @@
+// Conversion between the 3DS profile and the Mii formats of other consoles.
+//
+// The Wii (RFL) stores a Mii in 0x4A big-endian bytes. Style indices are shared with the 3DS up to
+// the Wii's own ranges, so converting from the Wii only needs the facial feature split into the
+// 3DS wrinkle and makeup. Converting to the Wii has to approximate the parts added on the 3DS:
+// - styles beyond the Wii ranges fall back to the styles of a new profile
+// - lip colors 3 and 4 become the nearest of the three Wii lip colors
+// - wrinkle and makeup become a single facial feature, keeping the makeup if both are set
+// - the vertical stretch (y_scale) of eyes, eyebrows and lips is dropped
+// Each approximation is reported so that the caller can show what changed.
+//
+// Mii IDs are carried over bit for bit rather than converted as times, so that converted Miis keep
+// unique IDs and convert back to the same ID. The flags of special and foreign Wii Miis are kept in
+// the flag bits of the 3DS ID. The Wii system ID is the last 4 bytes of the 3DS
+// MAC address; the first 2 bytes and the 3DS system ID are lost.
+//
+// The Wii U (FFSD) stores the same data as the 3DS (.cfsd).
+//
+// The DS format, which ProfileHeader::version_major can name as the origin of a profile, is not
+// supported.
+//
+// The Switch (nn::mii CharInfo) stores each field in a byte and has more parts and colors. Styles
+// beyond what the asset contains fall back to the styles of a new profile. Colors are indices
+// into a palette of 100 colors, of which only those taken over from the 3DS are known here: they
+// map to their 3DS color, or to the nearest 3DS color for another part. The other colors fall back
+// to the color of a new profile.
+//
+// Only CharInfo is read. The Switch's StoreData, which packs the same fields into bit fields with
+// a CRC, is not supported; a Mii in that format has to be exported as CharInfo first.
+
+use crate::asset::*;
+use crate::color::*;
+use crate::database::*;
+use crate::error::*;
+use crate::schema;
+use byte_struct::*;
+
+pub const RFL_LEN: usize = 0x4A;
+
+bitfields!(
+    #[derive(Clone, Debug, Default)]
+    pub RflGeneral: u16 {
+        pub favorite: 1,
+        pub favorite_color: 4,
+        pub birth_day: 5,
+        pub birth_month: 4,
+        pub sex: 1,
+        pub invalid: 1,
+    }
+);
+
+bitfields!(
+    #[derive(Clone, Debug, Default)]
+    pub RflFace: u16 {
+        pub downloaded: 1,
+        pub unknown_a: 1,
+        pub mingle_off: 1,
+        pub unknown_b: 3,
+        pub feature: 4,
+        pub color: 3,
+        pub style: 3,
+    }
+);
+
+bitfields!(
+    #[derive(Clone, Debug, Default)]
+    pub RflHair: u16 {
+        pub unknown: 5,
+        pub flip: 1,
+        pub color: 3,
+        pub style: 7,
+    }
+);
+
+bitfields!(
+    #[derive(Clone, Debug, Default)]
+    pub RflEyebrow: u32 {
+        pub x: 4,
+        pub y: 5,
+        pub scale: 4,
+        pub color: 3,
+        pub unknown_b: 6,
+        pub rotation: 4,
+        pub unknown_a: 1,
+        pub style: 5,
+    }
+);
+
+bitfields!(
+    #[derive(Clone, Debug, Default)]
+    pub RflEye: u32 {
+        pub unknown_c: 5,
+        pub x: 4,
+        pub scale: 3,
+        pub unknown_b: 1,
+        pub color: 3,
+        pub y: 5,
+        pub rotation: 3,
+        pub unknown_a: 2,
+        pub style: 6,
+    }
+);
+
+bitfields!(
+    #[derive(Clone, Debug, Default)]
+    pub RflNose: u16 {
+        pub unknown: 3,
+        pub y: 5,
+        pub scale: 4,
+        pub style: 4,
+    }
+);
+
+bitfields!(
+    #[derive(Clone, Debug, Default)]
+    pub RflLip: u16 {
+        pub y: 5,
+        pub scale: 4,
+        pub color: 2,
+        pub style: 5,
+    }
+);
+
+bitfields!(
+    #[derive(Clone, Debug, Default)]
+    pub RflGlass: u16 {
+        pub y: 5,
+        pub scale: 3,
+        pub unknown: 1,
+        pub color: 3,
+        pub style: 4,
+    }
+);
+
+bitfields!(
+    #[derive(Clone, Debug, Default)]
+    pub RflBeard: u16 {
+        pub mustache_y: 5,
+        pub mustache_scale: 4,
+        pub color: 3,
+        pub style: 2,
+        pub mustache_style: 2,
+    }
+);
+
+bitfields!(
+    #[derive(Clone, Debug, Default)]
+    pub RflMole: u16 {
+        pub unknown: 1,
+        pub x: 5,
+        pub y: 5,
+        pub scale: 4,
+        pub style: 1,
+    }
+);
+
+#[derive(ByteStruct, Clone, Debug, Default)]
+#[byte_struct_be]
+pub struct RflCharData {
+    pub general: RflGeneral,
+    pub name: [u16; 10],
+    pub height: u8,
+    pub width: u8,
+    pub id: u32, // flags in the top 3 bits, then 4-second units since 2006-01-01
+    pub system_id: [u8; 4],
+    pub face: RflFace,
+    pub hair: RflHair,
+    pub eyebrow: RflEyebrow,
+    pub eye: RflEye,
+    pub nose: RflNose,
+    pub lip: RflLip,
+    pub glass: RflGlass,
+    pub beard: RflBeard,
+    pub mole: RflMole,
+    pub author: [u16; 10],
+}
+
+// The Wii ID as a 3DS ID. The top bits, which mark special and foreign Wii Miis, become the flags.
+fn id_from_rfl(id: u32) -> ProfileIdLow {
+    ProfileIdLow {
+        creation_date: id & 0x0FFF_FFFF,
+        unknown: (id >> 28) & 1,
+        temporary: (id >> 29) & 1,
+        ntr: (id >> 30) & 1,
+        normal: id >> 31,
+    }
+}
+
+fn id_to_rfl(id: &ProfileIdLow) -> u32 {
+    id.creation_date | id.unknown << 28 | id.temporary << 29 | id.ntr << 30 | id.normal << 31
+}
+
+// 3DS (wrinkle, makeup) of each Wii facial feature
+const FEATURE_TABLE: [(u16, u16); 12] = [
+    (0, 0),
+    (0, 1),
+    (0, 6),
+    (0, 9),
+    (5, 0),
+    (2, 0),
+    (3, 0),
+    (7, 0),
+    (8, 0),
+    (0, 10),
+    (9, 0),
+    (11, 0),
+];
+
+// Largest Wii value of the fields whose Wii range is smaller than the 3DS one
+const RFL_MAX: &[(&str, u32)] = &[
+    ("height", 127),
+    ("width", 127),
+    ("face.style", 7),
+    ("hair.style", 71),
+    ("eye.style", 47),
+    ("eye.scale", 7),
+    ("eye.rotation", 7),
+    ("eyebrow.rotation", 11),
+    ("nose.style", 11),
+    ("lip.style", 23),
+    ("lip.color", 2),
+    ("mustache.style", 3),
+    ("beard.style", 3),
+    ("glass.scale", 7),
+];
+
+// Fields that can't be stored, with the value they have when converted back
+const RFL_DROPPED: &[(&str, u32)] = &[
+    ("eye.y_scale", 3),
+    ("eyebrow.y_scale", 3),
+    ("lip.y_scale", 3),
+];
+
+// A field that couldn't be converted exactly. `to` is None if the field was dropped.
+#[derive(Debug, PartialEq)]
+pub struct Approximation {
+    pub field: &'static str,
+    pub from: u32,
+    pub to: Option<u32>,
+}
+
+impl std::fmt::Display for Approximation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.to {
+            Some(to) => write!(f, "{} {} is replaced by {}", self.field, self.from, to),
+            None => write!(f, "{} {} is not supported", self.field, self.from),
+        }
+    }
+}
+
+// Wrinkle and makeup as a Wii facial feature, matching the makeup first if both can't be kept
+fn feature_from_face(face: &ProfileFace) -> Option<u16> {
+    let (wrinkle, makeup) = (face.wrinkle, face.makeup);
+    let position = |wrinkle: u16, makeup: u16| {
+        FEATURE_TABLE
+            .iter()
+            .position(|&f| f == (wrinkle, makeup))
+            .map(|f| f as u16)
+    };
+    position(wrinkle, makeup)
+        .or_else(|| position(0, makeup))
+        .or_else(|| position(wrinkle, 0))
+}
+
+impl ProfileFull {
+    pub fn from_rfl(data: &[u8]) -> Result<ProfileFull, Error> {
+        if data.len() != RFL_LEN {
+            return Err(Error::BadProfileSize {
+                expected: RFL_LEN,
+                found: data.len(),
+            });
+        }
+        let rfl = RflCharData::read_bytes(data);
+        if rfl.id == 0 {
+            return Err(Error::NullProfile);
+        }
+
+        let system_id = rfl.system_id;
+        let mac = [0, 0, system_id[0], system_id[1], system_id[2], system_id[3]];
+        let epoch = chrono::NaiveDate::from_ymd(2010, 1, 1).and_hms(0, 0, 0);
+        let mut main = Profile::new(mac, [0; 8], epoch, 0);
+        main.id.low = id_from_rfl(rfl.id);
+        main.name = rfl.name;
+        main.height = rfl.height;
+        main.width = rfl.width;
+
+        let general = &mut main.general;
+        general.sex = rfl.general.sex;
+        general.birth_month = rfl.general.birth_month;
+        general.birth_day = rfl.general.birth_day;
+        general.favorite_color = rfl.general.favorite_color;
+        general.favorite = rfl.general.favorite;
+
+        let face = &mut main.face;
+        face.disable_sharing = rfl.face.mingle_off;
+        face.style = rfl.face.style;
+        face.color = rfl.face.color;
+        let (wrinkle, makeup) = FEATURE_TABLE
+            .get(rfl.face.feature as usize)
+            .copied()
+            .unwrap_or((0, 0));
+        face.wrinkle = wrinkle;
+        face.makeup = makeup;
+
+        main.hair.style = rfl.hair.style;
+        main.hair.color = rfl.hair.color;
+        main.hair.flip = rfl.hair.flip;
+
+        let eye = &mut main.eye;
+        eye.style = rfl.eye.style;
+        eye.color = rfl.eye.color;
+        eye.scale = rfl.eye.scale;
+        eye.rotation = rfl.eye.rotation;
+        eye.x = rfl.eye.x;
+        eye.y = rfl.eye.y;
+
+        let eyebrow = &mut main.eyebrow;
+        eyebrow.style = rfl.eyebrow.style;
+        eyebrow.color = rfl.eyebrow.color;
+        eyebrow.scale = rfl.eyebrow.scale;
+        eyebrow.rotation = rfl.eyebrow.rotation;
+        eyebrow.x = rfl.eyebrow.x;
+        eyebrow.y = rfl.eyebrow.y;
+
+        main.nose.style = rfl.nose.style;
+        main.nose.scale = rfl.nose.scale;
+        main.nose.y = rfl.nose.y;
+
+        main.lip.style = rfl.lip.style;
+        main.lip.color = rfl.lip.color;
+        main.lip.scale = rfl.lip.scale;
+        main.misc.lip_y = rfl.lip.y;
+
+        main.misc.mustache_style = rfl.beard.mustache_style;
+        main.beard.style = rfl.beard.style;
+        main.beard.color = rfl.beard.color;
+        main.beard.mustache_scale = rfl.beard.mustache_scale;
+        main.beard.mustache_y = rfl.beard.mustache_y;
+
+        main.glass.style = rfl.glass.style;
+        main.glass.color = rfl.glass.color;
+        main.glass.scale = rfl.glass.scale;
+        main.glass.y = rfl.glass.y;
+
+        main.mole.style = rfl.mole.style;
+        main.mole.scale = rfl.mole.scale;
+        main.mole.x = rfl.mole.x;
+        main.mole.y = rfl.mole.y;
+
+        Ok(ProfileFull {
+            main,
+            author: rfl.author,
+        })
+    }
+
+    // Returns the Wii data and the fields that had to be approximated
+    pub fn to_rfl(&self) -> (Vec<u8>, Vec<Approximation>) {
+        let mut approximations = vec![];
+        let mut main = self.main.clone();
+        let epoch = chrono::NaiveDate::from_ymd(2010, 1, 1).and_hms(0, 0, 0);
+        let defaults = Profile::new([0; 6], [0; 8], epoch, 0);
+        for &(field, max) in RFL_MAX {
+            let from = main.get_field(field).unwrap();
+            if from <= max {
+                continue;
+            }
+            let to = if field == "lip.color" {
+                let color = *LIP_COLOR_TABLE.get(from as usize).unwrap_or(&INVALID_COLOR);
+                nearest_color(&LIP_COLOR_TABLE[0..=max as usize], color) as u32
+            } else if field.ends_with(".style") {
+                defaults.get_field(field).unwrap()
+            } else {
+                max
+            };
+            main.set_field(field, to);
+            approximations.push(Approximation {
+                field,
+                from,
+                to: Some(to),
+            });
+        }
+        for &(field, kept) in RFL_DROPPED {
+            let from = main.get_field(field).unwrap();
+            if from != kept {
+                approximations.push(Approximation {
+                    field,
+                    from,
+                    to: None,
+                });
+            }
+        }
+        let feature = feature_from_face(&main.face).unwrap_or(0);
+        let (wrinkle, makeup) = FEATURE_TABLE[feature as usize];
+        for &(field, from, to) in &[
+            ("face.wrinkle", main.face.wrinkle, wrinkle),
+            ("face.makeup", main.face.makeup, makeup),
+        ] {
+            if from != to {
+                approximations.push(Approximation {
+                    field,
+                    from: from as u32,
+                    to: Some(to as u32),
+                });
+            }
+        }
+
+        let mac = &main.id.mac;
+        let rfl = RflCharData {
+            general: RflGeneral {
+                favorite: main.general.favorite,
+                favorite_color: main.general.favorite_color,
+                birth_day: main.general.birth_day,
+                birth_month: main.general.birth_month,
+                sex: main.general.sex,
+                invalid: 0,
+            },
+            name: main.name,
+            height: main.height,
+            width: main.width,
+            id: id_to_rfl(&main.id.low),
+            system_id: [mac[2], mac[3], mac[4], mac[5]],
+            face: RflFace {
+                downloaded: 0,
+                unknown_a: 0,
+                mingle_off: main.face.disable_sharing,
+                unknown_b: 0,
+                feature,
+                color: main.face.color,
+                style: main.face.style,
+            },
+            hair: RflHair {
+                unknown: 0,
+                flip: main.hair.flip,
+                color: main.hair.color,
+                style: main.hair.style,
+            },
+            eyebrow: RflEyebrow {
+                x: main.eyebrow.x,
+                y: main.eyebrow.y,
+                scale: main.eyebrow.scale,
+                color: main.eyebrow.color,
+                unknown_b: 0,
+                rotation: main.eyebrow.rotation,
+                unknown_a: 0,
+                style: main.eyebrow.style,
+            },
+            eye: RflEye {
+                unknown_c: 0,
+                x: main.eye.x,
+                scale: main.eye.scale,
+                unknown_b: 0,
+                color: main.eye.color,
+                y: main.eye.y,
+                rotation: main.eye.rotation,
+                unknown_a: 0,
+                style: main.eye.style,
+            },
+            nose: RflNose {
+                unknown: 0,
+                y: main.nose.y,
+                scale: main.nose.scale,
+                style: main.nose.style,
+            },
+            lip: RflLip {
+                y: main.misc.lip_y,
+                scale: main.lip.scale,
+                color: main.lip.color,
+                style: main.lip.style,
+            },
+            glass: RflGlass {
+                y: main.glass.y,
+                scale: main.glass.scale,
+                unknown: 0,
+                color: main.glass.color,
+                style: main.glass.style,
+            },
+            beard: RflBeard {
+                mustache_y: main.beard.mustache_y,
+                mustache_scale: main.beard.mustache_scale,
+                color: main.beard.color,
+                style: main.beard.style,
+                mustache_style: main.misc.mustache_style,
+            },
+            mole: RflMole {
+                unknown: 0,
+                x: main.mole.x,
+                y: main.mole.y,
+                scale: main.mole.scale,
+                style: main.mole.style,
+            },
+            author: self.author,
+        };
+        let mut data = vec![0u8; RFL_LEN];
+        rfl.write_bytes(&mut data[..]);
+        (data, approximations)
+    }
+
+    pub fn from_ffsd(data: &[u8]) -> Result<ProfileFull, Error> {
+        ProfileFull::from_cfsd(data)
+    }
+
+    pub fn to_ffsd(&self) -> Vec<u8> {
+        self.to_cfsd()
+    }
+}
+
+pub const CHARINFO_LEN: usize = 0x58;
+
+#[derive(ByteStruct, Clone, Debug, Default)]
+#[byte_struct_le]
+pub struct CharInfo {
+    pub create_id: [u8; 16],
+    pub nickname: [u16; 11],
+    pub font_region: u8,
+    pub favorite_color: u8,
+    pub gender: u8,
+    pub height: u8,
+    pub build: u8,
+    pub mii_type: u8,
+    pub region_move: u8,
+    pub faceline_type: u8,
+    pub faceline_color: u8,
+    pub faceline_wrinkle: u8,
+    pub faceline_make: u8,
+    pub hair_type: u8,
+    pub hair_color: u8,
+    pub hair_flip: u8,
+    pub eye_type: u8,
+    pub eye_color: u8,
+    pub eye_scale: u8,
+    pub eye_aspect: u8,
+    pub eye_rotate: u8,
+    pub eye_x: u8,
+    pub eye_y: u8,
+    pub eyebrow_type: u8,
+    pub eyebrow_color: u8,
+    pub eyebrow_scale: u8,
+    pub eyebrow_aspect: u8,
+    pub eyebrow_rotate: u8,
+    pub eyebrow_x: u8,
+    pub eyebrow_y: u8,
+    pub nose_type: u8,
+    pub nose_scale: u8,
+    pub nose_y: u8,
+    pub mouth_type: u8,
+    pub mouth_color: u8,
+    pub mouth_scale: u8,
+    pub mouth_aspect: u8,
+    pub mouth_y: u8,
+    pub beard_color: u8,
+    pub beard_type: u8,
+    pub mustache_type: u8,
+    pub mustache_scale: u8,
+    pub mustache_y: u8,
+    pub glass_type: u8,
+    pub glass_color: u8,
+    pub glass_scale: u8,
+    pub glass_y: u8,
+    pub mole_type: u8,
+    pub mole_scale: u8,
+    pub mole_x: u8,
+    pub mole_y: u8,
+    pub reserved: u8,
+}
+
+// Switch palette index of each 3DS color
+const SWITCH_HAIR_COLORS: [u8; 8] = [8, 1, 2, 3, 4, 5, 6, 7];
+const SWITCH_EYE_COLORS: [u8; 6] = [8, 9, 10, 11, 12, 13];
+const SWITCH_GLASS_COLORS: [u8; 6] = [8, 14, 15, 16, 17, 18];
+const SWITCH_LIP_COLORS: [u8; 5] = [19, 20, 21, 22, 23];
+
+type ColorTable = [(u8, u8, u8)];
+
+const SWITCH_COLOR_TABLES: [(&[u8], &ColorTable); 4] = [
+    (&SWITCH_HAIR_COLORS, &HAIR_COLOR_TABLE),
+    (&SWITCH_EYE_COLORS, &EYE_COLOR_TABLE),
+    (&SWITCH_GLASS_COLORS, &GLASS_COLOR_TABLE),
+    (&SWITCH_LIP_COLORS, &LIP_COLOR_TABLE),
+];
+
+fn switch_color(color: u8) -> Option<(u8, u8, u8)> {
+    SWITCH_COLOR_TABLES
+        .iter()
+        .find_map(|(colors, table)| colors.iter().position(|&c| c == color).map(|i| table[i]))
+}
+
+// Whether the asset has the part that a style field selects
+fn asset_has_style<M, T>(asset: &AssetData<M, T>, field: &str, style: u32) -> bool {
+    let style = style as usize;
+    let models = |part| asset.models(part).len();
+    let textures = |part| asset.textures(part).len();
+    match field {
+        "face.style" => style < models(ModelPart::Face),
+        "face.wrinkle" => style < textures(TexturePart::Wrinkle),
+        "face.makeup" => style < textures(TexturePart::Makeup),
+        "hair.style" => style * 2 < models(ModelPart::Hair),
+        "eye.style" => style < textures(TexturePart::Eye),
+        "eyebrow.style" => style < textures(TexturePart::Eyebrow),
+        "nose.style" => style < models(ModelPart::Nose),
+        "lip.style" => style < textures(TexturePart::Lip),
+        "mustache.style" => style < textures(TexturePart::Mustache),
+        "beard.style" if style <= 3 => style < models(ModelPart::Beard),
+        "beard.style" => style - 3 < textures(TexturePart::Beard),
+        "glass.style" => style < textures(TexturePart::Glass),
+        _ => true,
+    }
+}
+
+impl ProfileFull {
+    // Returns the profile and the fields that had to be approximated. Without an asset, styles
+    // are only checked against the ranges of the 3DS.
+    pub fn from_charinfo<M, T>(
+        data: &[u8],
+        asset: Option<&AssetData<M, T>>,
+    ) -> Result<(ProfileFull, Vec<Approximation>), Error> {
+        if data.len() != CHARINFO_LEN {
+            return Err(Error::BadProfileSize {
+                expected: CHARINFO_LEN,
+                found: data.len(),
+            });
+        }
+        let info = CharInfo::read_bytes(data);
+        if info.create_id == [0; 16] {
+            return Err(Error::NullProfile);
+        }
+
+        // The ID is taken from the create ID, so that the same Mii always gets the same ID
+        let id = &info.create_id;
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&id[10..16]);
+        let epoch = chrono::NaiveDate::from_ymd(2010, 1, 1).and_hms(0, 0, 0);
+        let defaults = Profile::new(mac, [0; 8], epoch, 0);
+        let mut main = defaults.clone();
+        main.id.low.creation_date = u32::from_le_bytes([id[0], id[1], id[2], id[3]]) & 0x0FFF_FFFF;
+        main.name.copy_from_slice(&info.nickname[0..10]);
+
+        let mut approximations = vec![];
+        let fields = [
+            ("char_set", info.font_region),
+            ("sex", info.gender),
+            ("favorite_color", info.favorite_color),
+            ("height", info.height),
+            ("width", info.build),
+            ("face.style", info.faceline_type),
+            ("face.color", info.faceline_color),
+            ("face.wrinkle", info.faceline_wrinkle),
+            ("face.makeup", info.faceline_make),
+            ("hair.style", info.hair_type),
+            ("hair.flip", info.hair_flip),
+            ("eye.style", info.eye_type),
+            ("eye.scale", info.eye_scale),
+            ("eye.y_scale", info.eye_aspect),
+            ("eye.rotation", info.eye_rotate),
+            ("eye.x", info.eye_x),
+            ("eye.y", info.eye_y),
+            ("eyebrow.style", info.eyebrow_type),
+            ("eyebrow.scale", info.eyebrow_scale),
+            ("eyebrow.y_scale", info.eyebrow_aspect),
+            ("eyebrow.rotation", info.eyebrow_rotate),
+            ("eyebrow.x", info.eyebrow_x),
+            ("eyebrow.y", info.eyebrow_y),
+            ("nose.style", info.nose_type),
+            ("nose.scale", info.nose_scale),
+            ("nose.y", info.nose_y),
+            ("lip.style", info.mouth_type),
+            ("lip.scale", info.mouth_scale),
+            ("lip.y_scale", info.mouth_aspect),
+            ("lip.y", info.mouth_y),
+            ("mustache.style", info.mustache_type),
+            ("mustache.scale", info.mustache_scale),
+            ("mustache.y", info.mustache_y),
+            ("beard.style", info.beard_type),
+            ("glass.style", info.glass_type),
+            ("glass.scale", info.glass_scale),
+            ("glass.y", info.glass_y),
+            ("mole.style", info.mole_type),
+            ("mole.scale", info.mole_scale),
+            ("mole.x", info.mole_x),
+            ("mole.y", info.mole_y),
+        ];
+        for &(field, value) in fields.iter() {
+            let from = u32::from(value);
+            let param = schema::param(field).unwrap();
+            let is_style =
+                field.ends_with(".style") || field == "face.wrinkle" || field == "face.makeup";
+            let in_asset = !is_style || asset.map_or(true, |a| asset_has_style(a, field, from));
+            let to = if param.contains(from) && in_asset {
+                from
+            } else if is_style || field == "face.color" {
+                defaults.get_field(field).unwrap()
+            } else {
+                from.clamp(param.min, param.max)
+            };
+            main.set_field(field, to);
+            if to != from {
+                approximations.push(Approximation {
+                    field,
+                    from,
+                    to: Some(to),
+                });
+            }
+        }
+
+        let colors: [(&str, u8, &[u8], &ColorTable); 6] = [
+            (
+                "hair.color",
+                info.hair_color,
+                &SWITCH_HAIR_COLORS,
+                &HAIR_COLOR_TABLE,
+            ),
+            (
+                "eyebrow.color",
+                info.eyebrow_color,
+                &SWITCH_HAIR_COLORS,
+                &HAIR_COLOR_TABLE,
+            ),
+            (
+                "beard.color",
+                info.beard_color,
+                &SWITCH_HAIR_COLORS,
+                &HAIR_COLOR_TABLE,
+            ),
+            (
+                "eye.color",
+                info.eye_color,
+                &SWITCH_EYE_COLORS,
+                &EYE_COLOR_TABLE,
+            ),
+            (
+                "lip.color",
+                info.mouth_color,
+                &SWITCH_LIP_COLORS,
+                &LIP_COLOR_TABLE,
+            ),
+            (
+                "glass.color",
+                info.glass_color,
+                &SWITCH_GLASS_COLORS,
+                &GLASS_COLOR_TABLE,
+            ),
+        ];
+        for &(field, color, switch_colors, table) in colors.iter() {
+            let exact = switch_colors.iter().position(|&c| c == color);
+            let to = match (exact, switch_color(color)) {
+                (Some(index), _) => index as u32,
+                (None, Some(rgb)) => nearest_color(table, rgb) as u32,
+                (None, None) => defaults.get_field(field).unwrap(),
+            };
+            main.set_field(field, to);
+            if exact.is_none() {
+                approximations.push(Approximation {
+                    field,
+                    from: u32::from(color),
+                    to: Some(to),
+                });
+            }
+        }
+
+        Ok((ProfileFull::from_profile(main), approximations))
+    }
+}
+
+// A Wii Mii assembled by hand from the layout documented on WiiBrew, with each field set to a
+// distinct value
+#[cfg(test)]
+const RFL_FIXTURE: [u8; RFL_LEN] = [
+    0x4D, 0xCB, // female, March 14, favorite color 5, favorite
+    0x00, 0x41, 0x00, 0x6E, 0x00, 0x6E, 0x00, 0x00, 0x00, 0x00, // name "Ann"
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    0x40, 0x20, // height 64, weight 32
+    0x80, 0x00, 0x12, 0x34, // regular Mii ID, created at 0x1234
+    0xDE, 0xAD, 0xBE, 0xEF, // system ID
+    0x45, 0x04, // face shape 2, skin color 1, facial feature 4, mingle off
+    0x42, 0xA0, // hair 33, color 2, parted on the other side
+    0x39, 0x80, 0x68, 0xA2, // eyebrow 7, rotation 6, color 3, size 4, y 10, spacing 2
+    0x50, 0x8C, 0x26, 0x40, // eye 20, rotation 4, y 12, color 1, size 3, spacing 2
+    0x56, 0x48, // nose 5, size 6, y 9
+    0x52, 0xAD, // lip 10, color 1, size 5, y 13
+    0x18, 0x68, // glasses 1, color 4, size 3, y 8
+    0x9A, 0xCB, // mustache 2, beard 1, color 5, mustache size 6, mustache y 11
+    0xA5, 0x06, // mole on, size 4, y 20, x 3
+    0x00, 0x42, 0x00, 0x6F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // creator "Bo"
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+];
+
+#[test]
+fn convert_test() {
+    let fixture = ProfileFull::from_rfl(&RFL_FIXTURE[..]).unwrap();
+    let main = &fixture.main;
+    assert_eq!(name_to_string(&main.name), "Ann");
+    assert_eq!(name_to_string(&fixture.author), "Bo");
+    assert_eq!(main.id.low.creation_date, 0x1234);
+    assert_eq!(main.id.mac, [0, 0, 0xDE, 0xAD, 0xBE, 0xEF]);
+    let expected: &[(&str, u32)] = &[
+        ("sex", 1),
+        ("birth_month", 3),
+        ("birth_day", 14),
+        ("favorite_color", 5),
+        ("favorite", 1),
+        ("height", 64),
+        ("width", 32),
+        ("disable_sharing", 1),
+        ("face.style", 2),
+        ("face.color", 1),
+        ("face.wrinkle", 5),
+        ("face.makeup", 0),
+        ("hair.style", 33),
+        ("hair.color", 2),
+        ("hair.flip", 1),
+        ("eyebrow.style", 7),
+        ("eyebrow.rotation", 6),
+        ("eyebrow.color", 3),
+        ("eyebrow.scale", 4),
+        ("eyebrow.y", 10),
+        ("eyebrow.x", 2),
+        ("eye.style", 20),
+        ("eye.rotation", 4),
+        ("eye.y", 12),
+        ("eye.color", 1),
+        ("eye.scale", 3),
+        ("eye.x", 2),
+        ("nose.style", 5),
+        ("nose.scale", 6),
+        ("nose.y", 9),
+        ("lip.style", 10),
+        ("lip.color", 1),
+        ("lip.scale", 5),
+        ("lip.y", 13),
+        ("glass.style", 1),
+        ("glass.color", 4),
+        ("glass.scale", 3),
+        ("glass.y", 8),
+        ("mustache.style", 2),
+        ("beard.style", 1),
+        ("beard.color", 5),
+        ("mustache.scale", 6),
+        ("mustache.y", 11),
+        ("mole.style", 1),
+        ("mole.scale", 4),
+        ("mole.y", 20),
+        ("mole.x", 3),
+    ];
+    for &(field, value) in expected {
+        assert_eq!(main.get_field(field), Some(value), "{}", field);
+    }
+    // Everything in the fixture is known, so it is written back unchanged
+    let (data, approximations) = fixture.to_rfl();
+    assert!(approximations.is_empty());
+    assert_eq!(data, RFL_FIXTURE.to_vec());
+
+    // The flags of special (0x0) and foreign (0xC) Miis are kept too
+    for &flags in &[0x00, 0x10, 0xC0, 0xF0] {
+        let mut special = RFL_FIXTURE;
+        special[0x18] = flags;
+        let profile = ProfileFull::from_rfl(&special[..]).unwrap();
+        assert_eq!(profile.main.id.low.creation_date, 0x1234);
+        assert_eq!(profile.to_rfl().0, special.to_vec());
+    }
+
+    let time = chrono::NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0);
+    let mut profile = ProfileFull::from_profile(Profile::new([0, 0, 1, 2, 3, 4], [0; 8], time, 0));
+    profile.main.name = name_from_str("Tester");
+    profile.main.general.sex = 1;
+    profile.main.hair.style = 40;
+    profile.main.eye.rotation = 5;
+    profile.main.face.wrinkle = 3;
+    profile.main.mole.style = 1;
+    profile.main.mole.y = 30;
+
+    // Everything fits on the Wii
+    let (data, approximations) = profile.to_rfl();
+    assert_eq!(data.len(), RFL_LEN);
+    assert!(approximations.is_empty());
+    assert_eq!(data[0] & 0x40, 0x40);
+    let back = ProfileFull::from_rfl(&data[..]).unwrap();
+    assert_eq!(back.main.id, profile.main.id);
+    assert_eq!(name_to_string(&back.main.name), "Tester");
+    for field in PROFILE_FIELDS {
+        assert_eq!(back.main.get_field(field), profile.main.get_field(field));
+    }
+
+    // 3DS-only parts
+    profile.main.hair.style = 100;
+    profile.main.lip.color = 3;
+    profile.main.eye.y_scale = 2;
+    profile.main.face.wrinkle = 5;
+    profile.main.face.makeup = 1;
+    let (data, approximations) = profile.to_rfl();
+    assert!(approximations.contains(&Approximation {
+        field: "hair.style",
+        from: 100,
+        to: Some(12)
+    }));
+    assert!(approximations.contains(&Approximation {
+        field: "lip.color",
+        from: 3,
+        to: Some(2)
+    }));
+    assert!(approximations.contains(&Approximation {
+        field: "eye.y_scale",
+        from: 2,
+        to: None
+    }));
+    assert!(approximations.contains(&Approximation {
+        field: "face.wrinkle",
+        from: 5,
+        to: Some(0)
+    }));
+    assert_eq!(approximations.len(), 4);
+    let back = ProfileFull::from_rfl(&data[..]).unwrap();
+    assert_eq!(back.main.hair.style, 12);
+    assert_eq!(back.main.face.makeup, 1);
+
+    assert!(matches!(
+        ProfileFull::from_rfl(&[0; RFL_LEN]),
+        Err(Error::NullProfile)
+    ));
+    assert!(matches!(
+        ProfileFull::from_rfl(&data[1..]),
+        Err(Error::BadProfileSize { .. })
+    ));
+
+    // Switch
+    let mut info = CharInfo {
+        create_id: [7; 16],
+        gender: 1,
+        faceline_color: 8,
+        hair_type: 40,
+        hair_color: 8,
+        eye_color: 14,
+        mouth_color: 50,
+        glass_type: 12,
+        ..CharInfo::default()
+    };
+    info.nickname[0..6].copy_from_slice(&name_from_str("Switch")[0..6]);
+    let mut data = [0; CHARINFO_LEN];
+    info.write_bytes(&mut data[..]);
+    let (profile, approximations) =
+        ProfileFull::from_charinfo(&data[..], None::<&DecodedAsset>).unwrap();
+    assert_eq!(name_to_string(&profile.main.name), "Switch");
+    assert_eq!(profile.main.general.sex, 1);
+    assert_eq!(profile.main.hair.style, 40);
+    assert_eq!(profile.main.hair.color, 0);
+    assert_eq!(profile.main.face.color, 0);
+    // The brown of the glasses is closest to the brown of the eyes
+    assert_eq!(profile.main.eye.color, 2);
+    assert_eq!(profile.main.glass.style, 0);
+    let fields: Vec<_> = approximations.iter().map(|a| a.field).collect();
+    assert!(fields.contains(&"face.color"));
+    assert!(fields.contains(&"eye.color"));
+    assert!(fields.contains(&"lip.color"));
+    assert!(fields.contains(&"glass.style"));
+    assert!(!fields.contains(&"hair.color"));
+}
@@ -0,0 +1,161 @@
+// Locates the Mii database and the Mii Maker assets inside the user folder of Citra-based
+// emulators. The console ID folders are found by scanning instead of assuming the all-zero IDs.
+
+use std::path::{Path, PathBuf};
+
+const ASSET_CONTENT_PATH: &str = "title/0004009b/00010202/content";
+const ASSET_FILE_NAMES: [&str; 2] = ["00000000.app.romfs", "00000000.app"];
+const DATABASE_PATH: &str = "extdata/00048000/F000000B/user/CFL_DB.dat";
+
+// What was found in a user folder. Files are in order of preference.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UserFolder {
+    pub path: PathBuf,
+    pub databases: Vec<PathBuf>,
+    pub assets: Vec<PathBuf>,
+}
+
+impl UserFolder {
+    pub fn is_empty(&self) -> bool {
+        self.databases.is_empty() && self.assets.is_empty()
+    }
+}
+
+// Console ID folders are named with 32 hexadecimal digits. The all-zero ID used by the
+// emulators by default comes first.
+fn id_folders(parent: &Path) -> Vec<PathBuf> {
+    let mut result: Vec<PathBuf> = match std::fs::read_dir(parent) {
+        Err(_) => return vec![],
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                let name = e.file_name();
+                let name = name.to_string_lossy();
+                name.len() == 32 && name.chars().all(|c| c.is_ascii_hexdigit())
+            })
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect(),
+    };
+    result.sort_by_key(|p| {
+        let name = p.file_name().unwrap().to_string_lossy().to_string();
+        (name.chars().any(|c| c != '0'), name)
+    });
+    result
+}
+
+pub fn scan_user_folder(path: &Path) -> UserFolder {
+    let nand = path.join("nand");
+    let mut assets = vec![];
+    for id in id_folders(&nand) {
+        for name in ASSET_FILE_NAMES.iter() {
+            let file = id.join(ASSET_CONTENT_PATH).join(name);
+            if file.is_file() {
+                assets.push(file);
+            }
+        }
+    }
+    let databases = id_folders(&nand.join("data"))
+        .into_iter()
+        .map(|id| id.join(DATABASE_PATH))
+        .filter(|file| file.is_file())
+        .collect();
+    UserFolder {
+        path: path.to_path_buf(),
+        databases,
+        assets,
+    }
+}
+
+// The standard user folders of Citra and Lime3DS, including Flatpak installations, whether or
+// not they exist.
+#[cfg(target_os = "linux")]
+pub fn standard_user_folders() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| home.as_ref().map(|h| h.join(".local/share")));
+    let mut result = vec![];
+    if let Some(data_home) = data_home {
+        result.push(data_home.join("citra-emu"));
+        result.push(data_home.join("lime3ds-emu"));
+    }
+    if let Some(home) = home {
+        result.push(home.join(".var/app/org.citra_emu.citra/data/citra-emu"));
+        result.push(home.join(".var/app/io.github.lime3ds.Lime3DS/data/lime3ds-emu"));
+    }
+    result
+}
+
+#[cfg(target_os = "windows")]
+pub fn standard_user_folders() -> Vec<PathBuf> {
+    match std::env::var_os("APPDATA").map(PathBuf::from) {
+        Some(app_data) => vec![app_data.join("Citra"), app_data.join("Lime3DS")],
+        None => vec![],
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn standard_user_folders() -> Vec<PathBuf> {
+    match std::env::var_os("HOME").map(PathBuf::from) {
+        Some(home) => {
+            let support = home.join("Library/Application Support");
+            vec![support.join("Citra"), support.join("Lime3DS")]
+        }
+        None => vec![],
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+pub fn standard_user_folders() -> Vec<PathBuf> {
+    vec![]
+}
+
+// Scans the standard user folders and returns those containing a database or assets
+pub fn discover_user_folders() -> Vec<UserFolder> {
+    standard_user_folders()
+        .iter()
+        .map(|p| scan_user_folder(p))
+        .filter(|f| !f.is_empty())
+        .collect()
+}
+
+#[test]
+fn scan_test() {
+    let root = std::env::temp_dir().join(format!("citrii-scan-test-{}", std::process::id()));
+    let zero = "0".repeat(32);
+    let other = "0123456789abcdef0123456789ABCDEF";
+    let create = |path: PathBuf| {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, b"").unwrap();
+    };
+    create(root.join("nand/data").join(other).join(DATABASE_PATH));
+    create(root.join("nand/data").join(&zero).join(DATABASE_PATH));
+    create(
+        root.join("nand")
+            .join(other)
+            .join(ASSET_CONTENT_PATH)
+            .join("00000000.app"),
+    );
+    std::fs::create_dir_all(root.join("nand/not-an-id")).unwrap();
+
+    let folder = scan_user_folder(&root);
+    assert_eq!(
+        folder.databases,
+        vec![
+            root.join("nand/data").join(&zero).join(DATABASE_PATH),
+            root.join("nand/data").join(other).join(DATABASE_PATH)
+        ]
+    );
+    assert_eq!(
+        folder.assets,
+        vec![root
+            .join("nand")
+            .join(other)
+            .join(ASSET_CONTENT_PATH)
+            .join("00000000.app")]
+    );
+    assert!(scan_user_folder(&root.join("nand")).is_empty());
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -1,10 +1,13 @@
 use crate::color::*;
+use crate::crc;
+use crate::error::*;
 use crate::head_renderer;
+use crate::name::*;
 use byte_struct::*;
 use chrono::*;
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub ProfileHeader: u32 {
         pub three: 8, // always 3?
         pub allow_copying: 1,
@@ -22,7 +25,7 @@ bitfields!(
 );
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default, PartialEq)]
     pub ProfileIdLow: u32 {
         pub creation_date: 28,
         pub unknown: 1,
@@ -32,15 +35,26 @@ bitfields!(
     }
 );
 
-#[derive(ByteStruct, Debug, Default)]
+#[derive(ByteStruct, Clone, Debug, Default, PartialEq)]
 #[byte_struct_be]
 pub struct ProfileId {
     pub low: ProfileIdLow,
     pub mac: [u8; 6],
 }
 
+impl ProfileId {
+    pub fn is_null(&self) -> bool {
+        self.low.creation_date == 0
+            && self.low.unknown == 0
+            && self.low.temporary == 0
+            && self.low.ntr == 0
+            && self.low.normal == 0
+            && self.mac == [0; 6]
+    }
+}
+
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub ProfileGeneral: u16 { //?
         pub sex: 1,
         pub birth_month: 4,
@@ -52,7 +66,7 @@ bitfields!(
 );
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub ProfileFace: u16 { // 0x30
         pub disable_sharing: 1, // ?
         pub style: 4,
@@ -63,7 +77,7 @@ bitfields!(
 );
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub ProfileHair: u16 { // 0x32
         pub style: 8,
         pub color: 3,
@@ -73,7 +87,7 @@ bitfields!(
 );
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub ProfileEye: u32 { // 0x34
         pub style: 6,
         pub color: 3,
@@ -87,7 +101,7 @@ bitfields!(
 );
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub ProfileEyebrow: u32 { // 0x38
         pub style: 5,
         pub color: 3,
@@ -102,7 +116,7 @@ bitfields!(
 );
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub ProfileNose: u16 { // 0x3C
         pub style: 5,
         pub scale: 4,
@@ -112,7 +126,7 @@ bitfields!(
 );
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub ProfileLip: u16 { // 0x3E
         pub style: 6,
         pub color: 3,
@@ -122,7 +136,7 @@ bitfields!(
 );
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub ProfileMisc: u16 { // 0x40
         pub lip_y: 5,
         pub mustache_style: 3,
@@ -131,7 +145,7 @@ bitfields!(
 );
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub ProfileBeard: u16 { // 0x42
         pub style: 3,
         pub color: 3,
@@ -142,7 +156,7 @@ bitfields!(
 );
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub ProfileGlass: u16 { // 0x44
         pub style: 4,
         pub color: 3,
@@ -152,7 +166,7 @@ bitfields!(
 );
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub ProfileMole: u16 { // 0x46
         pub style: 1,
         pub scale: 4,
@@ -162,7 +176,7 @@ bitfields!(
     }
 );
 
-#[derive(ByteStruct, Debug, Default)]
+#[derive(ByteStruct, Clone, Debug, Default)]
 #[byte_struct_le]
 pub struct Profile {
     pub header: ProfileHeader,
@@ -186,6 +200,14 @@ pub struct Profile {
 }
 
 impl Profile {
+    pub fn try_get_slot(&self) -> Option<usize> {
+        if self.header.page < 10 && self.header.slot < 10 {
+            Some((self.header.page * 10 + self.header.slot) as usize)
+        } else {
+            None
+        }
+    }
+
     pub fn get_slot(&self) -> usize {
         assert!(self.header.page < 10);
         assert!(self.header.slot < 10);
@@ -199,12 +221,7 @@ impl Profile {
     }
 
     pub fn is_null(&self) -> bool {
-        self.id.low.creation_date == 0
-            && self.id.low.unknown == 0
-            && self.id.low.temporary == 0
-            && self.id.low.ntr == 0
-            && self.id.low.normal == 0
-            && self.id.mac == [0; 6]
+        self.id.is_null()
     }
 
     pub fn new(mac: [u8; 6], system_id: [u8; 8], time: NaiveDateTime, slot: usize) -> Profile {
@@ -319,14 +336,64 @@ impl Profile {
     }
 }
 
-#[derive(ByteStruct, Debug, Default)]
+#[derive(ByteStruct, Clone, Debug, Default)]
 #[byte_struct_le]
 pub struct ProfileFull {
     pub main: Profile,
     pub author: [u16; 10],
 }
 
-#[derive(ByteStruct, Debug, Default)]
+// Size of the standalone profile format (.cfsd), which is a ProfileFull followed by two bytes of
+// padding and a big-endian CRC16 of everything before it.
+pub const CFSD_LEN: usize = 0x60;
+
+impl ProfileFull {
+    pub fn from_profile(main: Profile) -> ProfileFull {
+        ProfileFull {
+            main,
+            author: [0; 10],
+        }
+    }
+
+    pub fn to_cfsd(&self) -> Vec<u8> {
+        let mut data = vec![0u8; CFSD_LEN];
+        self.write_bytes(&mut data[0..ProfileFull::BYTE_LEN]);
+        let crc = crc::crc16_ninty(&data[0..CFSD_LEN - 2]).to_be_bytes();
+        data[CFSD_LEN - 2..CFSD_LEN].copy_from_slice(&crc);
+        data
+    }
+
+    // Accepts both the .cfsd format and bare profile data without the CRC.
+    pub fn from_cfsd(data: &[u8]) -> Result<ProfileFull, Error> {
+        match data.len() {
+            CFSD_LEN => {
+                let expected = crc::crc16_ninty(&data[0..CFSD_LEN - 2]);
+                let found = u16::from_be_bytes([data[CFSD_LEN - 2], data[CFSD_LEN - 1]]);
+                if expected != found {
+                    return Err(Error::BadCrc {
+                        region: CrcRegion::Profile,
+                        expected,
+                        found,
+                    });
+                }
+            }
+            ProfileFull::BYTE_LEN => (),
+            found => {
+                return Err(Error::BadProfileSize {
+                    expected: CFSD_LEN,
+                    found,
+                })
+            }
+        }
+        let profile = ProfileFull::read_bytes(&data[0..ProfileFull::BYTE_LEN]);
+        if profile.main.is_null() {
+            return Err(Error::NullProfile);
+        }
+        Ok(profile)
+    }
+}
+
+#[derive(ByteStruct, Clone, Debug, Default)]
 #[byte_struct_le]
 pub struct ProfileAlt {
     pub main: Profile,
@@ -334,6 +401,143 @@ pub struct ProfileAlt {
     pub unk: [u8; 8],
 }
 
+pub fn name_to_string(name: &[u16]) -> String {
+    let end = name.iter().position(|&v| v == 0).unwrap_or(name.len());
+    String::from_utf16_lossy(&name[0..end])
+}
+
+// Truncates without splitting a surrogate pair. See MiiName::parse for checking the result.
+pub fn name_from_str(s: &str) -> [u16; 10] {
+    let (name, _) = MiiName::encode(s);
+    name.0
+}
+
+macro_rules! profile_fields {
+    (
+        $fields:ident, $get:ident, $set:ident, $get_bits:ident;
+        $($name:literal => $($path:ident).+ : $t:ty [$bits:literal]),* $(,)?
+    ) => {
+        pub const $fields: &[&str] = &[$($name),*];
+
+        impl Profile {
+            pub fn $get(&self, name: &str) -> Option<u32> {
+                match name {
+                    $($name => Some(self.$($path).+ as u32),)*
+                    _ => None,
+                }
+            }
+
+            // Returns false if the field doesn't exist or the value doesn't fit in the field.
+            pub fn $set(&mut self, name: &str, value: u32) -> bool {
+                match name {
+                    $($name => {
+                        if u64::from(value) >= 1u64 << $bits {
+                            return false;
+                        }
+                        self.$($path).+ = value as $t;
+                        true
+                    })*
+                    _ => false,
+                }
+            }
+
+            // Number of bits of the field
+            pub fn $get_bits(name: &str) -> Option<u32> {
+                match name {
+                    $($name => Some($bits),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+profile_fields!(
+    PROFILE_FIELDS, get_field, set_field, field_bits;
+    "allow_copying" => header.allow_copying: u32 [1],
+    "private_name" => header.private_name: u32 [1],
+    "region_lock" => header.region_lock: u32 [2],
+    "char_set" => header.char_set: u32 [2],
+    "sex" => general.sex: u16 [1],
+    "birth_month" => general.birth_month: u16 [4],
+    "birth_day" => general.birth_day: u16 [5],
+    "favorite_color" => general.favorite_color: u16 [4],
+    "favorite" => general.favorite: u16 [1],
+    "height" => height: u8 [8],
+    "width" => width: u8 [8],
+    "disable_sharing" => face.disable_sharing: u16 [1],
+    "face.style" => face.style: u16 [4],
+    "face.color" => face.color: u16 [3],
+    "face.wrinkle" => face.wrinkle: u16 [4],
+    "face.makeup" => face.makeup: u16 [4],
+    "hair.style" => hair.style: u16 [8],
+    "hair.color" => hair.color: u16 [3],
+    "hair.flip" => hair.flip: u16 [1],
+    "eye.style" => eye.style: u32 [6],
+    "eye.color" => eye.color: u32 [3],
+    "eye.scale" => eye.scale: u32 [4],
+    "eye.y_scale" => eye.y_scale: u32 [3],
+    "eye.rotation" => eye.rotation: u32 [5],
+    "eye.x" => eye.x: u32 [4],
+    "eye.y" => eye.y: u32 [5],
+    "eyebrow.style" => eyebrow.style: u32 [5],
+    "eyebrow.color" => eyebrow.color: u32 [3],
+    "eyebrow.scale" => eyebrow.scale: u32 [4],
+    "eyebrow.y_scale" => eyebrow.y_scale: u32 [3],
+    "eyebrow.rotation" => eyebrow.rotation: u32 [5],
+    "eyebrow.x" => eyebrow.x: u32 [4],
+    "eyebrow.y" => eyebrow.y: u32 [5],
+    "nose.style" => nose.style: u16 [5],
+    "nose.scale" => nose.scale: u16 [4],
+    "nose.y" => nose.y: u16 [5],
+    "lip.style" => lip.style: u16 [6],
+    "lip.color" => lip.color: u16 [3],
+    "lip.scale" => lip.scale: u16 [4],
+    "lip.y_scale" => lip.y_scale: u16 [3],
+    "lip.y" => misc.lip_y: u16 [5],
+    "mustache.style" => misc.mustache_style: u16 [3],
+    "mustache.scale" => beard.mustache_scale: u16 [4],
+    "mustache.y" => beard.mustache_y: u16 [5],
+    "beard.style" => beard.style: u16 [3],
+    "beard.color" => beard.color: u16 [3],
+    "glass.style" => glass.style: u16 [4],
+    "glass.color" => glass.color: u16 [3],
+    "glass.scale" => glass.scale: u16 [4],
+    "glass.y" => glass.y: u16 [5],
+    "mole.style" => mole.style: u16 [1],
+    "mole.scale" => mole.scale: u16 [4],
+    "mole.x" => mole.x: u16 [5],
+    "mole.y" => mole.y: u16 [5],
+);
+
+// Fields that are not edited, including the padding. Together with PROFILE_FIELDS, the name, the
+// MAC address and the system ID, they cover every bit of a profile.
+profile_fields!(
+    PROFILE_INTERNAL_FIELDS, get_internal_field, set_internal_field, internal_field_bits;
+    "id.creation_date" => id.low.creation_date: u32 [28],
+    "id.unknown" => id.low.unknown: u32 [1],
+    "id.temporary" => id.low.temporary: u32 [1],
+    "id.ntr" => id.low.ntr: u32 [1],
+    "id.normal" => id.low.normal: u32 [1],
+    "internal.three" => header.three: u32 [8],
+    "internal.page" => header.page: u32 [4],
+    "internal.slot" => header.slot: u32 [4],
+    "internal.version_minor" => header.version_minor: u32 [4],
+    "internal.version_major" => header.version_major: u32 [3],
+    "internal.header_padding_a" => header.padding_a: u32 [2],
+    "internal.header_padding_b" => header.padding_b: u32 [1],
+    "internal.padding" => padding: u16 [16],
+    "internal.general_padding" => general.padding: u16 [1],
+    "internal.hair_padding" => hair.padding: u16 [4],
+    "internal.eye_padding" => eye.padding: u32 [2],
+    "internal.eyebrow_padding" => eyebrow.padding: u32 [1],
+    "internal.eyebrow_padding2" => eyebrow.padding2: u32 [2],
+    "internal.nose_padding" => nose.padding: u16 [2],
+    "internal.misc_padding" => misc.padding: u16 [8],
+    "internal.beard_padding" => beard.padding: u16 [1],
+    "internal.mole_padding" => mole.padding: u16 [1],
+);
+
 const EYE_ROTATION_OFFSETS: [u32; 62] = [
     3, 4, 4, 4, 3, 4, 4, 4, 3, 4, 4, 4, 4, 3, 3, 4, 4, 4, 3, 3, 4, 3, 4, 3, 3, 4, 3, 4, 4, 3, 4, 4,
     4, 3, 3, 3, 4, 4, 3, 3, 3, 4, 4, 3, 3, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 3, 4, 4, 3, 4, 4,
@@ -366,11 +570,7 @@ impl Profile {
             glass: self.glass.style as usize,
             eye: self.eye.style as usize,
             eyebrow: self.eyebrow.style as usize,
-            beard_plain: if self.beard.style > 3 {
-                self.beard.style - 3
-            } else {
-                0
-            } as usize,
+            beard_plain: self.beard.style.saturating_sub(3) as usize,
             wrinkle: self.face.wrinkle as usize,
             makeup: self.face.makeup as usize,
             mole: self.mole.style as usize,
@@ -434,7 +634,7 @@ impl Profile {
 }
 
 bitfields!(
-    #[derive(Debug, Default)]
+    #[derive(Clone, Debug, Default)]
     pub CFHEListNode: u32 {
         pub prev: 15,
         pub pf: 1,
@@ -443,46 +643,347 @@ bitfields!(
     }
 );
 
-#[derive(ByteStruct, Debug, Default)]
+#[derive(ByteStruct, Clone, Debug, Default)]
 #[byte_struct_le]
 pub struct CFHEObject {
     pub profile_id: ProfileId,
     pub list_node: CFHEListNode,
 }
 
-#[derive(ByteStruct)]
-#[byte_struct_le]
 pub struct Database {
     pub cfog: [u8; 4],
     pub magic: u32, // 0x00000100
-    pub owned: [ProfileFull; 100],
+    pub owned: Box<[ProfileFull; 100]>,
     pub cfhe: [u8; 4],
     pub cfhe_tail: u16,
     pub cfhe_head: u16,
-    pub cfhe_objects: [CFHEObject; 3000],
+    pub cfhe_objects: Box<[CFHEObject; 3000]>,
     pub unk: [u8; 0xE],
     pub crc_a: u16, // actually BE, but we access this field from raw bytes
     pub cfra: u32,
     pub invited_count: u32,
     pub invited_order: [u8; 100],
-    pub invited: [Profile; 100],
+    pub invited: Box<[Profile; 100]>,
     pub unk2: [u8; 0x12],
     pub crc_b: u16,
-    pub cfhe_profiles: [ProfileAlt; 3000],
+    pub cfhe_profiles: Box<[ProfileAlt; 3000]>,
 }
 
+// A profile of the owned, invited or encountered (CFHE) list, by its index in the array
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileRef {
+    Owned(usize),
+    Invited(usize),
+    Encountered(usize),
+}
+
+// The database is read and written field by field, as the profile lists are too large to be
+// built on the stack and are boxed instead
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read<T: ByteStructUnspecifiedByteOrder>(&mut self) -> T {
+        let value = T::read_bytes_default_le(&self.data[self.offset..self.offset + T::BYTE_LEN]);
+        self.offset += T::BYTE_LEN;
+        value
+    }
+
+    fn read_array<T: ByteStructUnspecifiedByteOrder, const N: usize>(&mut self) -> Box<[T; N]> {
+        let list: Box<[T]> = (0..N).map(|_| self.read()).collect();
+        match std::convert::TryInto::try_into(list) {
+            Ok(array) => array,
+            Err(_) => unreachable!(),
+        }
+    }
+}
+
+struct Writer<'a> {
+    data: &'a mut [u8],
+    offset: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn write<T: ByteStructUnspecifiedByteOrder>(&mut self, value: &T) {
+        value.write_bytes_default_le(&mut self.data[self.offset..self.offset + T::BYTE_LEN]);
+        self.offset += T::BYTE_LEN;
+    }
+
+    fn write_array<T: ByteStructUnspecifiedByteOrder>(&mut self, list: &[T]) {
+        for value in list {
+            self.write(value);
+        }
+    }
+}
+
+const CRC_A_END: usize = 0xC81E;
+const CRC_B_BEGIN: usize = 0xC820;
+const CRC_B_END: usize = 0xE4BE;
+
 impl Database {
+    pub const BYTE_LEN: usize = 4
+        + 4
+        + ProfileFull::BYTE_LEN * 100
+        + 4
+        + 2
+        + 2
+        + CFHEObject::BYTE_LEN * 3000
+        + 0xE
+        + 2
+        + 4
+        + 4
+        + 100
+        + Profile::BYTE_LEN * 100
+        + 0x12
+        + 2
+        + ProfileAlt::BYTE_LEN * 3000;
+
+    // Reads the database without verifying the CRCs. The size is still checked.
+    pub fn from_bytes_unchecked(data: &[u8]) -> Result<Database, Error> {
+        if data.len() != Database::BYTE_LEN {
+            return Err(Error::BadDatabaseSize {
+                expected: Database::BYTE_LEN,
+                found: data.len(),
+            });
+        }
+        let mut reader = Reader { data, offset: 0 };
+        let database = Database {
+            cfog: reader.read(),
+            magic: reader.read(),
+            owned: reader.read_array(),
+            cfhe: reader.read(),
+            cfhe_tail: reader.read(),
+            cfhe_head: reader.read(),
+            cfhe_objects: reader.read_array(),
+            unk: reader.read(),
+            crc_a: reader.read(),
+            cfra: reader.read(),
+            invited_count: reader.read(),
+            invited_order: reader.read(),
+            invited: reader.read_array(),
+            unk2: reader.read(),
+            crc_b: reader.read(),
+            cfhe_profiles: reader.read_array(),
+        };
+        assert_eq!(reader.offset, Database::BYTE_LEN);
+        Ok(database)
+    }
+
+    fn write_bytes(&self, data: &mut [u8]) {
+        let mut writer = Writer { data, offset: 0 };
+        writer.write(&self.cfog);
+        writer.write(&self.magic);
+        writer.write_array(&self.owned[..]);
+        writer.write(&self.cfhe);
+        writer.write(&self.cfhe_tail);
+        writer.write(&self.cfhe_head);
+        writer.write_array(&self.cfhe_objects[..]);
+        writer.write(&self.unk);
+        writer.write(&self.crc_a);
+        writer.write(&self.cfra);
+        writer.write(&self.invited_count);
+        writer.write(&self.invited_order);
+        writer.write_array(&self.invited[..]);
+        writer.write(&self.unk2);
+        writer.write(&self.crc_b);
+        writer.write_array(&self.cfhe_profiles[..]);
+        assert_eq!(writer.offset, Database::BYTE_LEN);
+    }
+
+    // Returns an Error::BadCrc for each region whose stored CRC doesn't match its content.
+    // `data` must be a full database.
+    pub fn crc_mismatches(data: &[u8]) -> Vec<Error> {
+        let mut result = vec![];
+        for (region, begin, end) in [
+            (CrcRegion::Owned, 0, CRC_A_END),
+            (CrcRegion::Invited, CRC_B_BEGIN, CRC_B_END),
+        ] {
+            let expected = crc::crc16_ninty(&data[begin..end]);
+            let found = u16::from_be_bytes([data[end], data[end + 1]]);
+            if expected != found {
+                result.push(Error::BadCrc {
+                    region,
+                    expected,
+                    found,
+                });
+            }
+        }
+        result
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Database, Error> {
+        let database = Database::from_bytes_unchecked(data)?;
+        if let Some(e) = Database::crc_mismatches(data).into_iter().next() {
+            return Err(e);
+        }
+        Ok(database)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Database::BYTE_LEN];
+        self.write_bytes(&mut data[..]);
+        let crc_a = crc::crc16_ninty(&data[0..CRC_A_END]).to_be_bytes();
+        data[CRC_A_END..CRC_A_END + 2].copy_from_slice(&crc_a);
+        let crc_b = crc::crc16_ninty(&data[CRC_B_BEGIN..CRC_B_END]).to_be_bytes();
+        data[CRC_B_END..CRC_B_END + 2].copy_from_slice(&crc_b);
+        data
+    }
+
+    pub fn profile(&self, profile: ProfileRef) -> &Profile {
+        match profile {
+            ProfileRef::Owned(index) => &self.owned[index].main,
+            ProfileRef::Invited(index) => &self.invited[index],
+            ProfileRef::Encountered(node) => &self.cfhe_profiles[node].main,
+        }
+    }
+
+    pub fn profile_mut(&mut self, profile: ProfileRef) -> &mut Profile {
+        match profile {
+            ProfileRef::Owned(index) => &mut self.owned[index].main,
+            ProfileRef::Invited(index) => &mut self.invited[index],
+            ProfileRef::Encountered(node) => &mut self.cfhe_profiles[node].main,
+        }
+    }
+
+    // Walks the CFHE list from cfhe_head, stopping at the first missing or invalid link.
+    pub fn cfhe_order(&self) -> Vec<usize> {
+        let mut order = vec![];
+        let mut visited = vec![false; self.cfhe_objects.len()];
+        let mut current = self.cfhe_head as usize;
+        while current < self.cfhe_objects.len()
+            && !visited[current]
+            && !self.cfhe_objects[current].profile_id.is_null()
+        {
+            visited[current] = true;
+            order.push(current);
+            let node = &self.cfhe_objects[current].list_node;
+            if node.nf == 0 {
+                break;
+            }
+            current = node.next as usize;
+        }
+        order
+    }
+
     pub fn owned_slot_to_index(&self, slot: usize) -> Option<usize> {
         for (i, profile) in self.owned.iter().enumerate() {
-            if !profile.main.is_null() && profile.main.get_slot() == slot {
+            if !profile.main.is_null() && profile.main.try_get_slot() == Some(slot) {
                 return Some(i);
             }
         }
         None
     }
+
+    // Puts the profile into `slot`, or the first free slot if not specified, and returns the slot.
+    pub fn import_owned(
+        &mut self,
+        mut profile: ProfileFull,
+        slot: Option<usize>,
+    ) -> Result<usize, Error> {
+        if let Some(existing) = self
+            .owned
+            .iter()
+            .find(|existing| !existing.main.is_null() && existing.main.id == profile.main.id)
+        {
+            return Err(Error::DuplicateProfile {
+                slot: existing.main.try_get_slot(),
+            });
+        }
+        let slot = match slot {
+            Some(slot) => {
+                if self.owned_slot_to_index(slot).is_some() {
+                    return Err(Error::SlotOccupied(slot));
+                }
+                slot
+            }
+            None => self.free_owned_slot().ok_or(Error::NoFreeSlot)?,
+        };
+        let index = self.free_owned_index().ok_or(Error::NoFreeSlot)?;
+        profile.main.set_slot(slot);
+        self.owned[index] = profile;
+        Ok(slot)
+    }
+
+    pub fn free_owned_index(&self) -> Option<usize> {
+        self.owned.iter().position(|profile| profile.main.is_null())
+    }
+
+    pub fn free_owned_slot(&self) -> Option<usize> {
+        (0..100).find(|&slot| self.owned_slot_to_index(slot).is_none())
+    }
+
+    // Moves the profile in `slot` to `other_slot`, and the profile previously there (if any) to
+    // `slot`.
+    pub fn swap_owned_slots(&mut self, slot: usize, other_slot: usize) {
+        let this = self.owned_slot_to_index(slot);
+        let other = self.owned_slot_to_index(other_slot);
+        if let Some(i) = this {
+            self.owned[i].main.set_slot(other_slot);
+        }
+        if let Some(i) = other {
+            self.owned[i].main.set_slot(slot);
+        }
+    }
+}
+
+// An empty database for the tests
+#[cfg(test)]
+pub fn test_database() -> Database {
+    Database::from_bytes_unchecked(&vec![0u8; Database::BYTE_LEN]).unwrap()
+}
+
+// A profile for the tests. Profiles from different `console` values have different IDs, as do
+// profiles made on different days.
+#[cfg(test)]
+pub fn test_profile(console: u8, day: i64, slot: usize) -> Profile {
+    let time = NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0) + Duration::days(day);
+    Profile::new([console; 6], [console + 1; 8], time, slot)
 }
 
 #[test]
 fn struct_size_test() {
     assert_eq!(Database::BYTE_LEN, 0x4BD20);
 }
+
+#[test]
+fn database_round_trip_test() {
+    // Every byte differs from its neighbors, so a field read from the wrong offset is noticed
+    let mut data: Vec<u8> = (0..Database::BYTE_LEN).map(|i| (i % 251) as u8).collect();
+    data[0..8].copy_from_slice(b"CFOG\x00\x01\x00\x00");
+    assert!(matches!(
+        Database::from_bytes(&data[..]),
+        Err(Error::BadCrc {
+            region: CrcRegion::Owned,
+            ..
+        })
+    ));
+    let crc_a = crc::crc16_ninty(&data[0..CRC_A_END]).to_be_bytes();
+    data[CRC_A_END..CRC_A_END + 2].copy_from_slice(&crc_a);
+    let crc_b = crc::crc16_ninty(&data[CRC_B_BEGIN..CRC_B_END]).to_be_bytes();
+    data[CRC_B_END..CRC_B_END + 2].copy_from_slice(&crc_b);
+    let database = Database::from_bytes(&data[..]).unwrap();
+    assert_eq!(database.to_bytes(), data);
+}
+
+#[test]
+fn cfsd_round_trip_test() {
+    let time = NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0);
+    let mut profile = ProfileFull::from_profile(Profile::new([1; 6], [2; 8], time, 5));
+    profile.author = name_from_str("author");
+    let mut data = profile.to_cfsd();
+    assert_eq!(data.len(), CFSD_LEN);
+    let decoded = ProfileFull::from_cfsd(&data[..]).unwrap();
+    assert_eq!(decoded.main.id, profile.main.id);
+    assert_eq!(name_to_string(&decoded.author), "author");
+    assert!(ProfileFull::from_cfsd(&data[0..ProfileFull::BYTE_LEN]).is_ok());
+    data[0x20] ^= 1;
+    assert!(matches!(
+        ProfileFull::from_cfsd(&data[..]),
+        Err(Error::BadCrc {
+            region: CrcRegion::Profile,
+            ..
+        })
+    ));
+}
@@ -0,0 +1,803 @@
+// Comparison and three-way merge of databases. Profiles are matched by their ProfileId within the
+// owned, invited and encountered lists, so that a moved profile shows up as a change of its slot
+// or position instead of a removal and an addition. In the invited and encountered lists, only
+// the profiles out of the longest common subsequence of the two orders are reported as moved, so
+// that adding, removing or moving one profile doesn't move the others.
+
+use crate::cfhe;
+use crate::database::*;
+use crate::error::*;
+use crate::profile_text;
+use byte_struct::*;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Owned,
+    Invited,
+    Encountered,
+}
+
+const SECTIONS: [Section; 3] = [Section::Owned, Section::Invited, Section::Encountered];
+
+impl std::fmt::Display for Section {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Section::Owned => write!(f, "owned"),
+            Section::Invited => write!(f, "invited"),
+            Section::Encountered => write!(f, "encountered"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Removed,
+    Modified(Vec<FieldChange>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileChange {
+    pub section: Section,
+    pub id: String,
+    pub name: String,
+    pub change: Change,
+}
+
+impl std::fmt::Display for ProfileChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let sign = match self.change {
+            Change::Added => '+',
+            Change::Removed => '-',
+            Change::Modified(_) => '~',
+        };
+        write!(f, "{} {} {} ({})", sign, self.section, self.name, self.id)?;
+        if let Change::Modified(fields) = &self.change {
+            for field in fields {
+                write!(f, "\n    {}: {} -> {}", field.field, field.old, field.new)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    // Both sides changed a field to different values
+    Field {
+        section: Section,
+        id: String,
+        name: String,
+        field: &'static str,
+        ours: String,
+        theirs: String,
+    },
+    // One side removed a profile that the other side changed
+    RemovedAndModified {
+        section: Section,
+        id: String,
+        name: String,
+        removed_by: Side,
+    },
+    // The slot of an owned profile was already taken by another profile
+    SlotTaken {
+        id: String,
+        name: String,
+        slot: usize,
+        moved_to: usize,
+    },
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Conflict::Field {
+                section,
+                id,
+                name,
+                field,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "{} {} ({}) has {} {} in ours and {} in theirs",
+                section, name, id, field, ours, theirs
+            ),
+            Conflict::RemovedAndModified {
+                section,
+                id,
+                name,
+                removed_by,
+            } => write!(
+                f,
+                "{} {} ({}) is removed in {} but changed in {}",
+                section,
+                name,
+                id,
+                if *removed_by == Side::Ours {
+                    "ours"
+                } else {
+                    "theirs"
+                },
+                if *removed_by == Side::Ours {
+                    "theirs"
+                } else {
+                    "ours"
+                }
+            ),
+            Conflict::SlotTaken {
+                id,
+                name,
+                slot,
+                moved_to,
+            } => write!(
+                f,
+                "owned {} ({}) is moved to slot {} as slot {} is taken",
+                name, id, moved_to, slot
+            ),
+        }
+    }
+}
+
+// A profile of a list together with where it is
+#[derive(Clone)]
+struct Entry {
+    index: usize,
+    position: usize,
+    profile: ProfileFull,
+    timestamp: u32,
+    unknown: [u8; 8],
+}
+
+impl Entry {
+    fn id(&self) -> String {
+        let id = &self.profile.main.id;
+        let mac: Vec<String> = id.mac.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("{:07X}-{}", id.low.creation_date, mac.join(""))
+    }
+
+    fn name(&self) -> String {
+        name_to_string(&self.profile.main.name)
+    }
+}
+
+// Everything compared and merged in an entry. The slot of owned profiles is the page and slot of
+// the header, while the position in the other lists is that of the list order.
+#[derive(Clone, Copy)]
+enum Field {
+    Slot,
+    Position,
+    Name,
+    Author,
+    Profile(&'static str),
+    Internal(&'static str),
+    Mac,
+    SystemId,
+    Timestamp,
+    Unknown,
+}
+
+fn fields(section: Section) -> Vec<Field> {
+    let mut result = vec![];
+    if section == Section::Owned {
+        result.extend_from_slice(&[Field::Slot, Field::Name, Field::Author]);
+    } else {
+        result.extend_from_slice(&[Field::Position, Field::Name]);
+    }
+    result.extend(PROFILE_FIELDS.iter().map(|&f| Field::Profile(f)));
+    result.extend(
+        PROFILE_INTERNAL_FIELDS
+            .iter()
+            .filter(|&&f| {
+                section != Section::Owned || (f != "internal.page" && f != "internal.slot")
+            })
+            .map(|&f| Field::Internal(f)),
+    );
+    result.extend_from_slice(&[Field::Mac, Field::SystemId]);
+    if section == Section::Encountered {
+        result.extend_from_slice(&[Field::Timestamp, Field::Unknown]);
+    }
+    result
+}
+
+impl Field {
+    fn name(&self) -> &'static str {
+        match *self {
+            Field::Slot => "slot",
+            Field::Position => "position",
+            Field::Name => "name",
+            Field::Author => "author",
+            Field::Profile(field) | Field::Internal(field) => field,
+            Field::Mac => "id.mac",
+            Field::SystemId => "id.system_id",
+            Field::Timestamp => "timestamp",
+            Field::Unknown => "unknown",
+        }
+    }
+
+    fn get(&self, entry: &Entry) -> Vec<u32> {
+        let main = &entry.profile.main;
+        match *self {
+            Field::Slot => vec![main.header.page, main.header.slot],
+            Field::Position => vec![entry.position as u32],
+            Field::Name => main.name.iter().map(|&c| c as u32).collect(),
+            Field::Author => entry.profile.author.iter().map(|&c| c as u32).collect(),
+            Field::Profile(field) => vec![main.get_field(field).unwrap()],
+            Field::Internal(field) => vec![main.get_internal_field(field).unwrap()],
+            Field::Mac => main.id.mac.iter().map(|&b| b as u32).collect(),
+            Field::SystemId => main.system_id.iter().map(|&b| b as u32).collect(),
+            Field::Timestamp => vec![entry.timestamp],
+            Field::Unknown => entry.unknown.iter().map(|&b| b as u32).collect(),
+        }
+    }
+
+    fn set(&self, entry: &mut Entry, value: &[u32]) {
+        let main = &mut entry.profile.main;
+        match *self {
+            Field::Slot => {
+                main.header.page = value[0];
+                main.header.slot = value[1];
+            }
+            Field::Position => entry.position = value[0] as usize,
+            Field::Name => {
+                for (c, &v) in main.name.iter_mut().zip(value) {
+                    *c = v as u16;
+                }
+            }
+            Field::Author => {
+                for (c, &v) in entry.profile.author.iter_mut().zip(value) {
+                    *c = v as u16;
+                }
+            }
+            Field::Profile(field) => {
+                main.set_field(field, value[0]);
+            }
+            Field::Internal(field) => {
+                main.set_internal_field(field, value[0]);
+            }
+            Field::Mac => {
+                for (b, &v) in main.id.mac.iter_mut().zip(value) {
+                    *b = v as u8;
+                }
+            }
+            Field::SystemId => {
+                for (b, &v) in main.system_id.iter_mut().zip(value) {
+                    *b = v as u8;
+                }
+            }
+            Field::Timestamp => entry.timestamp = value[0],
+            Field::Unknown => {
+                for (b, &v) in entry.unknown.iter_mut().zip(value) {
+                    *b = v as u8;
+                }
+            }
+        }
+    }
+
+    fn display(&self, value: &[u32]) -> String {
+        let hex = |separator: &str| {
+            let bytes: Vec<String> = value.iter().map(|b| format!("{:02X}", b)).collect();
+            bytes.join(separator)
+        };
+        match *self {
+            Field::Slot => (value[0] * 10 + value[1]).to_string(),
+            Field::Name | Field::Author => {
+                let name: Vec<u16> = value.iter().map(|&c| c as u16).collect();
+                format!("\"{}\"", name_to_string(&name))
+            }
+            Field::Profile(field) => match profile_text::enum_names(field) {
+                Some(names) => names[value[0] as usize].to_string(),
+                None => value[0].to_string(),
+            },
+            Field::Mac => hex(":"),
+            Field::SystemId | Field::Unknown => hex(""),
+            Field::Timestamp => cfhe::timestamp_to_time(value[0])
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string(),
+            _ => value[0].to_string(),
+        }
+    }
+}
+
+// Profiles with the same ID in a list are told apart by their occurrence
+type Key = ([u8; 10], usize);
+
+struct List {
+    entries: Vec<(Key, Entry)>,
+    map: HashMap<Key, usize>,
+}
+
+impl List {
+    fn new(database: &Database, section: Section) -> List {
+        let entry = |index, position, profile| Entry {
+            index,
+            position,
+            profile,
+            timestamp: 0,
+            unknown: [0; 8],
+        };
+        let entries: Vec<Entry> = match section {
+            Section::Owned => database
+                .owned
+                .iter()
+                .enumerate()
+                .filter(|(_, profile)| !profile.main.is_null())
+                .map(|(i, profile)| entry(i, 0, profile.clone()))
+                .collect(),
+            Section::Invited => database
+                .invited_list()
+                .into_iter()
+                .enumerate()
+                .map(|(position, i)| {
+                    let profile = ProfileFull::from_profile(database.invited[i].clone());
+                    entry(i, position, profile)
+                })
+                .collect(),
+            Section::Encountered => database
+                .cfhe_order()
+                .into_iter()
+                .enumerate()
+                .map(|(position, node)| {
+                    let alt = &database.cfhe_profiles[node];
+                    Entry {
+                        timestamp: alt.timestamp,
+                        unknown: alt.unk,
+                        ..entry(node, position, ProfileFull::from_profile(alt.main.clone()))
+                    }
+                })
+                .collect(),
+        };
+
+        let mut occurrences: HashMap<[u8; 10], usize> = HashMap::new();
+        let mut list = List {
+            entries: vec![],
+            map: HashMap::new(),
+        };
+        for entry in entries {
+            let mut id = [0; 10];
+            entry.profile.main.id.write_bytes(&mut id[..]);
+            let occurrence = occurrences.entry(id).or_insert(0);
+            let key = (id, *occurrence);
+            *occurrence += 1;
+            list.map.insert(key, list.entries.len());
+            list.entries.push((key, entry));
+        }
+        list
+    }
+
+    fn get(&self, key: &Key) -> Option<&Entry> {
+        self.map.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    fn contains(&self, key: &Key) -> bool {
+        self.map.contains_key(key)
+    }
+
+    // Profiles that are also in `other` but out of the longest common subsequence of the orders
+    fn moved(&self, other: &List) -> HashSet<Key> {
+        let a: Vec<Key> = self
+            .entries
+            .iter()
+            .map(|(key, _)| *key)
+            .filter(|key| other.contains(key))
+            .collect();
+        let b: Vec<Key> = other
+            .entries
+            .iter()
+            .map(|(key, _)| *key)
+            .filter(|key| self.contains(key))
+            .collect();
+        // lengths[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+        let mut lengths = vec![vec![0u16; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lengths[i][j] = if a[i] == b[j] {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            }
+        }
+        let mut moved: HashSet<Key> = a.iter().copied().collect();
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() && j < b.len() {
+            if a[i] == b[j] {
+                moved.remove(&a[i]);
+                i += 1;
+                j += 1;
+            } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        moved
+    }
+}
+
+fn field_changes(section: Section, old: &Entry, new: &Entry, moved: bool) -> Vec<FieldChange> {
+    fields(section)
+        .into_iter()
+        .filter(|field| moved || !matches!(field, Field::Position))
+        .filter_map(|field| {
+            let (old, new) = (field.get(old), field.get(new));
+            if old == new {
+                return None;
+            }
+            Some(FieldChange {
+                field: field.name(),
+                old: field.display(&old),
+                new: field.display(&new),
+            })
+        })
+        .collect()
+}
+
+// Changes from `old` to `new`, in the order of the lists of `old` followed by the added profiles
+pub fn diff(old: &Database, new: &Database) -> Vec<ProfileChange> {
+    let mut result = vec![];
+    for &section in SECTIONS.iter() {
+        let old_list = List::new(old, section);
+        let new_list = List::new(new, section);
+        let moved = old_list.moved(&new_list);
+
+        let change = |entry: &Entry, change| ProfileChange {
+            section,
+            id: entry.id(),
+            name: entry.name(),
+            change,
+        };
+        for (key, old_entry) in &old_list.entries {
+            match new_list.get(key) {
+                None => result.push(change(old_entry, Change::Removed)),
+                Some(new_entry) => {
+                    let fields = field_changes(section, old_entry, new_entry, moved.contains(key));
+                    if !fields.is_empty() {
+                        result.push(change(new_entry, Change::Modified(fields)));
+                    }
+                }
+            }
+        }
+        for (key, new_entry) in &new_list.entries {
+            if !old_list.contains(key) {
+                result.push(change(new_entry, Change::Added));
+            }
+        }
+    }
+    result
+}
+
+// Merges the fields changed on either side. The order of the lists is merged separately.
+fn merge_entry(
+    section: Section,
+    base: Option<&Entry>,
+    ours: &Entry,
+    theirs: &Entry,
+    prefer: Side,
+    conflicts: &mut Vec<Conflict>,
+) -> Entry {
+    let mut result = ours.clone();
+    for field in fields(section) {
+        if matches!(field, Field::Position) {
+            continue;
+        }
+        let (our_value, their_value) = (field.get(ours), field.get(theirs));
+        let value = match base.map(|base| field.get(base)) {
+            _ if our_value == their_value => continue,
+            Some(base_value) if base_value == our_value => their_value,
+            Some(base_value) if base_value == their_value => continue,
+            _ => {
+                conflicts.push(Conflict::Field {
+                    section,
+                    id: ours.id(),
+                    name: ours.name(),
+                    field: field.name(),
+                    ours: field.display(&our_value),
+                    theirs: field.display(&their_value),
+                });
+                match prefer {
+                    Side::Ours => continue,
+                    Side::Theirs => their_value,
+                }
+            }
+        };
+        field.set(&mut result, &value);
+    }
+    result
+}
+
+// Whether anything but the position differs
+fn is_modified(section: Section, base: &Entry, entry: &Entry) -> bool {
+    fields(section)
+        .into_iter()
+        .filter(|field| !matches!(field, Field::Position))
+        .any(|field| field.get(base) != field.get(entry))
+}
+
+// Takes the preferred index, or the first free one
+fn claim_index(taken: &mut [bool], preferred: usize) -> Result<usize, Error> {
+    let index = if taken.get(preferred) == Some(&false) {
+        preferred
+    } else {
+        taken.iter().position(|&t| !t).ok_or(Error::NoFreeSlot)?
+    };
+    taken[index] = true;
+    Ok(index)
+}
+
+// Merges the changes from `base` to `ours` and from `base` to `theirs` into a copy of `ours`.
+// Conflicting changes are resolved in favor of `prefer` and returned. Profiles added on both sides
+// to the same owned slot keep the one from `ours` in the slot.
+pub fn merge(
+    base: &Database,
+    ours: &Database,
+    theirs: &Database,
+    prefer: Side,
+) -> Result<(Database, Vec<Conflict>), Error> {
+    let mut result = Database::from_bytes_unchecked(&ours.to_bytes())?;
+    let mut conflicts = vec![];
+    for &section in SECTIONS.iter() {
+        let base_list = List::new(base, section);
+        let our_list = List::new(ours, section);
+        let their_list = List::new(theirs, section);
+
+        let mut merged: HashMap<Key, Entry> = HashMap::new();
+        let keys = our_list
+            .entries
+            .iter()
+            .map(|(key, _)| (*key, Side::Ours))
+            .chain(
+                their_list
+                    .entries
+                    .iter()
+                    .filter(|(key, _)| !our_list.contains(key))
+                    .map(|(key, _)| (*key, Side::Theirs)),
+            );
+        for (key, side) in keys {
+            let base_entry = base_list.get(&key);
+            let entry = match (our_list.get(&key), their_list.get(&key)) {
+                (Some(o), Some(t)) => {
+                    merge_entry(section, base_entry, o, t, prefer, &mut conflicts)
+                }
+                (Some(entry), None) | (None, Some(entry)) => {
+                    let removed_by = if side == Side::Ours {
+                        Side::Theirs
+                    } else {
+                        Side::Ours
+                    };
+                    match base_entry {
+                        None => entry.clone(),
+                        Some(base_entry) if !is_modified(section, base_entry, entry) => continue,
+                        Some(_) => {
+                            conflicts.push(Conflict::RemovedAndModified {
+                                section,
+                                id: entry.id(),
+                                name: entry.name(),
+                                removed_by,
+                            });
+                            if prefer == removed_by {
+                                continue;
+                            }
+                            entry.clone()
+                        }
+                    }
+                }
+                (None, None) => unreachable!(),
+            };
+            merged.insert(key, entry);
+        }
+
+        // Profiles that keep their place on both sides are in the order of `base`. The others
+        // follow the profile before them on the side that moved or added them, and the preferred
+        // side is placed first when both did.
+        let sides = match prefer {
+            Side::Ours => [&our_list, &their_list],
+            Side::Theirs => [&their_list, &our_list],
+        };
+        let moved: Vec<HashSet<Key>> = sides.iter().map(|list| base_list.moved(list)).collect();
+        let mut order: Vec<Key> = base_list
+            .entries
+            .iter()
+            .map(|(key, _)| *key)
+            .filter(|key| merged.contains_key(key) && !moved.iter().any(|m| m.contains(key)))
+            .collect();
+        for (list, moved) in sides.iter().zip(&moved) {
+            let mut position = 0;
+            for (key, _) in &list.entries {
+                if !merged.contains_key(key) {
+                    continue;
+                }
+                match order.iter().position(|k| k == key) {
+                    Some(p) => position = p + 1,
+                    None if moved.contains(key) || !base_list.contains(key) => {
+                        order.insert(position, *key);
+                        position += 1;
+                    }
+                    // Placed by the other side
+                    None => (),
+                }
+            }
+        }
+        let merged: Vec<Entry> = order
+            .iter()
+            .map(|key| merged.remove(key).unwrap())
+            .collect();
+
+        match section {
+            Section::Owned => {
+                result
+                    .owned
+                    .iter_mut()
+                    .for_each(|p| *p = ProfileFull::default());
+                let mut taken_slots = [false; 100];
+                let mut taken_indices = [false; 100];
+                for mut entry in merged {
+                    let slot = entry.profile.main.try_get_slot().unwrap_or(100);
+                    let new_slot = claim_index(&mut taken_slots, slot)?;
+                    if new_slot != slot {
+                        conflicts.push(Conflict::SlotTaken {
+                            id: entry.id(),
+                            name: entry.name(),
+                            slot,
+                            moved_to: new_slot,
+                        });
+                        entry.profile.main.set_slot(new_slot);
+                    }
+                    let index = claim_index(&mut taken_indices, entry.index)?;
+                    result.owned[index] = entry.profile;
+                }
+            }
+            Section::Invited | Section::Encountered => {
+                let capacity = if section == Section::Invited {
+                    result.invited.len()
+                } else {
+                    result.cfhe_objects.len()
+                };
+                let mut taken = vec![false; capacity];
+                let mut order = vec![];
+                for entry in merged {
+                    let index = claim_index(&mut taken, entry.index)?;
+                    order.push((index, entry));
+                }
+                if section == Section::Invited {
+                    result
+                        .invited
+                        .iter_mut()
+                        .for_each(|p| *p = Profile::default());
+                    for (index, entry) in &order {
+                        result.invited[*index] = entry.profile.main.clone();
+                    }
+                    let list: Vec<usize> = order.iter().map(|(index, _)| *index).collect();
+                    result.set_invited_list(&list);
+                } else {
+                    result
+                        .cfhe_objects
+                        .iter_mut()
+                        .for_each(|o| *o = CFHEObject::default());
+                    result
+                        .cfhe_profiles
+                        .iter_mut()
+                        .for_each(|p| *p = ProfileAlt::default());
+                    for (node, entry) in &order {
+                        result.cfhe_objects[*node].profile_id = entry.profile.main.id.clone();
+                        result.cfhe_profiles[*node] = ProfileAlt {
+                            main: entry.profile.main.clone(),
+                            timestamp: entry.timestamp,
+                            unk: entry.unknown,
+                        };
+                    }
+                    let list: Vec<usize> = order.iter().map(|(node, _)| *node).collect();
+                    result.set_cfhe_order(&list);
+                }
+            }
+        }
+    }
+    Ok((result, conflicts))
+}
+
+#[test]
+fn diff_test() {
+    let mut base = test_database();
+    for i in 0..3 {
+        base.owned[i].main = test_profile(1, i as i64, i);
+    }
+    for &i in &[4, 6, 8] {
+        base.invited[i] = test_profile(3, i as i64, 0);
+    }
+    base.fix_invited_order();
+    let copy = |database: &Database| Database::from_bytes_unchecked(&database.to_bytes()).unwrap();
+
+    let mut ours = copy(&base);
+    ours.owned[1].main.eye.style = 10;
+    ours.owned[2].main.name = name_from_str("Ours");
+    ours.swap_owned_slots(2, 5);
+    ours.move_invited(8, 0).unwrap();
+    ours.import_owned(ProfileFull::from_profile(test_profile(5, 0, 0)), Some(7))
+        .unwrap();
+
+    let mut theirs = copy(&base);
+    theirs.owned[1].main.eye.style = 20;
+    theirs.owned[2].main.hair.style = 30;
+    theirs.remove_invited(6).unwrap();
+    theirs
+        .import_owned(ProfileFull::from_profile(test_profile(6, 0, 0)), Some(7))
+        .unwrap();
+
+    let changes = diff(&base, &ours);
+    assert_eq!(changes.len(), 4);
+    assert_eq!(
+        changes[1].change,
+        Change::Modified(vec![
+            FieldChange {
+                field: "slot",
+                old: "2".into(),
+                new: "5".into()
+            },
+            FieldChange {
+                field: "name",
+                old: "\"?\"".into(),
+                new: "\"Ours\"".into()
+            },
+        ])
+    );
+    assert_eq!(changes[2].change, Change::Added);
+    // Only the profile moved to the front is reported
+    assert_eq!(changes[3].section, Section::Invited);
+    assert_eq!(
+        changes[3].change,
+        Change::Modified(vec![FieldChange {
+            field: "position",
+            old: "2".into(),
+            new: "0".into()
+        }])
+    );
+    // Removing a profile doesn't move the others
+    let changes = diff(&base, &theirs);
+    assert_eq!(changes.len(), 4);
+    assert_eq!(changes[3].change, Change::Removed);
+
+    let (merged, conflicts) = merge(&base, &ours, &theirs, Side::Ours).unwrap();
+    assert_eq!(conflicts.len(), 2);
+    assert!(matches!(
+        conflicts[0],
+        Conflict::Field {
+            field: "eye.style",
+            ..
+        }
+    ));
+    assert!(matches!(
+        conflicts[1],
+        Conflict::SlotTaken {
+            slot: 7,
+            moved_to: 2,
+            ..
+        }
+    ));
+    assert_eq!(merged.owned[1].main.eye.style, 10);
+    let index = merged.owned_slot_to_index(5).unwrap();
+    assert_eq!(name_to_string(&merged.owned[index].main.name), "Ours");
+    assert_eq!(merged.owned[index].main.hair.style, 30);
+    assert_eq!(merged.invited_list(), vec![8, 4]);
+    assert!(merged.validate().is_empty());
+    let (merged, _) = merge(&base, &ours, &theirs, Side::Theirs).unwrap();
+    assert_eq!(merged.owned[1].main.eye.style, 20);
+
+    // Nothing is lost when merging with no change on one side
+    let (merged, conflicts) = merge(&base, &base, &ours, Side::Ours).unwrap();
+    assert!(conflicts.is_empty());
+    assert!(diff(&ours, &merged).is_empty());
+}
@@ -0,0 +1,189 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrcRegion {
+    Owned,   // CFOG and CFHE, covered by crc_a
+    Invited, // CFRA, covered by crc_b
+    Profile, // standalone profile file
+}
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    ImageError(image::ImageError),
+    BadDatabaseSize {
+        expected: usize,
+        found: usize,
+    },
+    BadCrc {
+        region: CrcRegion,
+        expected: u16,
+        found: u16,
+    },
+    BadProfileSize {
+        expected: usize,
+        found: usize,
+    },
+    NullProfile,
+    CopyingNotAllowed,
+    // The slot is None if the existing profile has an invalid slot
+    DuplicateProfile {
+        slot: Option<usize>,
+    },
+    SlotOccupied(usize),
+    NoFreeSlot,
+    RomfsCorrupt,
+    RomfsPathNotFound(String),
+    NcchCorrupt,
+    NcchEncrypted,
+    NcchSectionMissing(&'static str),
+    AssetHeaderCorrupt,
+    AssetSectionCorrupt {
+        section: usize,
+        item: Option<usize>,
+    },
+    AssetItemInvalid {
+        section: usize,
+        item: usize,
+    },
+    AssetSectionTooLarge(usize),
+    BadTextureSize {
+        width: u32,
+        height: u32,
+    },
+    ObjCorrupt(usize),
+    ModelTooLarge,
+    NotFinite(&'static str),
+    BadQrKey,
+    QrNotFound,
+    QrCorrupt,
+    QrUnsupported,
+    QrNotMii,
+    ProfileTextCorrupt(usize),
+    ProfileFieldMissing(String),
+    ProfileFieldUnknown(String),
+    ProfileFieldInvalid(String),
+    ProfileFieldOutOfRange {
+        field: String,
+        min: u32,
+        max: u32,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::IoError(e) => write!(f, "{}", e),
+            Error::ImageError(e) => write!(f, "{}", e),
+            Error::BadDatabaseSize { expected, found } => write!(
+                f,
+                "database has size 0x{:X} instead of 0x{:X}",
+                found, expected
+            ),
+            Error::BadCrc {
+                region,
+                expected,
+                found,
+            } => write!(
+                f,
+                "CRC mismatch in the {} region: stored 0x{:04X}, computed 0x{:04X}",
+                match region {
+                    CrcRegion::Owned => "owned",
+                    CrcRegion::Invited => "invited",
+                    CrcRegion::Profile => "profile",
+                },
+                found,
+                expected
+            ),
+            Error::BadProfileSize { expected, found } => write!(
+                f,
+                "profile has size 0x{:X} instead of 0x{:X}",
+                found, expected
+            ),
+            Error::NullProfile => write!(f, "profile is empty"),
+            Error::CopyingNotAllowed => write!(f, "the profile doesn't allow copying"),
+            Error::DuplicateProfile { slot: Some(slot) } => {
+                write!(f, "the same profile already exists in slot {}", slot)
+            }
+            Error::DuplicateProfile { slot: None } => write!(
+                f,
+                "the same profile already exists in an owned entry with an invalid slot"
+            ),
+            Error::SlotOccupied(slot) => write!(f, "slot {} is occupied", slot),
+            Error::NoFreeSlot => write!(f, "there is no free slot"),
+            Error::RomfsCorrupt => write!(f, "RomFS is corrupted"),
+            Error::RomfsPathNotFound(path) => write!(f, "{} is not found in RomFS", path),
+            Error::NcchCorrupt => write!(f, "NCCH container is corrupted"),
+            Error::NcchEncrypted => write!(
+                f,
+                "NCCH container is encrypted; only decrypted dumps are supported"
+            ),
+            Error::NcchSectionMissing(section) => {
+                write!(f, "{} is not found in the NCCH container", section)
+            }
+            Error::AssetHeaderCorrupt => write!(f, "asset header is corrupted"),
+            Error::AssetSectionCorrupt {
+                section,
+                item: None,
+            } => write!(f, "asset section {} is corrupted", section),
+            Error::AssetSectionCorrupt {
+                section,
+                item: Some(item),
+            } => write!(f, "item {} in asset section {} is corrupted", item, section),
+            Error::AssetItemInvalid { section, item } => write!(
+                f,
+                "item {} in asset section {} can't be packed",
+                item, section
+            ),
+            Error::AssetSectionTooLarge(section) => {
+                write!(f, "asset section {} is too large to be packed", section)
+            }
+            Error::BadTextureSize { width, height } => write!(
+                f,
+                "texture size {}x{} is not supported; the width must be even and both sides at most 1024",
+                width, height
+            ),
+            Error::ObjCorrupt(line) => write!(f, "OBJ file is invalid at line {}", line),
+            Error::ModelTooLarge => write!(f, "model has more than 256 vertices"),
+            Error::NotFinite(field) => write!(f, "{} is not a finite number", field),
+            Error::BadQrKey => write!(f, "QR key must be 16 raw bytes or 32 hexadecimal digits"),
+            Error::QrNotFound => write!(f, "no QR code is found in the image"),
+            Error::QrCorrupt => write!(f, "QR code is too damaged to be read"),
+            Error::QrUnsupported => write!(f, "QR code uses an unsupported encoding"),
+            Error::QrNotMii => write!(f, "QR code doesn't contain a Mii for this key"),
+            Error::ProfileTextCorrupt(line) => {
+                write!(f, "profile text is invalid at line {}", line)
+            }
+            Error::ProfileFieldMissing(field) => write!(f, "profile text has no {}", field),
+            Error::ProfileFieldUnknown(field) => {
+                write!(f, "profile text has an unknown field {}", field)
+            }
+            Error::ProfileFieldInvalid(field) => {
+                write!(f, "profile text has an invalid value for {}", field)
+            }
+            Error::ProfileFieldOutOfRange { field, min, max } => {
+                write!(f, "{} must be between {} and {}", field, min, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IoError(e) => Some(e),
+            Error::ImageError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::IoError(e)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(e: image::ImageError) -> Error {
+        Error::ImageError(e)
+    }
+}
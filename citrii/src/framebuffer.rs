@@ -0,0 +1,118 @@
+use gl::types::*;
+
+// An offscreen render target with a color texture and a depth buffer.
+#[derive(Debug)]
+pub struct Framebuffer {
+    fbo: GLuint,
+    color: GLuint,
+    depth: GLuint,
+    width: u32,
+    height: u32,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Option<Framebuffer> {
+        let mut fbo = 0 as GLuint;
+        let mut color = 0 as GLuint;
+        let mut depth = 0 as GLuint;
+        let complete;
+        unsafe {
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+
+            gl::GenTextures(1, &mut color);
+            gl::BindTexture(gl::TEXTURE_2D, color);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as GLint,
+                width as GLint,
+                height as GLint,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                color,
+                0,
+            );
+
+            gl::GenRenderbuffers(1, &mut depth);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, depth);
+            gl::RenderbufferStorage(
+                gl::RENDERBUFFER,
+                gl::DEPTH_COMPONENT24,
+                width as GLint,
+                height as GLint,
+            );
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                gl::RENDERBUFFER,
+                depth,
+            );
+
+            complete = gl::CheckFramebufferStatus(gl::FRAMEBUFFER) == gl::FRAMEBUFFER_COMPLETE;
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+
+        let framebuffer = Framebuffer {
+            fbo,
+            color,
+            depth,
+            width,
+            height,
+        };
+        if complete {
+            Some(framebuffer)
+        } else {
+            None
+        }
+    }
+
+    // Binds the framebuffer and sets the viewport to cover it.
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+            gl::Viewport(0, 0, self.width as GLint, self.height as GLint);
+        }
+    }
+
+    // Reads the content back with the first row at the top.
+    pub fn read_pixels(&self) -> image::RgbaImage {
+        let mut data = vec![0u8; (self.width * self.height * 4) as usize];
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0,
+                0,
+                self.width as GLint,
+                self.height as GLint,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                data.as_mut_ptr() as *mut GLvoid,
+            );
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        }
+        let mut image = image::RgbaImage::from_raw(self.width, self.height, data).unwrap();
+        image::imageops::flip_vertical_in_place(&mut image);
+        image
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::DeleteTextures(1, &self.color);
+            gl::DeleteRenderbuffers(1, &self.depth);
+        }
+    }
+}
@@ -1,7 +1,8 @@
-use crate::asset;
-use crate::model;
+use crate::asset::*;
+#[cfg(feature = "opengl")]
+use crate::framebuffer;
+#[cfg(feature = "opengl")]
 use crate::shader;
-use crate::texture;
 use cgmath::prelude::*;
 
 pub struct HeadRenderInfo {
@@ -56,143 +57,36 @@ pub struct HeadRenderInfo {
     pub eyebrow_rotation: f32,
 }
 
-struct TextureWindow {
-    tran: cgmath::Matrix3<f32>,
-    mirrored: bool,
+pub(crate) struct TextureWindow {
+    pub tran: cgmath::Matrix3<f32>,
+    pub mirrored: bool,
 }
 
-struct LayerConfig {
-    texture: i32,
-    tran: cgmath::Matrix4<f32>,
-    window: Option<TextureWindow>,
+pub(crate) struct LayerConfig {
+    pub texture: (TexturePart, usize),
+    pub tran: cgmath::Matrix4<f32>,
+    pub window: Option<TextureWindow>,
 }
 
-pub struct HeadRenderer {
-    asset: asset::Asset,
-    head_shader: shader::Shader,
+// One model drawn with the head shader, independent of the backend.
+pub(crate) struct DrawCall {
+    pub model: (ModelPart, usize),
+    pub object_tran: cgmath::Matrix4<f32>,
+    pub base_color: cgmath::Vector4<f32>,
+    pub layers: [Option<LayerConfig>; 5],
+    pub cull_face: bool,
+    // Whether front faces are clockwise instead of counterclockwise
+    pub flip: bool,
 }
 
-impl HeadRenderer {
-    pub fn with_asset(asset: asset::Asset) -> HeadRenderer {
-        let head_shader =
-            shader::Shader::new(include_str!("head.v.glsl"), include_str!("head.f.glsl"));
-        HeadRenderer { asset, head_shader }
-    }
-
-    pub fn render_head(
-        &self,
-        info: &HeadRenderInfo,
-        object_tran: &cgmath::Matrix4<f32>,
-        aspect: f32,
-    ) {
-        fn draw_model(list: &[Option<model::Model>], index: usize) {
-            list.get(index).and_then(|o| o.as_ref()).map(|m| m.draw());
-        }
-
-        fn bind_texture(list: &[Option<texture::Texture>], index: usize, unit: u32) {
-            list.get(index)
-                .and_then(|o| o.as_ref())
-                .map(|t| t.bind(unit));
-        }
-
-        fn convert_color(color: &(f32, f32, f32), alpha: f32) -> cgmath::Vector4<f32> {
-            cgmath::Vector4::new(color.0, color.1, color.2, alpha)
-        }
-
-        fn scale4(v: &cgmath::Vector4<f32>) -> cgmath::Matrix4<f32> {
-            cgmath::Matrix4::from_cols(
-                cgmath::Vector4::new(v.x, 0.0, 0.0, 0.0),
-                cgmath::Vector4::new(0.0, v.y, 0.0, 0.0),
-                cgmath::Vector4::new(0.0, 0.0, v.z, 0.0),
-                cgmath::Vector4::new(0.0, 0.0, 0.0, v.w),
-            )
-        }
-
-        fn window(p00: (f32, f32), p11: (f32, f32)) -> cgmath::Matrix3<f32> {
-            let dx = 1.0 / (p11.0 - p00.0);
-            let dy = 1.0 / (p11.1 - p00.1);
-            cgmath::Matrix3::from_cols(
-                cgmath::Vector3::new(dx, 0.0, 0.0),
-                cgmath::Vector3::new(0.0, dy, 0.0),
-                cgmath::Vector3::new(-p00.0 * dx, -p00.1 * dy, 1.0),
-            )
-        }
-
-        fn rotate_around(center: (f32, f32), angle: f32) -> cgmath::Matrix3<f32> {
-            cgmath::Matrix3::from_cols(
-                cgmath::Vector3::new(1.0, 0.0, 0.0),
-                cgmath::Vector3::new(0.0, 1.0, 0.0),
-                cgmath::Vector3::new(center.0, center.1, 1.0),
-            ) * cgmath::Matrix3::from_angle_z(cgmath::Deg(angle))
-                * cgmath::Matrix3::from_cols(
-                    cgmath::Vector3::new(1.0, 0.0, 0.0),
-                    cgmath::Vector3::new(0.0, 1.0, 0.0),
-                    cgmath::Vector3::new(-center.0, -center.1, 1.0),
-                )
-        }
-
-        let set_object_tran = |object_tran: &cgmath::Matrix4<f32>| {
-            let object_tran_inv = object_tran.invert().unwrap();
-            self.head_shader
-                .set_uniform_mat4("object_tran", object_tran);
-            self.head_shader
-                .set_uniform_mat4("object_tran_inv", &object_tran_inv);
-        };
-
-        let set_layers = |configs: [Option<LayerConfig>; 5]| {
-            for (layer, config) in configs.iter().enumerate() {
-                match &config {
-                    None => self.head_shader.set_uniform_mat4(
-                        &format!("color_tran[{}]", layer),
-                        &cgmath::Matrix4::<f32>::zero(),
-                    ),
-                    Some(LayerConfig {
-                        texture,
-                        tran,
-                        window,
-                    }) => {
-                        self.head_shader
-                            .set_uniform_i(&format!("tex{}", layer), *texture);
-                        self.head_shader
-                            .set_uniform_mat4(&format!("color_tran[{}]", layer), tran);
-                        let mode_code = match window {
-                            None => 0,
-                            Some(TextureWindow { tran, mirrored }) => {
-                                self.head_shader
-                                    .set_uniform_mat3(&format!("tex_tran[{}]", layer), tran);
-                                if *mirrored {
-                                    2
-                                } else {
-                                    1
-                                }
-                            }
-                        };
-                        self.head_shader
-                            .set_uniform_i(&format!("tex_mode[{}]", layer), mode_code);
-                    }
-                }
-            }
-        };
-
-        let zero_vec4 = cgmath::Vector4::<f32>::zero();
-
-        let face_config = match self
-            .asset
-            .face_configs
-            .get(info.face)
-            .and_then(|o| o.as_ref())
-        {
-            Some(f) => f,
-            None => return,
-        };
-
-        let hair_pos = cgmath::Vector3::from(face_config.hair_pos);
-        let nose_pos = cgmath::Vector3::from(face_config.nose_pos);
-        let beard_pos = cgmath::Vector3::from(face_config.beard_pos);
-
-        self.head_shader.bind();
+pub(crate) struct Environment {
+    pub camera_pos: cgmath::Point3<f32>,
+    pub light_source: cgmath::Point3<f32>,
+    pub camera_tran: cgmath::Matrix4<f32>,
+}
 
-        // Setup environment
+impl Environment {
+    pub fn new(aspect: f32) -> Environment {
         let camera_pos = cgmath::Point3::new(0.0, 30.0, 400.0);
         let light_source = cgmath::Point3::new(-500.0, 500.0, 500.0);
         let camera_tran = cgmath::perspective(cgmath::Deg(15.0), aspect, 1.0, 1000.0)
@@ -201,50 +95,107 @@ impl HeadRenderer {
                 cgmath::Point3::new(0.0, 30.0, 0.0),
                 cgmath::Vector3::new(0.0, 1.0, 0.0),
             );
-        self.head_shader.set_uniform_vec("camera_pos", &camera_pos);
-        self.head_shader
-            .set_uniform_vec("light_source", &light_source);
-        self.head_shader
-            .set_uniform_mat4("camera_tran", &camera_tran);
-
-        // bind all relevant textures
-        bind_texture(&self.asset.accessory_textures, info.hair, 0);
-        bind_texture(&self.asset.eye_textures, info.eye, 1);
-        bind_texture(&self.asset.eyebrow_textures, info.eyebrow, 2);
-        bind_texture(&self.asset.beard_textures, info.beard_plain, 3);
-        bind_texture(&self.asset.wrinkle_textures, info.wrinkle, 4);
-        bind_texture(&self.asset.makeup_textures, info.makeup, 5);
-        bind_texture(&self.asset.glass_textures, info.glass, 6);
-        bind_texture(&self.asset.mole_textures, info.mole, 7);
-        bind_texture(&self.asset.lip_textures, info.lip, 8);
-        bind_texture(&self.asset.mustache_textures, info.mustache, 9);
-        bind_texture(&self.asset.nose_textures, info.nose, 10);
+        Environment {
+            camera_pos,
+            light_source,
+            camera_tran,
+        }
+    }
+}
 
-        unsafe {
-            gl::Enable(gl::CULL_FACE);
+// Lists the models to draw in order, or nothing if the face is missing.
+pub(crate) fn head_draw_calls(
+    face_configs: &[Option<FaceConfig>],
+    info: &HeadRenderInfo,
+    object_tran: &cgmath::Matrix4<f32>,
+) -> Vec<DrawCall> {
+    fn convert_color(color: &(f32, f32, f32), alpha: f32) -> cgmath::Vector4<f32> {
+        cgmath::Vector4::new(color.0, color.1, color.2, alpha)
+    }
+
+    fn scale4(v: &cgmath::Vector4<f32>) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_cols(
+            cgmath::Vector4::new(v.x, 0.0, 0.0, 0.0),
+            cgmath::Vector4::new(0.0, v.y, 0.0, 0.0),
+            cgmath::Vector4::new(0.0, 0.0, v.z, 0.0),
+            cgmath::Vector4::new(0.0, 0.0, 0.0, v.w),
+        )
+    }
+
+    fn window(p00: (f32, f32), p11: (f32, f32)) -> cgmath::Matrix3<f32> {
+        let dx = 1.0 / (p11.0 - p00.0);
+        let dy = 1.0 / (p11.1 - p00.1);
+        cgmath::Matrix3::from_cols(
+            cgmath::Vector3::new(dx, 0.0, 0.0),
+            cgmath::Vector3::new(0.0, dy, 0.0),
+            cgmath::Vector3::new(-p00.0 * dx, -p00.1 * dy, 1.0),
+        )
+    }
+
+    fn rotate_around(center: (f32, f32), angle: f32) -> cgmath::Matrix3<f32> {
+        cgmath::Matrix3::from_cols(
+            cgmath::Vector3::new(1.0, 0.0, 0.0),
+            cgmath::Vector3::new(0.0, 1.0, 0.0),
+            cgmath::Vector3::new(center.0, center.1, 1.0),
+        ) * cgmath::Matrix3::from_angle_z(cgmath::Deg(angle))
+            * cgmath::Matrix3::from_cols(
+                cgmath::Vector3::new(1.0, 0.0, 0.0),
+                cgmath::Vector3::new(0.0, 1.0, 0.0),
+                cgmath::Vector3::new(-center.0, -center.1, 1.0),
+            )
+    }
+
+    fn draw(
+        model: (ModelPart, usize),
+        object_tran: cgmath::Matrix4<f32>,
+        base_color: cgmath::Vector4<f32>,
+        layers: [Option<LayerConfig>; 5],
+    ) -> DrawCall {
+        DrawCall {
+            model,
+            object_tran,
+            base_color,
+            layers,
+            cull_face: true,
+            flip: false,
         }
+    }
 
-        // Draw face
-        set_object_tran(object_tran);
-        self.head_shader
-            .set_uniform_vec("base_color", &convert_color(&info.face_color, 1.0));
-        // layer order:
-        // 0 - makeup
-        // 1 - wrinkle
-        // 2 - beard
-        set_layers([
+    let zero_vec4 = cgmath::Vector4::<f32>::zero();
+
+    let face_config = match face_configs.get(info.face).and_then(|o| o.as_ref()) {
+        Some(f) => f,
+        None => return vec![],
+    };
+
+    let hair_pos = cgmath::Vector3::from(face_config.hair_pos);
+    let nose_pos = cgmath::Vector3::from(face_config.nose_pos);
+    let beard_pos = cgmath::Vector3::from(face_config.beard_pos);
+
+    let mut calls = vec![];
+
+    // Draw face
+    // layer order:
+    // 0 - makeup
+    // 1 - wrinkle
+    // 2 - beard
+    calls.push(draw(
+        (ModelPart::Face, info.face),
+        *object_tran,
+        convert_color(&info.face_color, 1.0),
+        [
             Some(LayerConfig {
-                texture: 5,
+                texture: (TexturePart::Makeup, info.makeup),
                 tran: cgmath::Matrix4::<f32>::identity(),
                 window: None,
             }),
             Some(LayerConfig {
-                texture: 4,
+                texture: (TexturePart::Wrinkle, info.wrinkle),
                 tran: cgmath::Matrix4::<f32>::identity(),
                 window: None,
             }),
             Some(LayerConfig {
-                texture: 3,
+                texture: (TexturePart::Beard, info.beard_plain),
                 tran: cgmath::Matrix4::from_cols(
                     cgmath::Vector4::zero(),
                     cgmath::Vector4::zero(),
@@ -255,85 +206,82 @@ impl HeadRenderer {
             }),
             None,
             None,
-        ]);
-
-        draw_model(&self.asset.face_models, info.face);
-
-        // Draw hair
-        if info.flip_hair {
-            unsafe {
-                gl::FrontFace(gl::CW);
-            }
-        }
-        let flip_matrix = cgmath::Matrix4::from_nonuniform_scale(
-            if info.flip_hair { -1.0 } else { 1.0 },
-            1.0,
-            1.0,
-        );
-        set_object_tran(&(object_tran * cgmath::Matrix4::from_translation(hair_pos) * flip_matrix));
-        let hair_index = info.hair * 2 + if info.full_hair { 0 } else { 1 };
-
-        self.head_shader
-            .set_uniform_vec("base_color", &convert_color(&info.wearing_color, 1.0));
-        set_layers([
-            Some(LayerConfig {
-                texture: 0,
-                tran: scale4(&convert_color(&info.wearing_color, 0.4)),
-                window: None,
-            }),
-            None,
-            None,
-            None,
-            None,
-        ]);
-        draw_model(&self.asset.accessory_models, hair_index);
-
-        set_layers([None, None, None, None, None]);
-
-        self.head_shader
-            .set_uniform_vec("base_color", &convert_color(&info.hair_color, 1.0));
-        draw_model(&self.asset.hair_models, hair_index);
-
-        self.head_shader
-            .set_uniform_vec("base_color", &convert_color(&info.face_color, 1.0));
-        draw_model(&self.asset.scalp_models, hair_index);
-
-        if info.flip_hair {
-            unsafe {
-                gl::FrontFace(gl::CCW);
-            }
-        }
-
-        // Draw beard
-        set_object_tran(&(object_tran * cgmath::Matrix4::from_translation(beard_pos)));
-        self.head_shader
-            .set_uniform_vec("base_color", &convert_color(&info.beard_color, 1.0));
-        draw_model(&self.asset.beard_models, info.beard);
-
-        // Draw nose model
-        let nose_tran = object_tran
-            * cgmath::Matrix4::from_translation(
-                nose_pos + cgmath::Vector3::new(0.0, info.nose_y, 0.0),
-            )
-            * cgmath::Matrix4::from_scale(info.nose_scale);
-        set_object_tran(&nose_tran);
+        ],
+    ));
+
+    // Draw hair
+    let flip_matrix =
+        cgmath::Matrix4::from_nonuniform_scale(if info.flip_hair { -1.0 } else { 1.0 }, 1.0, 1.0);
+    let hair_tran = object_tran * cgmath::Matrix4::from_translation(hair_pos) * flip_matrix;
+    let hair_index = info.hair * 2 + if info.full_hair { 0 } else { 1 };
+    let hair_calls = [
+        draw(
+            (ModelPart::Accessory, hair_index),
+            hair_tran,
+            convert_color(&info.wearing_color, 1.0),
+            [
+                Some(LayerConfig {
+                    texture: (TexturePart::Accessory, info.hair),
+                    tran: scale4(&convert_color(&info.wearing_color, 0.4)),
+                    window: None,
+                }),
+                None,
+                None,
+                None,
+                None,
+            ],
+        ),
+        draw(
+            (ModelPart::Hair, hair_index),
+            hair_tran,
+            convert_color(&info.hair_color, 1.0),
+            [None, None, None, None, None],
+        ),
+        draw(
+            (ModelPart::Scalp, hair_index),
+            hair_tran,
+            convert_color(&info.face_color, 1.0),
+            [None, None, None, None, None],
+        ),
+    ];
+    for mut call in hair_calls {
+        call.flip = info.flip_hair;
+        calls.push(call);
+    }
 
-        self.head_shader
-            .set_uniform_vec("base_color", &convert_color(&info.face_color, 1.0));
-        draw_model(&self.asset.nose_models, info.nose);
-
-        // Draw face canvas
-        set_object_tran(object_tran);
-        self.head_shader.set_uniform_vec("base_color", &zero_vec4);
-        // layer order:
-        // 0 - mole
-        // 1 - eye
-        // 2 - eyebrow
-        // 3 - lip
-        // 4 - mustache
-        set_layers([
+    // Draw beard
+    calls.push(draw(
+        (ModelPart::Beard, info.beard),
+        object_tran * cgmath::Matrix4::from_translation(beard_pos),
+        convert_color(&info.beard_color, 1.0),
+        [None, None, None, None, None],
+    ));
+
+    // Draw nose model
+    let nose_tran = object_tran
+        * cgmath::Matrix4::from_translation(nose_pos + cgmath::Vector3::new(0.0, info.nose_y, 0.0))
+        * cgmath::Matrix4::from_scale(info.nose_scale);
+    calls.push(draw(
+        (ModelPart::Nose, info.nose),
+        nose_tran,
+        convert_color(&info.face_color, 1.0),
+        [None, None, None, None, None],
+    ));
+
+    // Draw face canvas
+    // layer order:
+    // 0 - mole
+    // 1 - eye
+    // 2 - eyebrow
+    // 3 - lip
+    // 4 - mustache
+    calls.push(draw(
+        (ModelPart::FaceCanvas, info.face),
+        *object_tran,
+        zero_vec4,
+        [
             Some(LayerConfig {
-                texture: 7,
+                texture: (TexturePart::Mole, info.mole),
                 tran: cgmath::Matrix4::identity(),
                 window: Some(TextureWindow {
                     tran: window(
@@ -350,7 +298,7 @@ impl HeadRenderer {
                 }),
             }),
             Some(LayerConfig {
-                texture: 1,
+                texture: (TexturePart::Eye, info.eye),
                 tran: cgmath::Matrix4::from_cols(
                     cgmath::Vector4::new(0.0, 1.0, 1.0, 0.0),
                     cgmath::Vector4::new(1.0, 1.0, 1.0, 0.0),
@@ -369,7 +317,7 @@ impl HeadRenderer {
                 }),
             }),
             Some(LayerConfig {
-                texture: 2,
+                texture: (TexturePart::Eyebrow, info.eyebrow),
                 tran: cgmath::Matrix4::from_cols(
                     cgmath::Vector4::zero(),
                     cgmath::Vector4::zero(),
@@ -394,7 +342,7 @@ impl HeadRenderer {
                 }),
             }),
             Some(LayerConfig {
-                texture: 8,
+                texture: (TexturePart::Lip, info.lip),
                 tran: cgmath::Matrix4::from_cols(
                     convert_color(&info.lip_color, 0.0),
                     convert_color(&info.lip_color, 0.0) * 0.5,
@@ -416,7 +364,7 @@ impl HeadRenderer {
                 }),
             }),
             Some(LayerConfig {
-                texture: 9,
+                texture: (TexturePart::Mustache, info.mustache),
                 tran: cgmath::Matrix4::from_cols(
                     cgmath::Vector4::zero(),
                     cgmath::Vector4::zero(),
@@ -434,17 +382,17 @@ impl HeadRenderer {
                     mirrored: true,
                 }),
             }),
-        ]);
-
-        draw_model(&self.asset.face_canvas_models, info.face);
-
-        // Draw nose canvas
-        set_object_tran(&nose_tran);
-
-        self.head_shader.set_uniform_vec("base_color", &zero_vec4);
-        set_layers([
+        ],
+    ));
+
+    // Draw nose canvas
+    calls.push(draw(
+        (ModelPart::NoseCanvas, info.nose),
+        nose_tran,
+        zero_vec4,
+        [
             Some(LayerConfig {
-                texture: 10,
+                texture: (TexturePart::Nose, info.nose),
                 tran: cgmath::Matrix4::identity(),
                 window: None,
             }),
@@ -452,25 +400,21 @@ impl HeadRenderer {
             None,
             None,
             None,
-        ]);
-        draw_model(&self.asset.nose_canvas_models, info.nose);
-
-        // Draw glasses
-        unsafe {
-            gl::Disable(gl::CULL_FACE);
-        }
+        ],
+    ));
 
-        set_object_tran(
-            &(object_tran
-                * cgmath::Matrix4::from_translation(
-                    nose_pos + cgmath::Vector3::new(0.0, info.glass_y, 2.0),
-                )
-                * cgmath::Matrix4::from_scale(info.glass_scale)),
-        );
-        self.head_shader.set_uniform_vec("base_color", &zero_vec4);
-        set_layers([
+    // Draw glasses
+    let mut glass_call = draw(
+        (ModelPart::Glass, 0),
+        object_tran
+            * cgmath::Matrix4::from_translation(
+                nose_pos + cgmath::Vector3::new(0.0, info.glass_y, 2.0),
+            )
+            * cgmath::Matrix4::from_scale(info.glass_scale),
+        zero_vec4,
+        [
             Some(LayerConfig {
-                texture: 6,
+                texture: (TexturePart::Glass, info.glass),
                 tran: scale4(&convert_color(&info.glass_color, 1.0)),
                 window: None,
             }),
@@ -478,7 +422,193 @@ impl HeadRenderer {
             None,
             None,
             None,
-        ]);
-        draw_model(&self.asset.glass_models, 0);
+        ],
+    );
+    glass_call.cull_face = false;
+    calls.push(glass_call);
+
+    calls
+}
+
+// Parameters for rendering a head into an image instead of the window.
+pub struct OffscreenParams {
+    pub width: u32,
+    pub height: u32,
+    // Rotation of the head in degrees. Positive yaw turns the head to its left, and positive
+    // pitch tilts it down.
+    pub yaw: f32,
+    pub pitch: f32,
+    // None for a transparent background
+    pub background: Option<(f32, f32, f32)>,
+}
+
+impl OffscreenParams {
+    pub fn object_tran(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_angle_x(cgmath::Deg(self.pitch))
+            * cgmath::Matrix4::from_angle_y(cgmath::Deg(self.yaw))
+    }
+}
+
+// Colors are blended over black for a transparent background, so this undoes the
+// premultiplication for partially transparent pixels.
+pub(crate) fn unpremultiply(image: &mut image::RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        if alpha != 0 && alpha != 255 {
+            for c in 0..3 {
+                pixel[c] = std::cmp::min(pixel[c] as u32 * 255 / alpha, 255) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "opengl")]
+pub struct HeadRenderer {
+    asset: Asset,
+    head_shader: shader::Shader,
+}
+
+#[cfg(feature = "opengl")]
+impl HeadRenderer {
+    pub fn with_asset(asset: Asset) -> HeadRenderer {
+        let head_shader =
+            shader::Shader::new(include_str!("head.v.glsl"), include_str!("head.f.glsl"));
+        HeadRenderer { asset, head_shader }
+    }
+
+    pub fn render_head(
+        &self,
+        info: &HeadRenderInfo,
+        object_tran: &cgmath::Matrix4<f32>,
+        aspect: f32,
+    ) {
+        let calls = head_draw_calls(&self.asset.face_configs, info, object_tran);
+        if calls.is_empty() {
+            return;
+        }
+
+        self.head_shader.bind();
+
+        // Setup environment
+        let environment = Environment::new(aspect);
+        self.head_shader
+            .set_uniform_vec("camera_pos", &environment.camera_pos);
+        self.head_shader
+            .set_uniform_vec("light_source", &environment.light_source);
+        self.head_shader
+            .set_uniform_mat4("camera_tran", &environment.camera_tran);
+
+        for call in &calls {
+            unsafe {
+                if call.cull_face {
+                    gl::Enable(gl::CULL_FACE);
+                } else {
+                    gl::Disable(gl::CULL_FACE);
+                }
+                gl::FrontFace(if call.flip { gl::CW } else { gl::CCW });
+            }
+
+            let object_tran_inv = call.object_tran.invert().unwrap();
+            self.head_shader
+                .set_uniform_mat4("object_tran", &call.object_tran);
+            self.head_shader
+                .set_uniform_mat4("object_tran_inv", &object_tran_inv);
+            self.head_shader
+                .set_uniform_vec("base_color", &call.base_color);
+
+            for (layer, config) in call.layers.iter().enumerate() {
+                let texture = config
+                    .as_ref()
+                    .and_then(|c| self.asset.texture(c.texture.0, c.texture.1));
+                match (config, texture) {
+                    (Some(config), Some(texture)) => {
+                        texture.bind(layer as u32);
+                        self.head_shader
+                            .set_uniform_i(&format!("tex{}", layer), layer as i32);
+                        self.head_shader
+                            .set_uniform_mat4(&format!("color_tran[{}]", layer), &config.tran);
+                        let mode_code = match &config.window {
+                            None => 0,
+                            Some(TextureWindow { tran, mirrored }) => {
+                                self.head_shader
+                                    .set_uniform_mat3(&format!("tex_tran[{}]", layer), tran);
+                                if *mirrored {
+                                    2
+                                } else {
+                                    1
+                                }
+                            }
+                        };
+                        self.head_shader
+                            .set_uniform_i(&format!("tex_mode[{}]", layer), mode_code);
+                    }
+                    // A zero color transformation makes the layer fully transparent
+                    _ => self.head_shader.set_uniform_mat4(
+                        &format!("color_tran[{}]", layer),
+                        &cgmath::Matrix4::<f32>::zero(),
+                    ),
+                }
+            }
+
+            if let Some(m) = self.asset.model(call.model.0, call.model.1) {
+                m.draw();
+            }
+        }
+
+        unsafe {
+            gl::FrontFace(gl::CCW);
+        }
+    }
+
+    // Renders into an offscreen framebuffer and reads the result back. The current viewport and
+    // framebuffer binding are not preserved. Returns None if the framebuffer can't be created.
+    pub fn render_head_offscreen(
+        &self,
+        info: &HeadRenderInfo,
+        params: &OffscreenParams,
+    ) -> Option<image::RgbaImage> {
+        let framebuffer = framebuffer::Framebuffer::new(params.width, params.height)?;
+        framebuffer.bind();
+        let (r, g, b) = params.background.unwrap_or((0.0, 0.0, 0.0));
+        let a = if params.background.is_some() {
+            1.0
+        } else {
+            0.0
+        };
+        unsafe {
+            gl::ClearColor(r, g, b, a);
+            gl::ClearDepth(1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            gl::Enable(gl::DEPTH_TEST);
+            gl::Enable(gl::BLEND);
+            // Accumulates coverage in alpha so that a transparent background stays transparent
+            gl::BlendFuncSeparate(
+                gl::SRC_ALPHA,
+                gl::ONE_MINUS_SRC_ALPHA,
+                gl::ONE,
+                gl::ONE_MINUS_SRC_ALPHA,
+            );
+        }
+
+        self.render_head(
+            info,
+            &params.object_tran(),
+            params.width as f32 / params.height as f32,
+        );
+
+        unsafe {
+            gl::Disable(gl::CULL_FACE);
+            gl::Disable(gl::DEPTH_TEST);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+        let mut image = framebuffer.read_pixels();
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+
+        if params.background.is_none() {
+            unpremultiply(&mut image);
+        }
+        Some(image)
     }
 }
@@ -0,0 +1,375 @@
+// Undo and redo of edits to the owned, invited and encountered profiles. An edit is recorded as
+// the entries it changed before and after, so field changes, slot moves, reordering, creation and
+// deletion are undone the same way.
+
+use crate::database::*;
+use byte_struct::*;
+
+// Entries are the owned profiles, the invited profiles, the invited order together with
+// invited_count, the CFHE nodes together with their profiles, and the CFHE head and tail.
+const INVITED_ENTRY_BEGIN: usize = 100;
+const INVITED_ORDER_ENTRY: usize = 200;
+const CFHE_ENTRY_BEGIN: usize = 201;
+const CFHE_ENDS_ENTRY: usize = 3201;
+const ENTRY_COUNT: usize = 3202;
+
+fn read_entry(database: &Database, entry: usize) -> Vec<u8> {
+    fn to_bytes<T: ByteStruct>(value: &T) -> Vec<u8> {
+        let mut data = vec![0; T::BYTE_LEN];
+        value.write_bytes(&mut data[..]);
+        data
+    }
+    match entry {
+        CFHE_ENDS_ENTRY => [database.cfhe_head, database.cfhe_tail]
+            .iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect(),
+        e if e >= CFHE_ENTRY_BEGIN => {
+            let node = e - CFHE_ENTRY_BEGIN;
+            let mut data = to_bytes(&database.cfhe_objects[node]);
+            data.append(&mut to_bytes(&database.cfhe_profiles[node]));
+            data
+        }
+        INVITED_ORDER_ENTRY => {
+            let mut data = database.invited_count.to_le_bytes().to_vec();
+            data.extend_from_slice(&database.invited_order);
+            data
+        }
+        e if e >= INVITED_ENTRY_BEGIN => to_bytes(&database.invited[e - INVITED_ENTRY_BEGIN]),
+        e => to_bytes(&database.owned[e]),
+    }
+}
+
+fn write_entry(database: &mut Database, entry: usize, data: &[u8]) {
+    match entry {
+        CFHE_ENDS_ENTRY => {
+            database.cfhe_head = u16::from_le_bytes([data[0], data[1]]);
+            database.cfhe_tail = u16::from_le_bytes([data[2], data[3]]);
+        }
+        e if e >= CFHE_ENTRY_BEGIN => {
+            let node = e - CFHE_ENTRY_BEGIN;
+            database.cfhe_objects[node] = CFHEObject::read_bytes(&data[0..CFHEObject::BYTE_LEN]);
+            database.cfhe_profiles[node] = ProfileAlt::read_bytes(&data[CFHEObject::BYTE_LEN..]);
+        }
+        INVITED_ORDER_ENTRY => {
+            database.invited_count = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+            database.invited_order.copy_from_slice(&data[4..]);
+        }
+        e if e >= INVITED_ENTRY_BEGIN => {
+            database.invited[e - INVITED_ENTRY_BEGIN] = Profile::read_bytes(data)
+        }
+        e => database.owned[e] = ProfileFull::read_bytes(data),
+    }
+}
+
+struct Change {
+    entry: usize,
+    before: Vec<u8>,
+    after: Vec<u8>,
+}
+
+struct Edit {
+    // Identifies the state after the edit, see History::position
+    id: u64,
+    changes: Vec<Change>,
+    selection_before: ProfileRef,
+    selection_after: ProfileRef,
+    merge_key: Option<u32>,
+}
+
+impl Edit {
+    fn touches(&self, entry: usize) -> bool {
+        self.changes.iter().any(|c| c.entry == entry)
+    }
+
+    fn same_entries(&self, other: &Edit) -> bool {
+        self.changes.len() == other.changes.len()
+            && other.changes.iter().all(|c| self.touches(c.entry))
+    }
+}
+
+fn profile_entry(profile: ProfileRef) -> usize {
+    match profile {
+        ProfileRef::Owned(index) => index,
+        ProfileRef::Invited(index) => INVITED_ENTRY_BEGIN + index,
+        ProfileRef::Encountered(node) => CFHE_ENTRY_BEGIN + node,
+    }
+}
+
+// Editable entries and the selection before an edit, passed to History::record once the edit is
+// done. Only the entries in the snapshot are recorded, so it has to cover everything the edit
+// changes.
+pub struct Snapshot {
+    entries: Vec<(usize, Vec<u8>)>,
+    selection: ProfileRef,
+}
+
+impl Snapshot {
+    fn of_entries(
+        database: &Database,
+        selection: ProfileRef,
+        entries: impl Iterator<Item = usize>,
+    ) -> Snapshot {
+        Snapshot {
+            entries: entries
+                .map(|entry| (entry, read_entry(database, entry)))
+                .collect(),
+            selection,
+        }
+    }
+
+    // All entries, for edits that move, create or delete profiles
+    pub fn new(database: &Database, selection: ProfileRef) -> Snapshot {
+        Snapshot::of_entries(database, selection, 0..ENTRY_COUNT)
+    }
+
+    // The selected profile only, for edits of its fields
+    pub fn of_selection(database: &Database, selection: ProfileRef) -> Snapshot {
+        Snapshot::of_entries(
+            database,
+            selection,
+            std::iter::once(profile_entry(selection)),
+        )
+    }
+
+    // The owned profiles only, for edits that create an owned profile
+    pub fn of_owned(database: &Database, selection: ProfileRef) -> Snapshot {
+        Snapshot::of_entries(database, selection, 0..INVITED_ENTRY_BEGIN)
+    }
+}
+
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    limit_per_profile: usize,
+    // Identifies the state before the oldest edit that can be undone
+    base_id: u64,
+    next_id: u64,
+}
+
+impl History {
+    // Keeps at most `limit_per_profile` edits touching each profile
+    pub fn new(limit_per_profile: usize) -> History {
+        History {
+            undo: vec![],
+            redo: vec![],
+            limit_per_profile: limit_per_profile.max(1),
+            base_id: 0,
+            next_id: 1,
+        }
+    }
+
+    fn new_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    // Identifies the current state of the database. Undo and redo return to the same positions,
+    // while new and merged edits and dropped history always give a new one, so the database is
+    // unchanged since some point exactly when the position is the same.
+    pub fn position(&self) -> u64 {
+        self.undo.last().map_or(self.base_id, |e| e.id)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.base_id = self.new_id();
+    }
+
+    // Records the difference between `before` and the current database as one edit, and returns
+    // whether anything changed. Consecutive edits with the same `merge_key` on the same profiles
+    // are merged, so that dragging a scroll bar is undone in one step.
+    pub fn record(
+        &mut self,
+        before: Snapshot,
+        database: &Database,
+        selection: ProfileRef,
+        merge_key: Option<u32>,
+    ) -> bool {
+        let changes: Vec<Change> = before
+            .entries
+            .into_iter()
+            .map(|(entry, old)| (entry, old, read_entry(database, entry)))
+            .filter(|(_, old, new)| old != new)
+            .map(|(entry, before, after)| Change {
+                entry,
+                before,
+                after,
+            })
+            .collect();
+        if changes.is_empty() {
+            return false;
+        }
+        let edit = Edit {
+            id: self.new_id(),
+            changes,
+            selection_before: before.selection,
+            selection_after: selection,
+            merge_key,
+        };
+        self.redo.clear();
+
+        if let Some(last) = self.undo.last_mut() {
+            if merge_key.is_some() && last.merge_key == merge_key && last.same_entries(&edit) {
+                for change in edit.changes {
+                    let entry = change.entry;
+                    let target = last.changes.iter_mut().find(|c| c.entry == entry).unwrap();
+                    target.after = change.after;
+                }
+                last.selection_after = selection;
+                last.id = edit.id;
+                return true;
+            }
+        }
+
+        let entries: Vec<usize> = edit.changes.iter().map(|c| c.entry).collect();
+        self.undo.push(edit);
+        for entry in entries {
+            self.trim(entry);
+        }
+        true
+    }
+
+    // Drops the oldest edits of an entry beyond the limit. Older edits of the other entries
+    // touched by a dropped edit are dropped as well, as they can no longer be reached by undo.
+    fn trim(&mut self, entry: usize) {
+        while self.undo.iter().filter(|e| e.touches(entry)).count() > self.limit_per_profile {
+            let oldest = self.undo.iter().position(|e| e.touches(entry)).unwrap();
+            let mut remove = vec![false; self.undo.len()];
+            remove[oldest] = true;
+            let mut entries: Vec<usize> =
+                self.undo[oldest].changes.iter().map(|c| c.entry).collect();
+            for i in (0..oldest).rev() {
+                if entries.iter().any(|&entry| self.undo[i].touches(entry)) {
+                    remove[i] = true;
+                    entries.extend(self.undo[i].changes.iter().map(|c| c.entry));
+                }
+            }
+            let mut remove = remove.into_iter();
+            self.undo.retain(|_| !remove.next().unwrap());
+            // Undoing everything that is left now reaches a state that never existed
+            self.base_id = self.new_id();
+        }
+    }
+
+    // Reverts the last edit and returns the selection before it
+    pub fn undo(&mut self, database: &mut Database) -> Option<ProfileRef> {
+        let edit = self.undo.pop()?;
+        for change in &edit.changes {
+            write_entry(database, change.entry, &change.before);
+        }
+        let selection = edit.selection_before;
+        self.redo.push(edit);
+        Some(selection)
+    }
+
+    // Applies the last undone edit again and returns the selection after it
+    pub fn redo(&mut self, database: &mut Database) -> Option<ProfileRef> {
+        let edit = self.redo.pop()?;
+        for change in &edit.changes {
+            write_entry(database, change.entry, &change.after);
+        }
+        let selection = edit.selection_after;
+        self.undo.push(edit);
+        Some(selection)
+    }
+}
+
+#[test]
+fn history_test() {
+    let mut database = test_database();
+    database.owned[0].main = test_profile(1, 0, 0);
+    database.owned[1].main = test_profile(1, 0, 1);
+    let style = database.owned[0].main.hair.style;
+    let width = database.owned[0].main.width;
+    let height = database.owned[1].main.height;
+    let owned = ProfileRef::Owned(0);
+    let mut history = History::new(2);
+
+    let before = Snapshot::new(&database, owned);
+    assert!(!history.record(before, &database, owned, None));
+    assert!(!history.can_undo());
+
+    let start = history.position();
+    let before = Snapshot::of_selection(&database, owned);
+    database.owned[0].main.hair.style = style + 5;
+    assert!(history.record(before, &database, owned, None));
+    let styled = history.position();
+    assert_ne!(styled, start);
+
+    let before = Snapshot::new(&database, owned);
+    database.swap_owned_slots(0, 1);
+    assert!(history.record(before, &database, owned, None));
+
+    assert_eq!(history.undo(&mut database), Some(owned));
+    assert_eq!(database.owned[0].main.get_slot(), 0);
+    assert_eq!(database.owned[1].main.get_slot(), 1);
+    assert_eq!(history.undo(&mut database), Some(owned));
+    assert_eq!(database.owned[0].main.hair.style, style);
+    assert_eq!(history.undo(&mut database), None);
+    assert_eq!(history.position(), start);
+    assert_eq!(history.redo(&mut database), Some(owned));
+    assert_eq!(database.owned[0].main.hair.style, style + 5);
+    assert_eq!(history.position(), styled);
+
+    // A new edit discards the redo history
+    let before = Snapshot::new(&database, owned);
+    database.owned[0].main.width = width + 10;
+    history.record(before, &database, owned, Some(1));
+    assert!(!history.can_redo());
+
+    // Merged into the previous edit
+    let before = Snapshot::of_selection(&database, owned);
+    database.owned[0].main.width = width + 20;
+    history.record(before, &database, owned, Some(1));
+    history.undo(&mut database);
+    assert_eq!(database.owned[0].main.width, width);
+    history.redo(&mut database);
+
+    // Profile 0 now has the style and width edits; a third one drops the style edit
+    let before = Snapshot::new(&database, owned);
+    database.owned[0].main.height = height + 30;
+    history.record(before, &database, owned, None);
+    let before = Snapshot::new(&database, ProfileRef::Owned(1));
+    database.owned[1].main.height = height + 40;
+    history.record(before, &database, ProfileRef::Owned(1), None);
+    assert_eq!(history.undo.len(), 3);
+    while history.undo(&mut database).is_some() {}
+    assert_ne!(history.position(), start);
+    assert_eq!(database.owned[0].main.hair.style, style + 5);
+    assert_eq!(database.owned[0].main.width, width);
+    assert_eq!(database.owned[1].main.height, height);
+
+    // Invited profiles and their order
+    database.invited[3] = test_profile(1, 0, 0);
+    database.fix_invited_order();
+    let invited = ProfileRef::Invited(3);
+    let before = Snapshot::new(&database, invited);
+    database.remove_invited(3).unwrap();
+    assert!(history.record(before, &database, owned, None));
+    assert_eq!(history.undo(&mut database), Some(invited));
+    assert_eq!(database.invited_list(), vec![3]);
+    assert_eq!(database.invited_count, 1);
+
+    // Encountered profiles and their links
+    let profile = test_profile(3, 0, 0);
+    database.cfhe_objects[5].profile_id = profile.id.clone();
+    database.cfhe_profiles[5].main = profile;
+    database.cfhe_head = 5;
+    database.cfhe_tail = 5;
+    let before = Snapshot::new(&database, owned);
+    database.remove_cfhe(5).unwrap();
+    assert!(history.record(before, &database, owned, None));
+    history.undo(&mut database);
+    assert_eq!(database.cfhe_order(), vec![5]);
+}
@@ -0,0 +1,120 @@
+// Editing of the invited (CFRA) list. The first invited_count entries of invited_order are the
+// indices into `invited` in display order. The remaining entries are unused and keep whatever
+// value the file already has there.
+
+use crate::database::*;
+use crate::error::*;
+
+const INVITED_LEN: usize = 100;
+
+impl Database {
+    // Indices of the invited profiles in display order. Entries of invited_order that are out of
+    // range, duplicated or point to an empty profile are skipped, and profiles missing from it are
+    // appended in index order.
+    pub fn invited_list(&self) -> Vec<usize> {
+        let mut listed = [false; INVITED_LEN];
+        let mut list = vec![];
+        let count = (self.invited_count as usize).min(INVITED_LEN);
+        for &index in &self.invited_order[0..count] {
+            let index = index as usize;
+            if index < INVITED_LEN && !listed[index] && !self.invited[index].is_null() {
+                listed[index] = true;
+                list.push(index);
+            }
+        }
+        for (index, profile) in self.invited.iter().enumerate() {
+            if !listed[index] && !profile.is_null() {
+                list.push(index);
+            }
+        }
+        list
+    }
+
+    pub fn invited_order_is_consistent(&self) -> bool {
+        let list = self.invited_list();
+        self.invited_count as usize == list.len()
+            && self.invited_order[0..list.len()]
+                .iter()
+                .zip(list.iter())
+                .all(|(&a, &b)| a as usize == b)
+    }
+
+    pub(crate) fn set_invited_list(&mut self, list: &[usize]) {
+        let old_count = (self.invited_count as usize).min(INVITED_LEN);
+        let unused = self.invited_order.get(old_count).copied().unwrap_or(0xFF);
+        for (i, entry) in self.invited_order.iter_mut().enumerate() {
+            match list.get(i) {
+                Some(&index) => *entry = index as u8,
+                None if i < old_count => *entry = unused,
+                None => (),
+            }
+        }
+        self.invited_count = list.len() as u32;
+    }
+
+    // Rewrites invited_order and invited_count to match invited_list()
+    pub fn fix_invited_order(&mut self) {
+        let list = self.invited_list();
+        self.set_invited_list(&list);
+    }
+
+    fn invited_position(&self, index: usize) -> Result<(Vec<usize>, usize), Error> {
+        let list = self.invited_list();
+        let position = list
+            .iter()
+            .position(|&i| i == index)
+            .ok_or(Error::NullProfile)?;
+        Ok((list, position))
+    }
+
+    // Moves the invited profile at `index` to `position` in the display order. Positions past the
+    // end move it to the end.
+    pub fn move_invited(&mut self, index: usize, position: usize) -> Result<(), Error> {
+        let (mut list, old_position) = self.invited_position(index)?;
+        list.remove(old_position);
+        let position = position.min(list.len());
+        list.insert(position, index);
+        self.set_invited_list(&list);
+        Ok(())
+    }
+
+    pub fn remove_invited(&mut self, index: usize) -> Result<(), Error> {
+        let (mut list, position) = self.invited_position(index)?;
+        list.remove(position);
+        self.invited[index] = Profile::default();
+        self.set_invited_list(&list);
+        Ok(())
+    }
+}
+
+#[test]
+fn invited_test() {
+    let mut database = test_database();
+    for &index in &[2, 5, 7] {
+        database.invited[index] = test_profile(1, 0, 0);
+    }
+    database.invited_order.iter_mut().for_each(|i| *i = 0xFF);
+    database.invited_order[0..2].copy_from_slice(&[7, 2]);
+    database.invited_count = 2;
+    assert_eq!(database.invited_list(), vec![7, 2, 5]);
+    assert!(!database.invited_order_is_consistent());
+    database.fix_invited_order();
+    assert!(database.invited_order_is_consistent());
+    assert_eq!(database.invited_count, 3);
+    assert_eq!(&database.invited_order[0..4], &[7, 2, 5, 0xFF]);
+
+    database.move_invited(5, 0).unwrap();
+    assert_eq!(database.invited_list(), vec![5, 7, 2]);
+    database.move_invited(5, 10).unwrap();
+    assert_eq!(database.invited_list(), vec![7, 2, 5]);
+
+    database.remove_invited(7).unwrap();
+    assert!(database.invited[7].is_null());
+    assert_eq!(database.invited_count, 2);
+    assert_eq!(&database.invited_order[0..3], &[2, 5, 0xFF]);
+    assert!(database.invited_order_is_consistent());
+    assert!(matches!(
+        database.remove_invited(7),
+        Err(Error::NullProfile)
+    ));
+}
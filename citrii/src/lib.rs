@@ -0,0 +1,35 @@
+pub mod asset;
+pub mod asset_export;
+pub mod asset_import;
+pub mod cfhe;
+pub mod color;
+pub mod convert;
+pub mod crc;
+pub mod data_paths;
+pub mod database;
+pub mod diff;
+pub mod error;
+#[cfg(feature = "opengl")]
+pub mod framebuffer;
+pub mod head_renderer;
+pub mod history;
+pub mod invited;
+#[cfg(feature = "opengl")]
+pub mod model;
+pub mod name;
+pub mod ncch;
+pub mod profile_text;
+pub mod qr;
+mod qr_reader;
+pub mod random;
+pub mod romfs;
+pub mod save;
+pub mod schema;
+#[cfg(feature = "opengl")]
+pub mod shader;
+pub mod software_renderer;
+#[cfg(feature = "opengl")]
+pub mod texture;
+pub mod validation;
+
+pub use error::Error;
@@ -1,66 +1,32 @@
 #![allow(dead_code)]
 
-mod asset;
-mod color;
-mod crc;
-mod database;
-mod head_renderer;
-mod model;
 mod rect_renderer;
-mod romfs;
-mod shader;
 mod text_renderer;
-mod texture;
 mod ui;
 
+use citrii::{
+    asset, color, data_paths, database, head_renderer, history, name, ncch, random, romfs, save,
+    schema, texture, validation,
+};
+
 use std::cell::RefCell;
 use std::rc::Rc;
 
 use gl::types::*;
 use glutin::dpi::*;
-use glutin::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use glutin::event::{
+    ElementState, Event, KeyboardInput, ModifiersState, VirtualKeyCode, WindowEvent,
+};
 use glutin::event_loop::ControlFlow;
 
 use glutin::PossiblyCurrent;
 
-use byte_struct::*;
-
 use clipboard::ClipboardContext;
 use clipboard::ClipboardProvider;
 use glutin::window::Window;
 
-const PAGE_FACE: u8 = 0;
-const PAGE_MAKEUP: u8 = 1;
-const PAGE_WRINKLE: u8 = 2;
-const PAGE_HAIR: u8 = 3;
-const PAGE_EYEBROW: u8 = 4;
-const PAGE_EYE: u8 = 5;
-const PAGE_NOSE: u8 = 6;
-const PAGE_LIP: u8 = 7;
-const PAGE_GLASS: u8 = 8;
-const PAGE_MUSTACHE: u8 = 9;
-const PAGE_BEARD: u8 = 10;
-const PAGE_MOLE: u8 = 11;
-
-const PAGE_END: u8 = 12;
-
-const PAGE_NAMES: [&str; 12] = [
-    "Face",
-    "Makeup",
-    "Wrinkle",
-    "Hairstyle",
-    "Eyebrows",
-    "Eyes",
-    "Nose",
-    "Mouth",
-    "Glasses",
-    "Mustache",
-    "Goatee",
-    "Beauty mark",
-];
-
 const ID_PAGE_BUTTON_BEGIN: u32 = 100;
-const ID_PAGE_BUTTON_END: u32 = ID_PAGE_BUTTON_BEGIN + PAGE_END as u32;
+const ID_PAGE_BUTTON_END: u32 = ID_PAGE_BUTTON_BEGIN + schema::Page::ALL.len() as u32;
 const ID_STYLE_DEC: u32 = 200;
 const ID_STYLE_INC: u32 = 201;
 const ID_Y_DEC: u32 = 202;
@@ -74,6 +40,9 @@ const ID_SCALE_INC: u32 = 209;
 const ID_Y_SCALE_DEC: u32 = 210;
 const ID_Y_SCALE_INC: u32 = 211;
 const ID_FLIP_HAIR: u32 = 212;
+const ID_RANDOMIZE: u32 = 213;
+const ID_SPACING_DEC: u32 = 214;
+const ID_SPACING_INC: u32 = 215;
 const ID_PALETTE: u32 = 300;
 const ID_EXTRA_FOLD: u32 = 400;
 const ID_FAVORITE: u32 = 500;
@@ -96,59 +65,200 @@ const ID_PROFILE_RIGHT: u32 = 1101;
 const ID_PROFILE_UP: u32 = 1102;
 const ID_PROFILE_DOWN: u32 = 1103;
 const ID_PROFILE_DELETE: u32 = 1104;
+const ID_PROFILE_LIST_MODE: u32 = 1105;
+const ID_PROFILE_COPY: u32 = 1106;
+const ID_PROFILE_PAGE_PREV: u32 = 1107;
+const ID_PROFILE_PAGE_NEXT: u32 = 1108;
+const ID_CLOSE_SAVE: u32 = 1200;
+const ID_CLOSE_DISCARD: u32 = 1201;
+const ID_CLOSE_CANCEL: u32 = 1202;
+const ID_MESSAGE_OK: u32 = 1203;
+const ID_MESSAGE_REPAIR: u32 = 1204;
+const ID_MESSAGE_QUIT: u32 = 1205;
+
+// Events that change the selected profile. Encountered profiles are read-only and have to be
+// copied to an owned slot to be edited.
+fn is_profile_edit(id: u32) -> bool {
+    (ID_STYLE_DEC..=ID_SPACING_INC).contains(&id)
+        || id == ID_PALETTE
+        || (ID_FAVORITE..=ID_AUTHOR).contains(&id)
+        || (ID_BIRTHMONTH_BEGIN..ID_BIRTHDAY_BEGIN + 50).contains(&id)
+}
 
-enum Delta {
-    Inc,
-    Dec,
+// The list shown in the profile list
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ListMode {
+    Owned,
+    Invited,
+    Encountered,
 }
 
-fn wrap_change_value<
-    T: std::ops::AddAssign<T> + std::ops::SubAssign<T> + std::cmp::PartialOrd<T> + From<u8>,
->(
-    v: &mut T,
-    limit: T,
-    delta: Delta,
-) {
-    match delta {
-        Delta::Inc => {
-            *v += T::from(1u8);
-            if *v >= limit {
-                *v = T::from(0u8);
+// Size of the text of a message prompt
+const MESSAGE_LINES: usize = 5;
+const MESSAGE_LINE_LEN: usize = 56;
+
+// Splits a message into the lines of a message prompt. Lines past the last are left out.
+fn wrap_message(message: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for paragraph in message.lines() {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let len = line.chars().count();
+            if len != 0 && len + 1 + word.chars().count() > MESSAGE_LINE_LEN {
+                lines.push(std::mem::take(&mut line));
             }
-        }
-        Delta::Dec => {
-            if *v <= T::from(0u8) {
-                *v = limit;
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            // Words that don't fit on a line, such as paths, are broken anywhere
+            for c in word.chars() {
+                if line.chars().count() == MESSAGE_LINE_LEN {
+                    lines.push(std::mem::take(&mut line));
+                }
+                line.push(c);
             }
-            *v -= T::from(1u8);
         }
+        lines.push(line);
     }
+    lines.resize(MESSAGE_LINES, String::new());
+    lines
 }
 
-fn clamp_change_value<
-    T: std::ops::AddAssign<T> + std::ops::SubAssign<T> + std::cmp::PartialOrd<T> + From<u8>,
->(
-    v: &mut T,
-    min: T,
-    max: T,
-    delta: Delta,
-) {
-    match delta {
-        Delta::Inc => {
-            if *v >= max {
-                return;
-            }
-            *v += T::from(1u8);
+// A modal prompt with a message and some buttons, docked in the center of the window
+struct MessagePrompt {
+    layout: Rc<RefCell<ui::GridLayout>>,
+    labels: Vec<Rc<RefCell<ui::Label>>>,
+    buttons: Vec<(u32, Rc<RefCell<ui::Button>>)>,
+}
+
+impl MessagePrompt {
+    fn new(
+        buttons: &[(u32, &str)],
+        rect_renderer: &Rc<rect_renderer::RectRenderer>,
+        text_renderer: &Rc<text_renderer::TextRenderer>,
+    ) -> MessagePrompt {
+        let labels: Vec<Rc<RefCell<ui::Label>>> = (0..MESSAGE_LINES)
+            .map(|_| ui::Label::new(1.2, 0.04, "", text_renderer.clone()))
+            .collect();
+        let buttons: Vec<(u32, Rc<RefCell<ui::Button>>)> = buttons
+            .iter()
+            .map(|&(id, text)| {
+                let button = ui::Button::new(
+                    id,
+                    0.18,
+                    0.06,
+                    ui::ButtonContent::from_text(text),
+                    rect_renderer.clone(),
+                    text_renderer.clone(),
+                );
+                (id, button)
+            })
+            .collect();
+        let layout_buttons = ui::GridLayout::new(
+            buttons.len(),
+            1,
+            buttons
+                .iter()
+                .map(|(_, b)| -> Rc<RefCell<dyn ui::UIElement>> { b.clone() })
+                .collect(),
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            0.02,
+            0.0,
+            rect_renderer.clone(),
+        );
+        let mut children: Vec<Rc<RefCell<dyn ui::UIElement>>> = labels
+            .iter()
+            .cloned()
+            .map(|l| -> Rc<RefCell<dyn ui::UIElement>> { l })
+            .collect();
+        children.push(layout_buttons);
+        let layout = ui::GridLayout::new(
+            1,
+            MESSAGE_LINES + 1,
+            children,
+            0.03,
+            0.03,
+            0.03,
+            0.03,
+            0.01,
+            0.01,
+            rect_renderer.clone(),
+        );
+        layout.borrow_mut().set_visible(false);
+        layout.borrow_mut().set_color((1.0, 1.0, 1.0, 0.9));
+        MessagePrompt {
+            layout,
+            labels,
+            buttons,
         }
-        Delta::Dec => {
-            if *v <= min {
-                return;
-            }
-            *v -= T::from(1u8);
+    }
+
+    fn docker(&self) -> ui::Docker {
+        ui::Docker::new(self.layout.clone(), ui::XAlign::Center, ui::YAlign::Center)
+    }
+
+    fn is_visible(&self) -> bool {
+        self.layout.borrow().get_visible()
+    }
+
+    fn hide(&self) {
+        self.layout.borrow_mut().set_visible(false);
+    }
+
+    // Shows the message with the buttons of `button_ids`. The message is also printed.
+    fn show(&self, message: &str, button_ids: &[u32]) {
+        println!("{}", message);
+        for (label, line) in self.labels.iter().zip(wrap_message(message)) {
+            label.borrow_mut().set_text(&line);
         }
+        for (id, button) in &self.buttons {
+            button.borrow_mut().set_visible(button_ids.contains(id));
+        }
+        self.layout.borrow_mut().set_visible(true);
     }
 }
 
+fn create_gl_context(
+    event_loop: &glutin::event_loop::EventLoop<()>,
+) -> glutin::ContextWrapper<PossiblyCurrent, Window> {
+    let window = glutin::window::WindowBuilder::new()
+        .with_title("Citrii")
+        .with_inner_size(LogicalSize::new(800.0f32, 600.0f32));
+    let context = glutin::ContextBuilder::new()
+        .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
+        .with_gl_profile(glutin::GlProfile::Core)
+        .build_windowed(window, event_loop)
+        .unwrap();
+
+    let gl_context = unsafe { context.make_current().unwrap() };
+
+    unsafe {
+        gl::load_with(|ptr| gl_context.get_proc_address(ptr) as *const _);
+
+        if gl::DebugMessageCallback::is_loaded() {
+            gl::Enable(gl::DEBUG_OUTPUT);
+            gl::DebugMessageCallback(Some(gl_debug_message), std::ptr::null_mut());
+        }
+
+        gl::ClearColor(0.5, 1.0, 0.5, 1.0);
+        gl::ClearDepth(1.0);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+    }
+    gl_context
+}
+
+// Number of undo steps kept for each profile
+const HISTORY_LIMIT: usize = 100;
+
+enum Delta {
+    Inc,
+    Dec,
+}
+
 const BIRTH_DAY_RANGE: [(u16, u16); 13] = [
     (0, 0),
     (1, 31),
@@ -199,9 +309,17 @@ struct Main {
     rect_renderer: std::rc::Rc<rect_renderer::RectRenderer>,
     text_renderer: std::rc::Rc<text_renderer::TextRenderer>,
     database: database::Database,
-
-    profile_index: usize,
-    page: u8,
+    history: history::History,
+    // The history position of the database as last loaded or saved, to tell whether there are
+    // unsaved changes. None when the file doesn't match any state in the history.
+    saved_position: Option<u64>,
+    dirty: bool,
+    exit_requested: bool,
+
+    selection: database::ProfileRef,
+    list_mode: ListMode,
+    encountered_page: usize,
+    page: schema::Page,
 
     scene: ui::Scene,
     page_buttons: Vec<Rc<RefCell<ui::Button>>>,
@@ -235,8 +353,17 @@ struct Main {
     layout_extra: Rc<RefCell<ui::GridLayout>>,
     buttons_profile_list: Vec<Rc<RefCell<ui::Button>>>,
     button_profile_delete: Rc<RefCell<ui::Button>>,
+    button_list_mode: Rc<RefCell<ui::Button>>,
+    button_profile_copy: Rc<RefCell<ui::Button>>,
+    buttons_profile_move: Vec<Rc<RefCell<ui::Button>>>,
+    buttons_profile_page: Vec<Rc<RefCell<ui::Button>>>,
+    label_profile_info: Rc<RefCell<ui::Label>>,
     layout_profile_list: Rc<RefCell<ui::GridLayout>>,
     icon_new_profile: Rc<texture::Texture>,
+    layout_close_prompt: Rc<RefCell<ui::GridLayout>>,
+    message_prompt: MessagePrompt,
+    // Problems found when loading. The head is not rendered until they are repaired.
+    load_issues: Vec<validation::Issue>,
 }
 
 impl Main {
@@ -244,55 +371,30 @@ impl Main {
         asset_filename: std::path::PathBuf,
         database_filename: std::path::PathBuf,
         event_loop: &mut glutin::event_loop::EventLoop<()>,
-    ) -> Main {
+    ) -> Result<Main, citrii::Error> {
         let clipboard_context = ClipboardProvider::new()
             .map_err(|e| println!("Failed to get clipboard: {}", e))
             .ok();
 
-        let window = glutin::window::WindowBuilder::new()
-            .with_title("Citrii")
-            .with_inner_size(LogicalSize::new(800.0f32, 600.0f32));
-        let context = glutin::ContextBuilder::new()
-            .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
-            .with_gl_profile(glutin::GlProfile::Core)
-            .build_windowed(window, event_loop)
-            .unwrap();
+        let gl_context = create_gl_context(event_loop);
 
-        let gl_context = unsafe { context.make_current().unwrap() };
-
-        unsafe {
-            gl::load_with(|ptr| gl_context.get_proc_address(ptr) as *const _);
-
-            if gl::DebugMessageCallback::is_loaded() {
-                gl::Enable(gl::DEBUG_OUTPUT);
-                gl::DebugMessageCallback(Some(gl_debug_message), std::ptr::null_mut());
-            }
-
-            gl::ClearColor(0.5, 1.0, 0.5, 1.0);
-            gl::ClearDepth(1.0);
-            gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-        }
-
-        let asset_romfs = std::fs::read(asset_filename).expect("Unable to read RomFS");
-        let asset_data = romfs::get_romfs_file(&asset_romfs, &["CFL_Res.dat".to_string()])
-            .expect("The provided RomFS is corrupted");
-        let asset =
-            asset::Asset::from_bytes(asset_data).expect("The provided CFL_Res.dat is corrupted");
+        let asset_file = std::fs::read(asset_filename)?;
+        let asset_romfs = ncch::find_romfs(&asset_file)?;
+        let asset_data = romfs::get_romfs_file(asset_romfs, &["CFL_Res.dat".to_string()])?;
+        let asset = asset::Asset::from_bytes(asset_data)?;
         let head_renderer = head_renderer::HeadRenderer::with_asset(asset);
 
-        let database_data = std::fs::read(&database_filename).expect("Unable to read CFL_Res.dat");
-        let crc_a = crc::crc16_ninty(&database_data[0..0xC81E]);
-        assert_eq!(
-            crc_a,
-            u16::from_be_bytes([database_data[0xC81E], database_data[0xC81F]])
-        );
-        let crc_b = crc::crc16_ninty(&database_data[0xC820..0xE4BE]);
-        assert_eq!(
-            crc_b,
-            u16::from_be_bytes([database_data[0xE4BE], database_data[0xE4BF]])
-        );
-        let database = database::Database::read_bytes(&database_data[..]);
+        // A damaged database is opened anyway, so that it can be repaired from the window
+        let database_data = std::fs::read(&database_filename)?;
+        let load_issues = validation::validate_bytes(&database_data[..])?;
+        let database = database::Database::from_bytes_unchecked(&database_data[..])?;
+        let history = history::History::new(HISTORY_LIMIT);
+        // Saving a damaged database changes the file, even if only the CRCs are fixed
+        let saved_position = if load_issues.is_empty() {
+            Some(history.position())
+        } else {
+            None
+        };
 
         let rect_renderer = std::rc::Rc::new(rect_renderer::RectRenderer::new());
         let text_renderer =
@@ -300,12 +402,12 @@ impl Main {
 
         let mut page_buttons: Vec<Rc<RefCell<ui::Button>>> = vec![];
 
-        for i in 0..PAGE_END {
+        for (i, page) in schema::Page::ALL.iter().enumerate() {
             let button = ui::Button::new(
                 ID_PAGE_BUTTON_BEGIN + i as u32,
                 0.35,
                 0.05,
-                ui::ButtonContent::from_text(PAGE_NAMES[i as usize]),
+                ui::ButtonContent::from_text(page.name()),
                 rect_renderer.clone(),
                 text_renderer.clone(),
             );
@@ -314,7 +416,7 @@ impl Main {
 
         let layout_pages = ui::GridLayout::new(
             1,
-            PAGE_END as usize,
+            schema::Page::ALL.len(),
             page_buttons
                 .iter()
                 .cloned()
@@ -385,7 +487,7 @@ impl Main {
         );
 
         let button_x_space_dec = ui::Button::new(
-            ID_X_DEC,
+            ID_SPACING_DEC,
             0.1,
             0.1,
             ui::ButtonContent::from_image(include_bytes!("icon/x-scale-down.png")),
@@ -394,7 +496,7 @@ impl Main {
         );
 
         let button_x_space_inc = ui::Button::new(
-            ID_X_INC,
+            ID_SPACING_INC,
             0.1,
             0.1,
             ui::ButtonContent::from_image(include_bytes!("icon/x-scale-up.png")),
@@ -465,6 +567,15 @@ impl Main {
             text_renderer.clone(),
         );
 
+        let button_randomize = ui::Button::new(
+            ID_RANDOMIZE,
+            0.1,
+            0.1,
+            ui::ButtonContent::from_text("?"),
+            rect_renderer.clone(),
+            text_renderer.clone(),
+        );
+
         let layout_controls = ui::GridLayout::new(
             2,
             8,
@@ -484,7 +595,7 @@ impl Main {
                 button_scale_inc.clone(),
                 button_scale_dec.clone(),
                 button_flip_hair.clone(),
-                ui::Placeholder::new(),
+                button_randomize,
             ],
             0.02,
             0.02,
@@ -922,16 +1033,70 @@ impl Main {
             text_renderer.clone(),
         );
 
+        let button_list_mode = ui::Button::new(
+            ID_PROFILE_LIST_MODE,
+            0.18,
+            0.07,
+            ui::ButtonContent::from_text("Invited"),
+            rect_renderer.clone(),
+            text_renderer.clone(),
+        );
+
+        let button_profile_copy = ui::Button::new(
+            ID_PROFILE_COPY,
+            0.18,
+            0.07,
+            ui::ButtonContent::from_text("Copy"),
+            rect_renderer.clone(),
+            text_renderer.clone(),
+        );
+        button_profile_copy.borrow_mut().set_visible(false);
+
+        let buttons_profile_page: Vec<Rc<RefCell<ui::Button>>> =
+            [(ID_PROFILE_PAGE_PREV, "<"), (ID_PROFILE_PAGE_NEXT, ">")]
+                .iter()
+                .map(|&(id, text)| {
+                    let button = ui::Button::new(
+                        id,
+                        0.07,
+                        0.07,
+                        ui::ButtonContent::from_text(text),
+                        rect_renderer.clone(),
+                        text_renderer.clone(),
+                    );
+                    button.borrow_mut().set_visible(false);
+                    button
+                })
+                .collect();
+
+        let buttons_profile_move = vec![
+            button_profile_left,
+            button_profile_right,
+            button_profile_up,
+            button_profile_down,
+        ];
+
+        let mut controls: Vec<Rc<RefCell<dyn ui::UIElement>>> = vec![];
+        controls.extend(
+            buttons_profile_move
+                .iter()
+                .cloned()
+                .map(|m| -> Rc<RefCell<dyn ui::UIElement>> { m }),
+        );
+        controls.push(button_profile_delete.clone());
+        controls.push(button_profile_copy.clone());
+        controls.extend(
+            buttons_profile_page
+                .iter()
+                .cloned()
+                .map(|m| -> Rc<RefCell<dyn ui::UIElement>> { m }),
+        );
+        controls.push(button_list_mode.clone());
+
         let layout_profile_list_control = ui::GridLayout::new(
-            5,
+            9,
             1,
-            vec![
-                button_profile_left,
-                button_profile_right,
-                button_profile_up,
-                button_profile_down,
-                button_profile_delete.clone(),
-            ],
+            controls,
             0.01,
             0.01,
             0.01,
@@ -941,10 +1106,16 @@ impl Main {
             rect_renderer.clone(),
         );
 
+        let label_profile_info = ui::Label::new(0.6, 0.04, "", text_renderer.clone());
+
         let layout_profile_list = ui::GridLayout::new(
             1,
-            2,
-            vec![layout_profile_list_inner, layout_profile_list_control],
+            3,
+            vec![
+                layout_profile_list_inner,
+                layout_profile_list_control,
+                label_profile_info.clone(),
+            ],
             0.0,
             0.0,
             0.0,
@@ -987,17 +1158,86 @@ impl Main {
             ui::YAlign::Bottom,
         );
 
+        let label_close_prompt = ui::Label::new(
+            0.6,
+            0.05,
+            "Save changes before closing?",
+            text_renderer.clone(),
+        );
+        let buttons_close_prompt: Vec<Rc<RefCell<dyn ui::UIElement>>> = [
+            (ID_CLOSE_SAVE, "Save"),
+            (ID_CLOSE_DISCARD, "Discard"),
+            (ID_CLOSE_CANCEL, "Cancel"),
+        ]
+        .iter()
+        .map(|&(id, text)| -> Rc<RefCell<dyn ui::UIElement>> {
+            ui::Button::new(
+                id,
+                0.18,
+                0.06,
+                ui::ButtonContent::from_text(text),
+                rect_renderer.clone(),
+                text_renderer.clone(),
+            )
+        })
+        .collect();
+        let layout_close_buttons = ui::GridLayout::new(
+            3,
+            1,
+            buttons_close_prompt,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            0.02,
+            0.0,
+            rect_renderer.clone(),
+        );
+        let layout_close_prompt = ui::GridLayout::new(
+            1,
+            2,
+            vec![label_close_prompt, layout_close_buttons],
+            0.03,
+            0.03,
+            0.03,
+            0.03,
+            0.03,
+            0.03,
+            rect_renderer.clone(),
+        );
+        layout_close_prompt.borrow_mut().set_visible(false);
+        layout_close_prompt
+            .borrow_mut()
+            .set_color((1.0, 1.0, 1.0, 0.9));
+        let docker_close_prompt = ui::Docker::new(
+            layout_close_prompt.clone(),
+            ui::XAlign::Center,
+            ui::YAlign::Center,
+        );
+
+        let message_prompt = MessagePrompt::new(
+            &[
+                (ID_MESSAGE_OK, "OK"),
+                (ID_MESSAGE_REPAIR, "Repair"),
+                (ID_MESSAGE_QUIT, "Quit"),
+            ],
+            &rect_renderer,
+            &text_renderer,
+        );
+
         let scene = ui::Scene::new(vec![
             docker_pages,
             docker_controls,
             docker_extra,
             docker_save,
             docker_profile_list,
+            docker_close_prompt,
+            message_prompt.docker(),
         ]);
 
         let icon_new_profile = Rc::new(texture::Texture::from_png(include_bytes!("icon/new.png")));
 
-        Main {
+        Ok(Main {
             clipboard_context,
             gl_context,
             database_filename,
@@ -1005,8 +1245,10 @@ impl Main {
             rect_renderer,
             text_renderer,
             database,
-            profile_index: 0,
-            page: 0,
+            selection: database::ProfileRef::Owned(0),
+            list_mode: ListMode::Owned,
+            encountered_page: 0,
+            page: schema::Page::Face,
             scene,
             page_buttons,
             palette,
@@ -1038,66 +1280,147 @@ impl Main {
             layout_extra,
             buttons_profile_list,
             button_profile_delete,
+            button_list_mode,
+            button_profile_copy,
+            buttons_profile_move,
+            buttons_profile_page,
+            label_profile_info,
             layout_profile_list,
             icon_new_profile,
-        }
+            history,
+            saved_position,
+            dirty: false,
+            exit_requested: false,
+            layout_close_prompt,
+            message_prompt,
+            load_issues,
+        })
     }
 
-    fn update_profile_list(&self) {
-        let mut slot_map: [Option<usize>; 100] = [None; 100];
-        for (i, profile) in self.database.owned.iter().enumerate() {
-            if profile.main.is_null() {
-                continue;
+    // The profiles shown in the buttons of the profile list. Owned profiles are shown in their
+    // slots, invited profiles in their order, and encountered profiles in their order one page at
+    // a time.
+    fn profile_list_entries(&self) -> Vec<Option<database::ProfileRef>> {
+        let mut entries = vec![None; 100];
+        match self.list_mode {
+            ListMode::Owned => {
+                for (i, profile) in self.database.owned.iter().enumerate() {
+                    if profile.main.is_null() {
+                        continue;
+                    }
+                    // Profiles in an invalid or taken slot are left out until repaired
+                    if let Some(slot) = profile.main.try_get_slot() {
+                        if entries[slot].is_none() {
+                            entries[slot] = Some(database::ProfileRef::Owned(i));
+                        }
+                    }
+                }
+            }
+            ListMode::Invited => {
+                for (slot, index) in self.database.invited_list().into_iter().enumerate() {
+                    entries[slot] = Some(database::ProfileRef::Invited(index));
+                }
+            }
+            ListMode::Encountered => {
+                let order = self.database.cfhe_order();
+                let page = order.chunks(100).nth(self.encountered_page).unwrap_or(&[]);
+                for (slot, &node) in page.iter().enumerate() {
+                    entries[slot] = Some(database::ProfileRef::Encountered(node));
+                }
             }
-            let slot = profile.main.get_slot();
-            assert!(slot_map[slot] == None);
-            slot_map[slot] = Some(i);
         }
+        entries
+    }
 
-        for (j, slot) in slot_map.iter().enumerate() {
+    fn update_profile_list(&self) {
+        for (j, slot) in self.profile_list_entries().iter().enumerate() {
             let mut button = self.buttons_profile_list[j].borrow_mut();
             button.set_content(match slot {
                 None => ui::ButtonContent::from_text(""),
-                Some(i) => ui::ButtonContent::from_text(&String::from_utf16_lossy(&[self
-                    .database
-                    .owned[*i]
-                    .main
-                    .name[0]])),
+                Some(profile) => {
+                    let name = name::MiiName(self.database.profile(*profile).name);
+                    let initial = name.to_string().chars().next().map(String::from);
+                    ui::ButtonContent::from_text(&initial.unwrap_or_default())
+                }
             });
 
-            button.set_selected(match slot {
-                None => false,
-                Some(i) => *i == self.profile_index,
-            });
+            button.set_selected(*slot == Some(self.selection));
 
             button.set_hover_image(match slot {
-                None => Some(self.icon_new_profile.clone()),
-                Some(_) => None,
+                None if self.list_mode == ListMode::Owned => Some(self.icon_new_profile.clone()),
+                _ => None,
             })
         }
 
-        self.button_profile_delete
+        let in_list = match (self.list_mode, self.selection) {
+            (ListMode::Owned, database::ProfileRef::Owned(index)) => index != 0,
+            (ListMode::Invited, database::ProfileRef::Invited(_)) => true,
+            (ListMode::Encountered, database::ProfileRef::Encountered(_)) => true,
+            _ => false,
+        };
+        self.button_profile_delete.borrow_mut().set_visible(in_list);
+        self.button_profile_copy
             .borrow_mut()
-            .set_visible(self.profile_index != 0);
+            .set_visible(in_list && self.list_mode == ListMode::Encountered);
+        for button in &self.buttons_profile_move {
+            button
+                .borrow_mut()
+                .set_visible(self.list_mode != ListMode::Encountered);
+        }
+        for button in &self.buttons_profile_page {
+            button
+                .borrow_mut()
+                .set_visible(self.list_mode == ListMode::Encountered);
+        }
+
+        self.update_profile_info();
     }
 
-    fn update_profile_extra(&self) {
-        fn name_to_text(name: &[u16]) -> String {
-            let end = name
-                .iter()
-                .position(|&v| v == 0)
-                .unwrap_or_else(|| name.len());
-            String::from_utf16_lossy(&name[0..end])
+    // Shows the page of the encountered list, and any character of the name or the author that
+    // the font of the profile can't display
+    fn update_profile_info(&self) {
+        let mut info = vec![];
+        if self.list_mode == ListMode::Encountered {
+            let pages = ((self.database.cfhe_order().len() + 99) / 100).max(1);
+            let mut page = format!("Page {}/{}", self.encountered_page + 1, pages);
+            if let database::ProfileRef::Encountered(node) = self.selection {
+                page += &format!(
+                    ", met on {}",
+                    self.database.cfhe_time(node).format("%Y-%m-%d %H:%M:%S")
+                );
+            }
+            info.push(page);
+        }
+        let profile = self.database.profile(self.selection);
+        let char_set = name::CharSet::of(profile);
+        let mut names = vec![("Name", profile.name)];
+        if let database::ProfileRef::Owned(index) = self.selection {
+            names.push(("Author", self.database.owned[index].author));
         }
+        for (field, name) in names {
+            for issue in name::MiiName(name).issues(char_set) {
+                info.push(format!("{}: {}", field, issue));
+            }
+        }
+        self.label_profile_info
+            .borrow_mut()
+            .set_text(&info.join("; "));
+    }
 
-        let profile_ex = &self.database.owned[self.profile_index];
-        let profile = &profile_ex.main;
+    fn update_profile_extra(&self) {
+        let profile = self.database.profile(self.selection);
+        // Only owned profiles have an author
+        let author = match self.selection {
+            database::ProfileRef::Owned(index) => self.database.owned[index].author,
+            _ => [0; 10],
+        };
         self.edit_name
             .borrow_mut()
-            .set_text(name_to_text(&profile.name[..]));
+            .set_text(name::MiiName(profile.name).to_string());
         self.edit_author
             .borrow_mut()
-            .set_text(name_to_text(&profile_ex.author[..]));
+            .set_text(name::MiiName(author).to_string());
+        self.update_profile_info();
 
         for (i, button) in self.buttons_birthmonth.iter().enumerate() {
             button
@@ -1137,137 +1460,150 @@ impl Main {
             .set_selected(profile.general.favorite_color as usize);
     }
 
-    fn on_style_change(&mut self, delta: Delta) {
-        let profile = &mut self.database.owned[self.profile_index].main;
-        match self.page {
-            PAGE_FACE => wrap_change_value(&mut profile.face.style, 12, delta),
-            PAGE_MAKEUP => wrap_change_value(&mut profile.face.makeup, 12, delta),
-            PAGE_WRINKLE => wrap_change_value(&mut profile.face.wrinkle, 12, delta),
-            PAGE_HAIR => wrap_change_value(&mut profile.hair.style, 132, delta),
-            PAGE_EYEBROW => wrap_change_value(&mut profile.eyebrow.style, 24, delta),
-            PAGE_EYE => wrap_change_value(&mut profile.eye.style, 62, delta),
-            PAGE_NOSE => wrap_change_value(&mut profile.nose.style, 18, delta),
-            PAGE_LIP => wrap_change_value(&mut profile.lip.style, 37, delta),
-            PAGE_GLASS => wrap_change_value(&mut profile.glass.style, 9, delta),
-            PAGE_MUSTACHE => wrap_change_value(&mut profile.misc.mustache_style, 6, delta),
-            PAGE_MOLE => wrap_change_value(&mut profile.mole.style, 2, delta),
-            PAGE_BEARD => wrap_change_value(&mut profile.beard.style, 6, delta),
-            _ => (),
-        }
-    }
-
-    fn on_color_change(&mut self, delta: Delta) {
-        let profile = &mut self.database.owned[self.profile_index].main;
-        match self.page {
-            PAGE_FACE => wrap_change_value(&mut profile.face.color, 6, delta),
-            PAGE_HAIR => wrap_change_value(&mut profile.hair.color, 8, delta),
-            PAGE_EYEBROW => wrap_change_value(&mut profile.eyebrow.color, 8, delta),
-            PAGE_EYE => wrap_change_value(&mut profile.eye.color, 6, delta),
-            PAGE_LIP => wrap_change_value(&mut profile.lip.color, 5, delta),
-            PAGE_GLASS => wrap_change_value(&mut profile.glass.color, 6, delta),
-            PAGE_MUSTACHE | PAGE_BEARD => wrap_change_value(&mut profile.beard.color, 8, delta),
-            _ => (),
+    // Steps the field that the control changes on the current page
+    fn on_control_change(&mut self, control: schema::Control, delta: Delta) {
+        if let Some(param) = schema::page_param(self.page, control) {
+            let profile = self.database.profile_mut(self.selection);
+            let value = param.step(param.get(profile), matches!(delta, Delta::Inc));
+            param.set(profile, value);
         }
     }
 
     fn on_color_change_from_palette(&mut self) {
-        let profile = &mut self.database.owned[self.profile_index].main;
-        let color = self.palette.borrow().get_selected();
-        match self.page {
-            PAGE_FACE => profile.face.color = color as u16,
-            PAGE_HAIR => profile.hair.color = color as u16,
-            PAGE_EYEBROW => profile.eyebrow.color = color as u32,
-            PAGE_EYE => profile.eye.color = color as u32,
-            PAGE_LIP => profile.lip.color = color as u16,
-            PAGE_GLASS => profile.glass.color = color as u16,
-            PAGE_MUSTACHE | PAGE_BEARD => profile.beard.color = color as u16,
-            _ => (),
+        if let Some(param) = schema::page_param(self.page, schema::Control::Color) {
+            let color = self.palette.borrow().get_selected();
+            param.set(self.database.profile_mut(self.selection), color as u32);
         }
     }
 
-    fn on_scale_change(&mut self, delta: Delta) {
-        let profile = &mut self.database.owned[self.profile_index].main;
-        match self.page {
-            PAGE_EYEBROW => clamp_change_value(&mut profile.eyebrow.scale, 0, 8, delta),
-            PAGE_EYE => clamp_change_value(&mut profile.eye.scale, 0, 7, delta),
-            PAGE_NOSE => clamp_change_value(&mut profile.nose.scale, 0, 8, delta),
-            PAGE_LIP => clamp_change_value(&mut profile.lip.scale, 0, 8, delta),
-            PAGE_GLASS => clamp_change_value(&mut profile.glass.scale, 0, 7, delta),
-            PAGE_MUSTACHE => clamp_change_value(&mut profile.beard.mustache_scale, 0, 8, delta),
-            PAGE_MOLE => clamp_change_value(&mut profile.mole.scale, 0, 8, delta),
-            _ => (),
-        }
+    // Randomizes the appearance of the selected profile, keeping its sex. The seed is printed so
+    // that the result can be reproduced with the CLI.
+    fn on_randomize(&mut self) {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        let profile = self.database.profile_mut(self.selection);
+        let constraints = random::RandomConstraints {
+            sex: Some(profile.general.sex as u32),
+            ..random::RandomConstraints::default()
+        };
+        random::randomize(profile, seed, &constraints);
+        println!("Randomized with seed {}", seed);
+        self.update_profile_extra();
+        self.on_page_change(self.page);
     }
 
-    fn on_y_scale_change(&mut self, delta: Delta) {
-        let profile = &mut self.database.owned[self.profile_index].main;
-        match self.page {
-            PAGE_EYEBROW => clamp_change_value(&mut profile.eyebrow.y_scale, 0, 6, delta),
-            PAGE_EYE => clamp_change_value(&mut profile.eye.y_scale, 0, 6, delta),
-            PAGE_LIP => clamp_change_value(&mut profile.lip.y_scale, 0, 6, delta),
-            _ => (),
+    // Asks whether to repair the problems found when loading, or to quit
+    fn show_load_issues(&mut self) {
+        let mut message = format!(
+            "{} has {} problems:",
+            self.database_filename.display(),
+            self.load_issues.len()
+        );
+        for issue in &self.load_issues {
+            message += &format!("\n{}", issue);
         }
+        self.message_prompt
+            .show(&message, &[ID_MESSAGE_REPAIR, ID_MESSAGE_QUIT]);
     }
 
-    fn on_rotation_change(&mut self, delta: Delta) {
-        let profile = &mut self.database.owned[self.profile_index].main;
-        match self.page {
-            PAGE_EYEBROW => clamp_change_value(&mut profile.eyebrow.rotation, 0, 11, delta),
-            PAGE_EYE => clamp_change_value(&mut profile.eye.rotation, 0, 7, delta),
-            _ => (),
+    // Repairs the problems found when loading. Undoing would bring the damaged data back, so the
+    // history starts over from the repaired database, which counts as unsaved.
+    fn on_repair(&mut self) {
+        let fixed = self.database.repair();
+        let remaining = self.database.validate();
+        self.load_issues.clear();
+        self.history.clear();
+        self.saved_position = None;
+        self.update_dirty();
+        self.selection = database::ProfileRef::Owned(0);
+        self.update_profile_extra();
+        self.update_profile_list();
+        self.on_page_change(self.page);
+        let mut message = format!(
+            "Repaired {} problems. Save to keep the changes.",
+            fixed.len()
+        );
+        if !remaining.is_empty() {
+            message += &format!("\n{} problems can't be repaired:", remaining.len());
+            for issue in &remaining {
+                message += &format!("\n{}", issue);
+            }
         }
+        self.message_prompt.show(&message, &[ID_MESSAGE_OK]);
     }
 
-    fn on_x_change(&mut self, delta: Delta) {
-        let profile = &mut self.database.owned[self.profile_index].main;
-        match self.page {
-            PAGE_EYEBROW => clamp_change_value(&mut profile.eyebrow.x, 0, 12, delta),
-            PAGE_EYE => clamp_change_value(&mut profile.eye.x, 0, 12, delta),
-            PAGE_MOLE => clamp_change_value(&mut profile.mole.x, 0, 0x10, delta),
-            _ => (),
+    // Marks the window title while there are unsaved changes
+    fn update_dirty(&mut self) {
+        let dirty = self.saved_position != Some(self.history.position());
+        if dirty != self.dirty {
+            self.dirty = dirty;
+            self.gl_context
+                .window()
+                .set_title(if dirty { "Citrii *" } else { "Citrii" });
         }
     }
 
-    fn on_y_change(&mut self, delta: Delta) {
-        let profile = &mut self.database.owned[self.profile_index].main;
-        match self.page {
-            PAGE_EYEBROW => clamp_change_value(&mut profile.eyebrow.y, 3, 18, delta),
-            PAGE_EYE => clamp_change_value(&mut profile.eye.y, 0, 18, delta),
-            PAGE_NOSE => clamp_change_value(&mut profile.nose.y, 0, 18, delta),
-            PAGE_LIP => clamp_change_value(&mut profile.misc.lip_y, 0, 18, delta),
-            PAGE_GLASS => clamp_change_value(&mut profile.glass.y, 0, 20, delta),
-            PAGE_MUSTACHE => clamp_change_value(&mut profile.beard.mustache_y, 0, 16, delta),
-            PAGE_MOLE => clamp_change_value(&mut profile.mole.y, 0, 30, delta),
-            _ => (),
+    fn on_save(&mut self) -> bool {
+        let data = self.database.to_bytes();
+        match save::save_with_backup(&self.database_filename, &data, save::BACKUP_COUNT) {
+            Ok(backup) => {
+                let mut message = format!("Saved to {}", self.database_filename.display());
+                if let Some(backup) = backup {
+                    message += &format!("\nPrevious database backed up to {}", backup.display());
+                }
+                self.message_prompt.show(&message, &[ID_MESSAGE_OK]);
+                self.saved_position = Some(self.history.position());
+                self.update_dirty();
+                true
+            }
+            Err(e) => {
+                self.message_prompt
+                    .show(&format!("Failed to save: {}", e), &[ID_MESSAGE_OK]);
+                false
+            }
         }
     }
 
-    fn on_save(&self) {
-        let mut database_data = vec![0u8; database::Database::BYTE_LEN];
-        self.database.write_bytes(&mut database_data[..]);
-        let crc_a = crc::crc16_ninty(&database_data[0..0xC81E]).to_be_bytes();
-        database_data[0xC81E..0xC820].copy_from_slice(&crc_a);
-        let crc_b = crc::crc16_ninty(&database_data[0xC820..0xE4BE]).to_be_bytes();
-        database_data[0xE4BE..0xE4C0].copy_from_slice(&crc_b);
-        match std::fs::write(&self.database_filename, &database_data) {
-            Ok(()) => println!("Saved"),
-            Err(e) => println!("Failed to save: {}", e),
-        };
+    // Returns whether the window can be closed right away. Otherwise, asks whether to save first.
+    fn on_close_requested(&mut self) -> bool {
+        // Closing instead of repairing is the same as quitting
+        if !self.load_issues.is_empty() {
+            std::process::exit(1);
+        }
+        if !self.dirty {
+            return true;
+        }
+        self.message_prompt.hide();
+        self.layout_extra.borrow_mut().set_visible(false);
+        self.layout_profile_list.borrow_mut().set_visible(false);
+        self.layout_close_prompt.borrow_mut().set_visible(true);
+        false
     }
 
-    fn get_string_from_clipboard(&mut self) -> Option<[u16; 10]> {
+    // Reads a name for the selected profile from the clipboard. Text that doesn't fit or can't be
+    // displayed with the character set of the profile is rejected, with the reasons shown.
+    fn get_name_from_clipboard(&mut self) -> Option<[u16; 10]> {
+        let char_set = name::CharSet::of(self.database.profile(self.selection));
         if let Some(c) = &mut self.clipboard_context {
             match c.get_contents() {
                 Ok(s) => {
-                    let v: Vec<u16> = s
-                        .encode_utf16()
-                        .chain(std::iter::repeat(0))
-                        .take(10)
-                        .collect();
-                    Some([v[0], v[1], v[2], v[3], v[4], v[5], v[6], v[7], v[8], v[9]])
+                    let (name, issues) =
+                        name::MiiName::parse(s.trim_end_matches(&['\r', '\n'][..]), char_set);
+                    if issues.is_empty() {
+                        return Some(name.0);
+                    }
+                    let mut message = "The name was not pasted:".to_string();
+                    for issue in issues {
+                        message += &format!("\n{}", issue);
+                    }
+                    self.message_prompt.show(&message, &[ID_MESSAGE_OK]);
+                    None
                 }
                 Err(e) => {
-                    println!("Clipboard error: {}", e);
+                    self.message_prompt
+                        .show(&format!("Clipboard error: {}", e), &[ID_MESSAGE_OK]);
                     None
                 }
             }
@@ -1276,26 +1612,19 @@ impl Main {
         }
     }
 
-    fn on_page_change(&mut self, page: u8) {
+    fn on_page_change(&mut self, page: schema::Page) {
         self.page = page;
-        for i in 0..PAGE_END {
-            self.page_buttons[i as usize]
-                .borrow_mut()
-                .set_selected(i == page);
+        for (button, &p) in self.page_buttons.iter().zip(schema::Page::ALL.iter()) {
+            button.borrow_mut().set_selected(p == page);
         }
 
-        let enable_y = page == PAGE_EYEBROW
-            || page == PAGE_EYE
-            || page == PAGE_NOSE
-            || page == PAGE_LIP
-            || page == PAGE_GLASS
-            || page == PAGE_MUSTACHE
-            || page == PAGE_MOLE;
-        let enable_x = page == PAGE_MOLE;
-        let enable_x_space = page == PAGE_EYEBROW || page == PAGE_EYE;
-        let enable_rotation = page == PAGE_EYEBROW || page == PAGE_EYE;
-        let enable_scale = enable_y;
-        let enable_y_scale = page == PAGE_EYEBROW || page == PAGE_EYE || page == PAGE_LIP;
+        let enabled = |control| schema::page_param(page, control).is_some();
+        let enable_y = enabled(schema::Control::Y);
+        let enable_x = enabled(schema::Control::X);
+        let enable_x_space = enabled(schema::Control::Spacing);
+        let enable_rotation = enabled(schema::Control::Rotation);
+        let enable_scale = enabled(schema::Control::Scale);
+        let enable_y_scale = enabled(schema::Control::YScale);
 
         self.button_y_dec.borrow_mut().set_visible(enable_y);
         self.button_y_inc.borrow_mut().set_visible(enable_y);
@@ -1323,95 +1652,218 @@ impl Main {
             .set_visible(enable_y_scale);
         self.button_flip_hair
             .borrow_mut()
-            .set_visible(page == PAGE_HAIR);
+            .set_visible(enabled(schema::Control::Flip));
 
-        let profile = &self.database.owned[self.profile_index];
+        let profile = self.database.profile(self.selection);
         let mut palette = self.palette.borrow_mut();
-        match page {
-            PAGE_FACE => {
-                palette.set_colors(color::SKIN_COLOR_TABLE.to_vec());
-                palette.set_selected(profile.main.face.color as usize);
-            }
-            PAGE_MAKEUP => {
-                palette.set_colors(vec![]);
-            }
-            PAGE_WRINKLE => {
-                palette.set_colors(vec![]);
-            }
-            PAGE_HAIR => {
-                palette.set_colors(color::HAIR_COLOR_TABLE.to_vec());
-                palette.set_selected(profile.main.hair.color as usize);
-            }
-            PAGE_EYEBROW => {
-                palette.set_colors(color::HAIR_COLOR_TABLE.to_vec());
-                palette.set_selected(profile.main.eyebrow.color as usize);
-            }
-            PAGE_EYE => {
-                palette.set_colors(color::EYE_COLOR_TABLE.to_vec());
-                palette.set_selected(profile.main.eye.color as usize);
-            }
-            PAGE_NOSE => {
-                palette.set_colors(vec![]);
+        let color = schema::page_param(page, schema::Control::Color);
+        match color.and_then(|param| param.colors.map(|colors| (param, colors))) {
+            Some((param, colors)) => {
+                palette.set_colors(colors.to_vec());
+                palette.set_selected(param.get(profile) as usize);
             }
-            PAGE_LIP => {
-                palette.set_colors(color::LIP_COLOR_TABLE.to_vec());
-                palette.set_selected(profile.main.lip.color as usize);
-            }
-            PAGE_GLASS => {
-                palette.set_colors(color::GLASS_COLOR_TABLE.to_vec());
-                palette.set_selected(profile.main.glass.color as usize);
+            None => palette.set_colors(vec![]),
+        }
+    }
+
+    // Moves the selected profile to another slot, or to another position in the invited list
+    fn on_slot_move(&mut self, modifier: fn(usize) -> Option<usize>) {
+        match (self.list_mode, self.selection) {
+            (ListMode::Owned, database::ProfileRef::Owned(index)) => {
+                let slot = match self.database.owned[index].main.try_get_slot() {
+                    Some(slot) => slot,
+                    None => return,
+                };
+                if let Some(other_slot) = modifier(slot) {
+                    self.database.swap_owned_slots(slot, other_slot);
+                }
             }
-            PAGE_MUSTACHE => {
-                palette.set_colors(color::HAIR_COLOR_TABLE.to_vec());
-                palette.set_selected(profile.main.hair.color as usize);
+            (ListMode::Invited, database::ProfileRef::Invited(index)) => {
+                let list = self.database.invited_list();
+                let position = list.iter().position(|&i| i == index).unwrap();
+                if let Some(position) = modifier(position).filter(|&p| p < list.len()) {
+                    self.database.move_invited(index, position).unwrap();
+                }
             }
-            PAGE_BEARD => {
-                palette.set_colors(color::HAIR_COLOR_TABLE.to_vec());
-                palette.set_selected(profile.main.hair.color as usize);
+            _ => return,
+        }
+        self.update_profile_list();
+    }
+
+    fn on_profile_delete(&mut self) {
+        match self.selection {
+            database::ProfileRef::Owned(index) => {
+                self.database.owned[index] = database::ProfileFull::default();
+                self.selection = database::ProfileRef::Owned(0);
             }
-            PAGE_MOLE => {
-                palette.set_colors(vec![]);
+            database::ProfileRef::Invited(index) => {
+                let list = self.database.invited_list();
+                let position = list.iter().position(|&i| i == index).unwrap();
+                self.database.remove_invited(index).unwrap();
+                // Selects the next invited profile, or the personal profile if none is left
+                let list = self.database.invited_list();
+                self.selection = match list.get(position).or_else(|| list.last()) {
+                    Some(&next) => database::ProfileRef::Invited(next),
+                    None => database::ProfileRef::Owned(0),
+                };
             }
-            _ => {
-                palette.set_colors(vec![]);
+            database::ProfileRef::Encountered(node) => {
+                let order = self.database.cfhe_order();
+                let position = order.iter().position(|&n| n == node).unwrap();
+                self.database.remove_cfhe(node).unwrap();
+                let order = self.database.cfhe_order();
+                self.selection = match order.get(position).or_else(|| order.last()) {
+                    Some(&next) => database::ProfileRef::Encountered(next),
+                    None => database::ProfileRef::Owned(0),
+                };
+                self.encountered_page = position.min(order.len().saturating_sub(1)) / 100;
             }
         }
+        self.update_profile_extra();
+        self.update_profile_list();
     }
 
-    fn on_slot_move(&mut self, modifier: fn(usize) -> Option<usize>) {
-        let slot = self.database.owned[self.profile_index].main.get_slot();
-        if let Some(other_slot) = modifier(slot) {
-            let other = self.database.owned_slot_to_index(other_slot);
-            self.database.owned[self.profile_index]
-                .main
-                .set_slot(other_slot);
-            if let Some(i) = other {
-                self.database.owned[i].main.set_slot(slot);
+    // Copies the selected encountered profile to a free owned slot and selects the copy
+    fn on_profile_copy(&mut self) {
+        if let database::ProfileRef::Encountered(node) = self.selection {
+            match self.database.copy_cfhe_to_owned(node, None) {
+                Ok(slot) => {
+                    let index = self.database.owned_slot_to_index(slot).unwrap();
+                    self.selection = database::ProfileRef::Owned(index);
+                    self.set_list_mode(ListMode::Owned);
+                    self.update_profile_extra();
+                    self.update_profile_list();
+                    self.on_page_change(self.page);
+                }
+                Err(e) => self
+                    .message_prompt
+                    .show(&format!("Failed to copy: {}", e), &[ID_MESSAGE_OK]),
             }
+        }
+    }
+
+    fn on_encountered_page_change(&mut self, next: bool) {
+        let pages = ((self.database.cfhe_order().len() + 99) / 100).max(1);
+        self.encountered_page = if next {
+            (self.encountered_page + 1).min(pages - 1)
+        } else {
+            self.encountered_page.saturating_sub(1)
+        };
+        self.update_profile_list();
+    }
+
+    fn set_list_mode(&mut self, list_mode: ListMode) {
+        self.list_mode = list_mode;
+        // The button shows the list it switches to
+        self.button_list_mode
+            .borrow_mut()
+            .set_content(ui::ButtonContent::from_text(match list_mode {
+                ListMode::Owned => "Invited",
+                ListMode::Invited => "Encountered",
+                ListMode::Encountered => "Owned",
+            }));
+    }
+
+    // Switches the profile list between the owned, the invited and the encountered profiles, and
+    // selects the first profile of the list
+    fn on_list_mode_change(&mut self) {
+        self.set_list_mode(match self.list_mode {
+            ListMode::Owned => ListMode::Invited,
+            ListMode::Invited => ListMode::Encountered,
+            ListMode::Encountered => ListMode::Owned,
+        });
+        self.encountered_page = 0;
+        self.selection = match self.profile_list_entries().into_iter().flatten().next() {
+            Some(profile) if self.list_mode != ListMode::Owned => profile,
+            _ => database::ProfileRef::Owned(0),
+        };
+        self.update_profile_extra();
+        self.update_profile_list();
+        self.on_page_change(self.page);
+    }
+
+    // Reverts (or reapplies) an edit and shows the profile it was made on
+    fn on_history(&mut self, redo: bool) {
+        let selection = if redo {
+            self.history.redo(&mut self.database)
+        } else {
+            self.history.undo(&mut self.database)
+        };
+        if let Some(selection) = selection {
+            self.selection = selection;
+            let list_mode = match selection {
+                database::ProfileRef::Owned(_) => ListMode::Owned,
+                database::ProfileRef::Invited(_) => ListMode::Invited,
+                database::ProfileRef::Encountered(node) => {
+                    let order = self.database.cfhe_order();
+                    if let Some(position) = order.iter().position(|&n| n == node) {
+                        self.encountered_page = position / 100;
+                    }
+                    ListMode::Encountered
+                }
+            };
+            self.set_list_mode(list_mode);
+            self.update_profile_extra();
             self.update_profile_list();
+            self.on_page_change(self.page);
+            self.update_dirty();
         }
     }
 
     fn on_ui_event(&mut self, events: Vec<ui::UIEvent>) {
         for event in events {
-            match event.id {
-                ID_STYLE_DEC => self.on_style_change(Delta::Dec),
-                ID_STYLE_INC => self.on_style_change(Delta::Inc),
-                ID_Y_DEC => self.on_y_change(Delta::Dec),
-                ID_Y_INC => self.on_y_change(Delta::Inc),
-                ID_X_DEC => self.on_x_change(Delta::Dec),
-                ID_X_INC => self.on_x_change(Delta::Inc),
-                ID_ROTATION_DEC => self.on_rotation_change(Delta::Dec),
-                ID_ROTATION_INC => self.on_rotation_change(Delta::Inc),
-                ID_SCALE_DEC => self.on_scale_change(Delta::Dec),
-                ID_SCALE_INC => self.on_scale_change(Delta::Inc),
-                ID_Y_SCALE_DEC => self.on_y_scale_change(Delta::Dec),
-                ID_Y_SCALE_INC => self.on_y_scale_change(Delta::Inc),
-                ID_FLIP_HAIR => {
-                    let flip = &mut self.database.owned[self.profile_index].main.hair.flip;
-                    *flip = 1 - *flip;
+            // The prompts are modal
+            let prompt_visible = self.layout_close_prompt.borrow().get_visible();
+            if prompt_visible && !(ID_CLOSE_SAVE..=ID_CLOSE_CANCEL).contains(&event.id) {
+                continue;
+            }
+            if self.message_prompt.is_visible()
+                && !(ID_MESSAGE_OK..=ID_MESSAGE_QUIT).contains(&event.id)
+            {
+                continue;
+            }
+            if matches!(self.selection, database::ProfileRef::Encountered(_))
+                && is_profile_edit(event.id)
+            {
+                continue;
+            }
+            // Only the entries that the event can change are copied, and none for most events
+            let before = match event.id {
+                id if is_profile_edit(id) => Some(history::Snapshot::of_selection(
+                    &self.database,
+                    self.selection,
+                )),
+                id if (ID_PROFILE_LIST..ID_PROFILE_LIST + 100).contains(&id) => {
+                    Some(history::Snapshot::of_owned(&self.database, self.selection))
+                }
+                ID_PROFILE_LEFT..=ID_PROFILE_DELETE | ID_PROFILE_COPY => {
+                    Some(history::Snapshot::new(&self.database, self.selection))
                 }
+                _ => None,
+            };
+            // Scroll bars send an event for each step of a drag
+            let merge_key = match event.id {
+                ID_WIDTH | ID_HEIGHT => Some(event.id),
+                _ => None,
+            };
+            match event.id {
+                ID_STYLE_DEC => self.on_control_change(schema::Control::Style, Delta::Dec),
+                ID_STYLE_INC => self.on_control_change(schema::Control::Style, Delta::Inc),
+                ID_Y_DEC => self.on_control_change(schema::Control::Y, Delta::Dec),
+                ID_Y_INC => self.on_control_change(schema::Control::Y, Delta::Inc),
+                ID_X_DEC => self.on_control_change(schema::Control::X, Delta::Dec),
+                ID_X_INC => self.on_control_change(schema::Control::X, Delta::Inc),
+                ID_ROTATION_DEC => self.on_control_change(schema::Control::Rotation, Delta::Dec),
+                ID_ROTATION_INC => self.on_control_change(schema::Control::Rotation, Delta::Inc),
+                ID_SCALE_DEC => self.on_control_change(schema::Control::Scale, Delta::Dec),
+                ID_SCALE_INC => self.on_control_change(schema::Control::Scale, Delta::Inc),
+                ID_Y_SCALE_DEC => self.on_control_change(schema::Control::YScale, Delta::Dec),
+                ID_Y_SCALE_INC => self.on_control_change(schema::Control::YScale, Delta::Inc),
+                ID_SPACING_DEC => self.on_control_change(schema::Control::Spacing, Delta::Dec),
+                ID_SPACING_INC => self.on_control_change(schema::Control::Spacing, Delta::Inc),
+                ID_FLIP_HAIR => self.on_control_change(schema::Control::Flip, Delta::Inc),
                 ID_PALETTE => self.on_color_change_from_palette(),
+                ID_RANDOMIZE => self.on_randomize(),
                 ID_EXTRA_FOLD => {
                     let visible = self.layout_extra.borrow().get_visible();
                     self.layout_extra.borrow_mut().set_visible(!visible);
@@ -1427,71 +1879,92 @@ impl Main {
                     }
                 }
                 ID_FAVORITE => {
-                    let b = &mut self.database.owned[self.profile_index]
-                        .main
-                        .general
-                        .favorite;
+                    let b = &mut self.database.profile_mut(self.selection).general.favorite;
                     *b = 1 - *b;
                     self.update_profile_extra();
                 }
                 ID_SHARE => {
-                    let b = &mut self.database.owned[self.profile_index]
-                        .main
+                    let b = &mut self
+                        .database
+                        .profile_mut(self.selection)
                         .face
                         .disable_sharing;
                     *b = 1 - *b;
                     self.update_profile_extra();
                 }
                 ID_COPY => {
-                    let b = &mut self.database.owned[self.profile_index]
-                        .main
+                    let b = &mut self
+                        .database
+                        .profile_mut(self.selection)
                         .header
                         .allow_copying;
                     *b = 1 - *b;
                     self.update_profile_extra();
                 }
                 ID_MALE => {
-                    self.database.owned[self.profile_index].main.general.sex = 0;
+                    self.database.profile_mut(self.selection).general.sex = 0;
                     self.update_profile_extra();
                 }
                 ID_FEMALE => {
-                    self.database.owned[self.profile_index].main.general.sex = 1;
+                    self.database.profile_mut(self.selection).general.sex = 1;
                     self.update_profile_extra();
                 }
                 ID_FAVORITE_COLOR => {
-                    self.database.owned[self.profile_index]
-                        .main
+                    self.database
+                        .profile_mut(self.selection)
                         .general
                         .favorite_color =
                         self.palette_favorite_color.borrow().get_selected() as u16;
                     self.update_profile_extra();
                 }
                 ID_WIDTH => {
-                    self.database.owned[self.profile_index].main.width =
+                    self.database.profile_mut(self.selection).width =
                         (self.scroll_width.borrow().get_value() * 127.0).round() as u8;
                     self.update_profile_extra();
                 }
                 ID_HEIGHT => {
-                    self.database.owned[self.profile_index].main.height =
+                    self.database.profile_mut(self.selection).height =
                         (self.scroll_height.borrow().get_value() * 127.0).round() as u8;
                     self.update_profile_extra();
                 }
                 ID_NAME => {
-                    if let Some(s) = self.get_string_from_clipboard() {
-                        self.database.owned[self.profile_index].main.name = s;
+                    if let Some(s) = self.get_name_from_clipboard() {
+                        self.database.profile_mut(self.selection).name = s;
                         self.update_profile_extra();
                         self.update_profile_list();
                     }
                 }
                 ID_AUTHOR => {
-                    if let Some(s) = self.get_string_from_clipboard() {
-                        self.database.owned[self.profile_index].author = s;
-                        self.update_profile_extra();
+                    if let Some(s) = self.get_name_from_clipboard() {
+                        // Invited profiles have no author
+                        if let database::ProfileRef::Owned(index) = self.selection {
+                            self.database.owned[index].author = s;
+                            self.update_profile_extra();
+                        }
                     }
                 }
                 ID_SAVE => {
                     self.on_save();
                 }
+                ID_CLOSE_SAVE => {
+                    self.layout_close_prompt.borrow_mut().set_visible(false);
+                    self.exit_requested = self.on_save();
+                }
+                ID_CLOSE_DISCARD => {
+                    self.exit_requested = true;
+                }
+                ID_CLOSE_CANCEL => {
+                    self.layout_close_prompt.borrow_mut().set_visible(false);
+                }
+                ID_MESSAGE_OK => {
+                    self.message_prompt.hide();
+                }
+                ID_MESSAGE_REPAIR => {
+                    self.on_repair();
+                }
+                ID_MESSAGE_QUIT => {
+                    std::process::exit(1);
+                }
                 ID_PROFILE_LEFT => {
                     fn mover(slot: usize) -> Option<usize> {
                         if slot % 10 == 0 {
@@ -1533,62 +2006,81 @@ impl Main {
                     self.on_slot_move(mover);
                 }
                 ID_PROFILE_DELETE => {
-                    self.database.owned[self.profile_index] = database::ProfileFull::default();
-                    self.profile_index = 0;
-                    self.update_profile_extra();
-                    self.update_profile_list();
+                    self.on_profile_delete();
+                }
+                ID_PROFILE_LIST_MODE => {
+                    self.on_list_mode_change();
+                }
+                ID_PROFILE_COPY => {
+                    self.on_profile_copy();
+                }
+                ID_PROFILE_PAGE_PREV => {
+                    self.on_encountered_page_change(false);
+                }
+                ID_PROFILE_PAGE_NEXT => {
+                    self.on_encountered_page_change(true);
                 }
 
                 _ => {
                     if event.id >= ID_PAGE_BUTTON_BEGIN && event.id < ID_PAGE_BUTTON_END {
-                        self.on_page_change((event.id - ID_PAGE_BUTTON_BEGIN) as u8);
+                        self.on_page_change(
+                            schema::Page::ALL[(event.id - ID_PAGE_BUTTON_BEGIN) as usize],
+                        );
                     } else if event.id >= ID_BIRTHMONTH_BEGIN && event.id < ID_BIRTHMONTH_BEGIN + 50
                     {
                         let birthmonth = (event.id - ID_BIRTHMONTH_BEGIN) as u16;
-                        let profile = &mut self.database.owned[self.profile_index].main.general;
+                        let profile = &mut self.database.profile_mut(self.selection).general;
                         profile.birth_month = birthmonth;
                         clamp_birthday(birthmonth, &mut profile.birth_day);
                         self.update_profile_extra();
                     } else if event.id >= ID_BIRTHDAY_BEGIN && event.id < ID_BIRTHDAY_BEGIN + 50 {
-                        let profile = &mut self.database.owned[self.profile_index].main.general;
+                        let profile = &mut self.database.profile_mut(self.selection).general;
                         let birthday = (event.id - ID_BIRTHDAY_BEGIN) as u16;
                         profile.birth_day = birthday;
                         adjust_birthmonth(birthday, &mut profile.birth_month);
                         self.update_profile_extra();
                     } else if event.id >= ID_PROFILE_LIST && event.id < ID_PROFILE_LIST + 100 {
                         let slot = (event.id - ID_PROFILE_LIST) as usize;
-                        if let Some(i) = self.database.owned_slot_to_index(slot) {
-                            self.profile_index = i;
+                        if let Some(profile) = self.profile_list_entries()[slot] {
+                            self.selection = profile;
                             self.update_profile_extra();
                             self.update_profile_list();
-                        } else {
-                            let index = self
-                                .database
-                                .owned
-                                .iter()
-                                .position(|profile| profile.main.is_null())
-                                .unwrap();
+                        } else if self.list_mode == ListMode::Owned {
+                            let index = self.database.free_owned_index().unwrap();
                             let mac = self.database.owned[0].main.id.mac;
                             let system_id = self.database.owned[0].main.system_id;
                             let now = chrono::Local::now().naive_local();
                             self.database.owned[index].main =
                                 database::Profile::new(mac, system_id, now, slot);
-                            self.profile_index = index;
+                            self.selection = database::ProfileRef::Owned(index);
                             self.update_profile_extra();
                             self.update_profile_list();
                         }
                     }
                 }
             }
+            if let Some(before) = before {
+                if self
+                    .history
+                    .record(before, &self.database, self.selection, merge_key)
+                {
+                    self.update_dirty();
+                }
+            }
         }
     }
 
     fn run(mut self, event_loop: glutin::event_loop::EventLoop<()>) {
-        self.on_page_change(0);
+        self.on_page_change(schema::Page::Face);
         self.update_profile_extra();
         self.update_profile_list();
+        if !self.load_issues.is_empty() {
+            self.show_load_issues();
+            self.update_dirty();
+        }
 
         let mut rotate = 0.0;
+        let mut modifiers = ModifiersState::empty();
         let mut aspect = 1.0f32;
         let mut window_width = 0.0f32;
         let mut window_height = 0.0f32;
@@ -1632,9 +2124,11 @@ impl Main {
                         );
                         self.on_ui_event(events);
                     }
+                    WindowEvent::ModifiersChanged(state) => {
+                        modifiers = state;
+                    }
                     WindowEvent::CloseRequested => {
-                        *control_flow = ControlFlow::Exit;
-                        return;
+                        self.exit_requested = self.on_close_requested();
                     }
                     WindowEvent::Resized(physical) => {
                         window_width = physical.width as f32;
@@ -1660,6 +2154,12 @@ impl Main {
                             },
                         ..
                     } => match virtual_keycode {
+                        Some(VirtualKeyCode::Z) if modifiers.ctrl() => {
+                            self.on_history(modifiers.shift());
+                        }
+                        Some(VirtualKeyCode::Y) if modifiers.ctrl() => {
+                            self.on_history(true);
+                        }
                         Some(VirtualKeyCode::Left) => {
                             rotate += 10.0;
                         }
@@ -1672,6 +2172,10 @@ impl Main {
                 }
             }
 
+            if self.exit_requested {
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
             *control_flow = glutin::event_loop::ControlFlow::Poll;
 
             let now = std::time::Instant::now();
@@ -1687,13 +2191,14 @@ impl Main {
                 gl::Enable(gl::DEPTH_TEST);
             }
 
-            let info = self.database.owned[self.profile_index]
-                .main
-                .to_render_info();
+            // Fields of a damaged database may be out of the range of the asset
+            if self.load_issues.is_empty() {
+                let info = self.database.profile(self.selection).to_render_info();
 
-            let object_tran = cgmath::Matrix4::from_angle_y(cgmath::Deg(rotate));
+                let object_tran = cgmath::Matrix4::from_angle_y(cgmath::Deg(rotate));
 
-            self.head_renderer.render_head(&info, &object_tran, aspect);
+                self.head_renderer.render_head(&info, &object_tran, aspect);
+            }
 
             unsafe {
                 gl::Disable(gl::CULL_FACE);
@@ -1728,19 +2233,118 @@ extern "system" fn gl_debug_message(
     }
 }
 
+fn print_usage() {
+    println!("Usage: citrii [Citra user folder] [Options]");
+    println!();
+    println!("Options:");
+    println!("    --database [File]             Path to CFL_DB.dat");
+    println!("    --assets [File]               Path to the Mii Maker RomFS or content file");
+    println!("    --list                        Show the files found and exit");
+    println!();
+    println!("Without a user folder, the standard folders of Citra and Lime3DS are searched.");
+}
+
+fn print_standard_user_folders() {
+    println!("No data found in the standard user folders:");
+    for path in data_paths::standard_user_folders() {
+        println!("    {}", path.display());
+    }
+}
+
+fn print_user_folder(folder: &data_paths::UserFolder) {
+    println!("{}", folder.path.display());
+    if folder.is_empty() {
+        println!("    nothing found");
+    }
+    for database in &folder.databases {
+        println!("    database: {}", database.display());
+    }
+    for assets in &folder.assets {
+        println!("    assets:   {}", assets.display());
+    }
+}
+
+// Picks the database and asset files from the arguments and the user folders. Returns None
+// after printing the reason if the editor shouldn't start.
+fn select_paths(args: &[String]) -> Option<(std::path::PathBuf, std::path::PathBuf)> {
+    let mut user_folder = None;
+    let mut database = None;
+    let mut assets = None;
+    let mut list = false;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--database" | "--assets" => {
+                let value = match args.get(i + 1) {
+                    None => {
+                        print_usage();
+                        return None;
+                    }
+                    Some(value) => std::path::PathBuf::from(value),
+                };
+                if args[i] == "--database" {
+                    database = Some(value);
+                } else {
+                    assets = Some(value);
+                }
+                i += 1;
+            }
+            "--list" => list = true,
+            arg if arg.starts_with('-') || user_folder.is_some() => {
+                print_usage();
+                return None;
+            }
+            arg => user_folder = Some(std::path::PathBuf::from(arg)),
+        }
+        i += 1;
+    }
+
+    let folders = match &user_folder {
+        Some(path) => vec![data_paths::scan_user_folder(path)],
+        None => data_paths::discover_user_folders(),
+    };
+    if list {
+        if folders.is_empty() {
+            print_standard_user_folders();
+        }
+        for folder in &folders {
+            print_user_folder(folder);
+        }
+        return None;
+    }
+
+    let database = database.or_else(|| folders.iter().find_map(|f| f.databases.first()).cloned());
+    let assets = assets.or_else(|| folders.iter().find_map(|f| f.assets.first()).cloned());
+    match (database, assets) {
+        (Some(database), Some(assets)) => {
+            println!("Database: {}", database.display());
+            println!("Assets:   {}", assets.display());
+            Some((database, assets))
+        }
+        (database, assets) => {
+            if database.is_none() {
+                println!("CFL_DB.dat is not found. Use --database to specify it.");
+            }
+            if assets.is_none() {
+                println!("The Mii Maker assets are not found. Use --assets to specify them.");
+            }
+            if user_folder.is_none() && folders.is_empty() {
+                print_standard_user_folders();
+            }
+            for folder in &folders {
+                print_user_folder(folder);
+            }
+            None
+        }
+    }
+}
+
 fn main_thread() {
     let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        println!("Usage: citrii [Path citra user folder]");
-        return;
-    }
-    let citra_path = std::path::Path::new(&args[1]);
-    let asset_path = citra_path.join(
-        "nand/00000000000000000000000000000000/title/0004009b/00010202/content/00000000.app.romfs",
-    );
-    let database_path = citra_path.join(
-        "nand/data/00000000000000000000000000000000/extdata/00048000/F000000B/user/CFL_DB.dat",
-    );
+    let (database_path, asset_path) = match select_paths(&args) {
+        None => return,
+        Some(paths) => paths,
+    };
 
     #[cfg(target_family = "unix")]
     use glutin::platform::unix::EventLoopExtUnix;
@@ -1748,8 +2352,68 @@ fn main_thread() {
     use glutin::platform::windows::EventLoopExtWindows;
 
     let mut event_loop = glutin::event_loop::EventLoop::new_any_thread();
-    let instance = Main::new(asset_path, database_path, &mut event_loop);
-    instance.run(event_loop);
+    match Main::new(asset_path, database_path, &mut event_loop) {
+        Ok(instance) => instance.run(event_loop),
+        Err(e) => show_load_error(event_loop, &format!("Failed to load: {}", e)),
+    }
+}
+
+// Shows an error that keeps the editor from starting, and exits with a failure once the window is
+// closed
+fn show_load_error(event_loop: glutin::event_loop::EventLoop<()>, message: &str) -> ! {
+    eprintln!("{}", message);
+    let gl_context = create_gl_context(&event_loop);
+    let rect_renderer = Rc::new(rect_renderer::RectRenderer::new());
+    let text_renderer = Rc::new(text_renderer::TextRenderer::new(rect_renderer.clone()));
+    let prompt = MessagePrompt::new(&[(ID_MESSAGE_QUIT, "Quit")], &rect_renderer, &text_renderer);
+    prompt.show(message, &[ID_MESSAGE_QUIT]);
+    let mut scene = ui::Scene::new(vec![prompt.docker()]);
+
+    let mut aspect = 1.0f32;
+    let mut window_size = (1.0f32, 1.0f32);
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+        if let Event::WindowEvent { event, .. } = event {
+            let events = match event {
+                WindowEvent::CloseRequested => std::process::exit(1),
+                WindowEvent::Resized(physical) => {
+                    window_size = (physical.width as f32, physical.height as f32);
+                    aspect = window_size.0 / window_size.1;
+                    unsafe {
+                        gl::Viewport(0, 0, physical.width as GLsizei, physical.height as GLsizei);
+                    }
+                    gl_context.resize(physical);
+                    vec![]
+                }
+                WindowEvent::CursorMoved {
+                    position: PhysicalPosition { x, y },
+                    ..
+                } => scene.on_mouse_event(
+                    ui::MouseEvent::Moved(
+                        (x as f32 / window_size.0) * aspect,
+                        y as f32 / window_size.1,
+                    ),
+                    aspect,
+                ),
+                WindowEvent::MouseInput { state, .. } => scene.on_mouse_event(
+                    match state {
+                        ElementState::Pressed => ui::MouseEvent::Pressed,
+                        ElementState::Released => ui::MouseEvent::Released,
+                    },
+                    aspect,
+                ),
+                _ => vec![],
+            };
+            if events.iter().any(|e| e.id == ID_MESSAGE_QUIT) {
+                std::process::exit(1);
+            }
+        }
+        unsafe {
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+        scene.render(aspect);
+        gl_context.swap_buffers().unwrap();
+    })
 }
 
 fn main() {
@@ -122,7 +122,7 @@ impl Model {
                 gl::TRIANGLES,
                 self.len as GLsizei,
                 gl::UNSIGNED_BYTE,
-                0 as *const GLvoid,
+                std::ptr::null(),
             );
         }
     }
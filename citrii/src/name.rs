@@ -0,0 +1,239 @@
+// Names and authors of profiles. A name is ten UTF-16 code units, ended by a zero unless all ten
+// are used. The console draws it with the font of the character set in the char_set field, so a
+// character that is valid Unicode can still be missing from the screen.
+
+use crate::database::*;
+
+pub const NAME_LEN: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharSet {
+    Standard,
+    Chinese,
+    Korean,
+    Taiwanese,
+}
+
+// Unicode blocks in the fonts of all character sets: Latin, Greek, Cyrillic, punctuation, symbols,
+// kana, full width forms and the icons of the console in the private use area
+const COMMON_CHARS: &[(u32, u32)] = &[
+    (0x20, 0x7E),
+    (0xA0, 0x17F),
+    (0x370, 0x3FF),
+    (0x400, 0x45F),
+    (0x2000, 0x206F),
+    (0x2100, 0x22FF),
+    (0x2460, 0x26FF),
+    (0x3000, 0x30FF),
+    (0xE000, 0xE0FF),
+    (0xFF01, 0xFFEF),
+];
+
+const CJK_CHARS: &[(u32, u32)] = &[(0x4E00, 0x9FFF)];
+const HANGUL_CHARS: &[(u32, u32)] = &[(0x1100, 0x11FF), (0x3130, 0x318F), (0xAC00, 0xD7A3)];
+const TAIWANESE_CHARS: &[(u32, u32)] = &[(0x3100, 0x312F), (0x4E00, 0x9FFF)];
+
+impl CharSet {
+    pub fn from_field(value: u32) -> CharSet {
+        match value {
+            1 => CharSet::Chinese,
+            2 => CharSet::Korean,
+            3 => CharSet::Taiwanese,
+            _ => CharSet::Standard,
+        }
+    }
+
+    pub fn of(profile: &Profile) -> CharSet {
+        CharSet::from_field(profile.header.char_set)
+    }
+
+    // Whether the font of the character set has the character. The fonts only cover the basic
+    // multilingual plane, so characters that need a surrogate pair are never displayed.
+    pub fn supports(self, c: char) -> bool {
+        let extra = match self {
+            CharSet::Standard | CharSet::Chinese => CJK_CHARS,
+            CharSet::Korean => HANGUL_CHARS,
+            CharSet::Taiwanese => TAIWANESE_CHARS,
+        };
+        let c = c as u32;
+        COMMON_CHARS
+            .iter()
+            .chain(extra)
+            .any(|&(first, last)| (first..=last).contains(&c))
+    }
+}
+
+impl std::fmt::Display for CharSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            CharSet::Standard => "standard",
+            CharSet::Chinese => "Chinese",
+            CharSet::Korean => "Korean",
+            CharSet::Taiwanese => "Taiwanese",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NameIssue {
+    Unsupported { c: char, char_set: CharSet },
+    // A surrogate without its other half
+    InvalidUnit(u16),
+    // The end of the text that didn't fit
+    Truncated(String),
+}
+
+impl std::fmt::Display for NameIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NameIssue::Unsupported { c, char_set } => write!(
+                f,
+                "{:?} (U+{:04X}) is not in the {} font",
+                c, *c as u32, char_set
+            ),
+            NameIssue::InvalidUnit(unit) => write!(f, "0x{:04X} is not valid UTF-16", unit),
+            NameIssue::Truncated(rest) => write!(
+                f,
+                "the name is longer than {} units; {:?} is left out",
+                NAME_LEN, rest
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MiiName(pub [u16; NAME_LEN]);
+
+impl MiiName {
+    // Encodes as much of the text as fits, and returns the rest. A character that needs a
+    // surrogate pair is moved to the rest as a whole when only one unit is left.
+    pub fn encode(s: &str) -> (MiiName, &str) {
+        let mut name = [0; NAME_LEN];
+        let mut len = 0;
+        for (i, c) in s.char_indices() {
+            if len + c.len_utf16() > NAME_LEN {
+                return (MiiName(name), &s[i..]);
+            }
+            len += c.encode_utf16(&mut name[len..]).len();
+        }
+        (MiiName(name), "")
+    }
+
+    // Encodes the text for a profile with the character set, listing everything that doesn't
+    // survive or can't be displayed
+    pub fn parse(s: &str, char_set: CharSet) -> (MiiName, Vec<NameIssue>) {
+        let (name, rest) = MiiName::encode(s);
+        let mut issues = name.issues(char_set);
+        if !rest.is_empty() {
+            issues.push(NameIssue::Truncated(rest.to_string()));
+        }
+        (name, issues)
+    }
+
+    pub fn units(&self) -> &[u16] {
+        let end = self.0.iter().position(|&v| v == 0).unwrap_or(NAME_LEN);
+        &self.0[0..end]
+    }
+
+    pub fn chars(&self) -> impl Iterator<Item = Result<char, u16>> + '_ {
+        std::char::decode_utf16(self.units().iter().copied())
+            .map(|c| c.map_err(|e| e.unpaired_surrogate()))
+    }
+
+    pub fn issues(&self, char_set: CharSet) -> Vec<NameIssue> {
+        self.chars()
+            .filter_map(|c| match c {
+                Ok(c) if char_set.supports(c) => None,
+                Ok(c) => Some(NameIssue::Unsupported { c, char_set }),
+                Err(unit) => Some(NameIssue::InvalidUnit(unit)),
+            })
+            .collect()
+    }
+
+    // The text followed by the problems that keep it from being displayed as it is
+    pub fn describe(&self, char_set: CharSet) -> String {
+        let issues: Vec<String> = self
+            .issues(char_set)
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+        if issues.is_empty() {
+            self.to_string()
+        } else {
+            format!("{} ({})", self, issues.join(", "))
+        }
+    }
+}
+
+impl ProfileFull {
+    // Problems with the name and the author, by the field they are in. Imported profiles are not
+    // checked otherwise, as they are stored unchanged.
+    pub fn name_issues(&self) -> Vec<(&'static str, NameIssue)> {
+        let char_set = CharSet::of(&self.main);
+        let name = MiiName(self.main.name).issues(char_set);
+        let author = MiiName(self.author).issues(char_set);
+        name.into_iter()
+            .map(|issue| ("name", issue))
+            .chain(author.into_iter().map(|issue| ("author", issue)))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for MiiName {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for c in self.chars() {
+            write!(f, "{}", c.unwrap_or(std::char::REPLACEMENT_CHARACTER))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn name_test() {
+    let (name, issues) = MiiName::parse("Mii", CharSet::Standard);
+    assert_eq!(name.units(), &[0x4D, 0x69, 0x69]);
+    assert!(issues.is_empty());
+
+    // Nine units are taken, so the pair of the emoji doesn't fit and is not split
+    let (name, rest) = MiiName::encode("123456789\u{1F600}x");
+    assert_eq!(name.to_string(), "123456789");
+    assert_eq!(rest, "\u{1F600}x");
+    let (name, rest) = MiiName::encode("12345678\u{1F600}x");
+    assert_eq!(name.units().len(), NAME_LEN);
+    assert_eq!(rest, "x");
+    assert_eq!(
+        name.issues(CharSet::Standard),
+        vec![NameIssue::Unsupported {
+            c: '\u{1F600}',
+            char_set: CharSet::Standard
+        }]
+    );
+
+    assert_eq!(
+        name.describe(CharSet::Standard),
+        "12345678\u{1F600} ('\u{1F600}' (U+1F600) is not in the standard font)"
+    );
+
+    let (_, issues) = MiiName::parse("민수", CharSet::Standard);
+    assert_eq!(issues.len(), 2);
+    let (_, issues) = MiiName::parse("민수", CharSet::Korean);
+    assert!(issues.is_empty());
+    let (_, issues) = MiiName::parse("山田", CharSet::Korean);
+    assert_eq!(issues.len(), 2);
+
+    let name = MiiName([0x41, 0xD800, 0x42, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(
+        name.issues(CharSet::Standard),
+        vec![NameIssue::InvalidUnit(0xD800)]
+    );
+    assert_eq!(name.to_string(), "A\u{FFFD}B");
+
+    let mut profile = ProfileFull::default();
+    profile.main.name = name.0;
+    profile.author = MiiName::encode("민수").0 .0;
+    let issues = profile.name_issues();
+    assert_eq!(issues.len(), 3);
+    assert_eq!(issues[0], ("name", NameIssue::InvalidUnit(0xD800)));
+    assert_eq!(issues[1].0, "author");
+}
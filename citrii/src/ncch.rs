@@ -0,0 +1,275 @@
+// Reads NCCH containers (CXI and CFA), such as the .app content files of installed titles. Only
+// decrypted containers are supported, which are marked with the NoCrypto flag.
+
+use crate::error::*;
+use byte_struct::*;
+
+const NCCH_HEADER_OFFSET: usize = 0x100;
+const EXEFS_HEADER_LEN: usize = 0x200;
+const EXEFS_FILE_COUNT: usize = 10;
+const IVFC_HEADER_LEN: usize = 0x60;
+
+const FLAG_CONTENT_UNIT_SIZE: usize = 6;
+const FLAG_OPTIONS: usize = 7;
+const OPTION_NO_CRYPTO: u8 = 0x4;
+
+#[derive(ByteStruct)]
+#[byte_struct_le]
+struct NcchHeader {
+    magic: [u8; 4],
+    content_size: u32,
+    partition_id: u64,
+    maker_code: u16,
+    version: u16,
+    seed_check: u32,
+    program_id: u64,
+    reserved0: [u8; 16],
+    logo_hash: [u8; 32],
+    product_code: [u8; 16],
+    exheader_hash: [u8; 32],
+    exheader_size: u32,
+    reserved1: u32,
+    flags: [u8; 8],
+    plain_offset: u32,
+    plain_size: u32,
+    logo_offset: u32,
+    logo_size: u32,
+    exefs_offset: u32,
+    exefs_size: u32,
+    exefs_hash_size: u32,
+    reserved2: u32,
+    romfs_offset: u32,
+    romfs_size: u32,
+    romfs_hash_size: u32,
+    reserved3: u32,
+    exefs_super_hash: [u8; 32],
+    romfs_super_hash: [u8; 32],
+}
+
+#[derive(ByteStruct)]
+#[byte_struct_le]
+struct ExefsFileHeader {
+    name: [u8; 8],
+    offset: u32,
+    size: u32,
+}
+
+#[derive(ByteStruct)]
+#[byte_struct_le]
+struct IvfcLevel {
+    logical_offset: u64,
+    hash_data_size: u64,
+    block_size_log2: u32,
+    reserved: u32,
+}
+
+#[derive(ByteStruct)]
+#[byte_struct_le]
+struct IvfcHeader {
+    magic: [u8; 4],
+    magic_id: u32,
+    master_hash_size: u32,
+    levels: [IvfcLevel; 3],
+    optional_info_size: u32,
+}
+
+fn get_slice(data: &[u8], begin: u64, len: u64) -> Result<&[u8], Error> {
+    begin
+        .checked_add(len)
+        .and_then(|end| data.get(begin as usize..end as usize))
+        .ok_or(Error::NcchCorrupt)
+}
+
+// Returns the level 3 data of a RomFS with an IVFC hash tree, which is what the romfs module
+// reads. The hashes are not verified.
+pub fn ivfc_level3(romfs: &[u8]) -> Result<&[u8], Error> {
+    let header = IvfcHeader::read_bytes(get_slice(romfs, 0, IvfcHeader::BYTE_LEN as u64)?);
+    if &header.magic != b"IVFC" || header.magic_id != 0x10000 {
+        return Err(Error::NcchCorrupt);
+    }
+    let level3 = &header.levels[2];
+    if level3.block_size_log2 >= 32 {
+        return Err(Error::NcchCorrupt);
+    }
+    let block_size = 1u64 << level3.block_size_log2;
+    let offset = (IVFC_HEADER_LEN as u64 + header.master_hash_size as u64 + block_size - 1)
+        / block_size
+        * block_size;
+    get_slice(romfs, offset, level3.hash_data_size)
+}
+
+pub struct Ncch<'a> {
+    data: &'a [u8],
+    header: NcchHeader,
+}
+
+impl<'a> Ncch<'a> {
+    pub fn is_ncch(data: &[u8]) -> bool {
+        data.get(NCCH_HEADER_OFFSET..NCCH_HEADER_OFFSET + 4) == Some(b"NCCH")
+    }
+
+    pub fn new(data: &'a [u8]) -> Result<Ncch<'a>, Error> {
+        if !Ncch::is_ncch(data) {
+            return Err(Error::NcchCorrupt);
+        }
+        let header = NcchHeader::read_bytes(get_slice(
+            data,
+            NCCH_HEADER_OFFSET as u64,
+            NcchHeader::BYTE_LEN as u64,
+        )?);
+        if header.flags[FLAG_CONTENT_UNIT_SIZE] >= 16 {
+            return Err(Error::NcchCorrupt);
+        }
+        if header.flags[FLAG_OPTIONS] & OPTION_NO_CRYPTO == 0 {
+            return Err(Error::NcchEncrypted);
+        }
+        Ok(Ncch { data, header })
+    }
+
+    pub fn program_id(&self) -> u64 {
+        self.header.program_id
+    }
+
+    pub fn product_code(&self) -> String {
+        self.header
+            .product_code
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as char)
+            .collect()
+    }
+
+    fn media_unit(&self) -> u64 {
+        0x200 << self.header.flags[FLAG_CONTENT_UNIT_SIZE]
+    }
+
+    fn region(&self, offset: u32, size: u32, name: &'static str) -> Result<&'a [u8], Error> {
+        if size == 0 {
+            return Err(Error::NcchSectionMissing(name));
+        }
+        let unit = self.media_unit();
+        get_slice(self.data, offset as u64 * unit, size as u64 * unit)
+    }
+
+    // The RomFS including its IVFC hash tree
+    pub fn romfs_raw(&self) -> Result<&'a [u8], Error> {
+        self.region(self.header.romfs_offset, self.header.romfs_size, "RomFS")
+    }
+
+    // The RomFS image as read by the romfs module
+    pub fn romfs(&self) -> Result<&'a [u8], Error> {
+        ivfc_level3(self.romfs_raw()?)
+    }
+
+    fn exefs(&self) -> Result<(&'a [u8], Vec<ExefsFileHeader>), Error> {
+        let exefs = self.region(self.header.exefs_offset, self.header.exefs_size, "ExeFS")?;
+        let headers = (0..EXEFS_FILE_COUNT)
+            .map(|i| {
+                get_slice(
+                    exefs,
+                    (i * ExefsFileHeader::BYTE_LEN) as u64,
+                    ExefsFileHeader::BYTE_LEN as u64,
+                )
+                .map(ExefsFileHeader::read_bytes)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((exefs, headers))
+    }
+
+    fn exefs_name(header: &ExefsFileHeader) -> String {
+        header
+            .name
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as char)
+            .collect()
+    }
+
+    // Names of the files in ExeFS, such as ".code", "icon" and "banner"
+    pub fn exefs_files(&self) -> Result<Vec<String>, Error> {
+        let (_, headers) = self.exefs()?;
+        Ok(headers
+            .iter()
+            .filter(|h| h.name[0] != 0)
+            .map(Ncch::exefs_name)
+            .collect())
+    }
+
+    pub fn exefs_file(&self, name: &str) -> Result<&'a [u8], Error> {
+        let (exefs, headers) = self.exefs()?;
+        let header = headers
+            .iter()
+            .find(|h| h.name[0] != 0 && Ncch::exefs_name(h) == name)
+            .ok_or(Error::NcchSectionMissing("ExeFS file"))?;
+        get_slice(
+            exefs,
+            EXEFS_HEADER_LEN as u64 + header.offset as u64,
+            header.size as u64,
+        )
+    }
+}
+
+// Finds the RomFS image in the content of a file, which can be an NCCH container, a RomFS with
+// its IVFC hash tree, or the RomFS image itself.
+pub fn find_romfs(data: &[u8]) -> Result<&[u8], Error> {
+    if Ncch::is_ncch(data) {
+        Ncch::new(data)?.romfs()
+    } else if data.starts_with(b"IVFC") {
+        ivfc_level3(data)
+    } else {
+        Ok(data)
+    }
+}
+
+#[test]
+fn ncch_test() {
+    let romfs = crate::romfs::build_romfs(&[(vec!["CFL_Res.dat".to_string()], b"asset")]);
+
+    // IVFC with a master hash of 0x20 bytes and 0x1000-byte blocks
+    let mut ivfc = vec![0; 0x1000];
+    ivfc[0..4].copy_from_slice(b"IVFC");
+    ivfc[4..8].copy_from_slice(&0x10000u32.to_le_bytes());
+    ivfc[8..12].copy_from_slice(&0x20u32.to_le_bytes());
+    ivfc[0x44..0x4C].copy_from_slice(&(romfs.len() as u64).to_le_bytes());
+    ivfc[0x4C..0x50].copy_from_slice(&12u32.to_le_bytes());
+    ivfc.extend_from_slice(&romfs);
+    ivfc.resize((ivfc.len() + 0x1FF) / 0x200 * 0x200, 0);
+
+    let mut exefs = vec![0; 0x200];
+    exefs[0..5].copy_from_slice(b".code");
+    exefs[12..16].copy_from_slice(&4u32.to_le_bytes());
+    exefs.extend_from_slice(b"code");
+    exefs.resize(0x400, 0);
+
+    let mut ncch = vec![0; 0x200];
+    ncch[0x100..0x104].copy_from_slice(b"NCCH");
+    ncch[0x150..0x159].copy_from_slice(b"CTR-N-MII");
+    ncch[0x188 + FLAG_OPTIONS] = OPTION_NO_CRYPTO;
+    ncch[0x1A0..0x1A4].copy_from_slice(&1u32.to_le_bytes());
+    ncch[0x1A4..0x1A8].copy_from_slice(&2u32.to_le_bytes());
+    ncch[0x1B0..0x1B4].copy_from_slice(&3u32.to_le_bytes());
+    ncch[0x1B4..0x1B8].copy_from_slice(&((ivfc.len() / 0x200) as u32).to_le_bytes());
+    ncch.extend_from_slice(&exefs);
+    ncch.extend_from_slice(&ivfc);
+
+    let container = Ncch::new(&ncch).unwrap();
+    assert_eq!(container.product_code(), "CTR-N-MII");
+    assert_eq!(container.exefs_files().unwrap(), vec![".code".to_string()]);
+    assert_eq!(container.exefs_file(".code").unwrap(), b"code");
+    assert_eq!(container.romfs().unwrap(), &romfs[..]);
+    assert_eq!(find_romfs(&ncch).unwrap(), &romfs[..]);
+    assert_eq!(find_romfs(&ivfc).unwrap(), &romfs[..]);
+    assert_eq!(find_romfs(&romfs).unwrap(), &romfs[..]);
+    assert_eq!(
+        crate::romfs::get_romfs_file(find_romfs(&ncch).unwrap(), &["CFL_Res.dat".to_string()])
+            .unwrap(),
+        b"asset"
+    );
+
+    ncch[0x188 + FLAG_OPTIONS] = 0;
+    assert!(matches!(Ncch::new(&ncch), Err(Error::NcchEncrypted)));
+    assert!(matches!(
+        Ncch::new(&ncch[0..0x180]),
+        Err(Error::NcchCorrupt)
+    ));
+}
@@ -0,0 +1,335 @@
+// Text representation of profiles in JSON or TOML, for reading and version control. Every bit of
+// a ProfileFull is written, so that reading the text back gives the same bytes. Names are written
+// as strings, or as arrays of UTF-16 code units when a string can't represent them exactly. The
+// text is read by serde_json and toml, so anything those accept works, such as dotted keys or
+// inline tables in TOML. Values out of the ranges of the schema are rejected after reading.
+
+use crate::database::*;
+use crate::error::*;
+use crate::schema;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+// Fields written by name instead of by value
+const ENUM_FIELDS: &[(&str, &[&str])] = &[
+    ("sex", &["male", "female"]),
+    ("region_lock", &["none", "japan", "usa", "europe"]),
+    ("char_set", &["standard", "chinese", "korean", "taiwanese"]),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Value {
+    Integer(i64),
+    String(String),
+    // A name that a string can't represent, as UTF-16 code units
+    Units(Vec<i64>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Value(Value),
+    Table(IndexMap<String, Value>),
+}
+
+// Values of the top level come before the tables, as TOML requires
+type Document = IndexMap<String, Entry>;
+
+pub(crate) fn enum_names(field: &str) -> Option<&'static [&'static str]> {
+    ENUM_FIELDS
+        .iter()
+        .find(|&&(name, _)| name == field)
+        .map(|&(_, names)| names)
+}
+
+fn name_to_value(name: &[u16; 10]) -> Value {
+    let s = name_to_string(name);
+    if name_from_str(&s) == *name {
+        Value::String(s)
+    } else {
+        Value::Units(name.iter().map(|&c| c as i64).collect())
+    }
+}
+
+fn hex(data: &[u8], separator: &str) -> String {
+    data.iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn to_document(profile: &ProfileFull) -> Document {
+    let main = &profile.main;
+    let mut values = vec![
+        ("name".to_string(), name_to_value(&main.name)),
+        ("author".to_string(), name_to_value(&profile.author)),
+    ];
+    for &field in PROFILE_FIELDS {
+        let value = main.get_field(field).unwrap();
+        let value = match enum_names(field) {
+            Some(names) => Value::String(names[value as usize].to_string()),
+            None => Value::Integer(value as i64),
+        };
+        values.push((field.to_string(), value));
+    }
+    for &field in PROFILE_INTERNAL_FIELDS {
+        let value = main.get_internal_field(field).unwrap();
+        values.push((field.to_string(), Value::Integer(value as i64)));
+    }
+    values.push(("id.mac".to_string(), Value::String(hex(&main.id.mac, ":"))));
+    values.push((
+        "id.system_id".to_string(),
+        Value::String(hex(&main.system_id, "")),
+    ));
+
+    let mut document = Document::new();
+    let mut tables: IndexMap<String, IndexMap<String, Value>> = IndexMap::new();
+    for (path, value) in values {
+        match path.split_once('.') {
+            None => {
+                document.insert(path, Entry::Value(value));
+            }
+            Some((table, key)) => {
+                tables
+                    .entry(table.to_string())
+                    .or_default()
+                    .insert(key.to_string(), value);
+            }
+        }
+    }
+    for (table, entries) in tables {
+        document.insert(table, Entry::Table(entries));
+    }
+    document
+}
+
+// The values by their path, removed as they are read so that unknown fields can be reported
+struct Fields(IndexMap<String, Value>);
+
+impl Fields {
+    fn new(document: Document) -> Fields {
+        let mut fields = IndexMap::new();
+        for (key, entry) in document {
+            match entry {
+                Entry::Table(table) => {
+                    for (inner_key, value) in table {
+                        fields.insert(format!("{}.{}", key, inner_key), value);
+                    }
+                }
+                Entry::Value(value) => {
+                    fields.insert(key, value);
+                }
+            }
+        }
+        Fields(fields)
+    }
+
+    fn take(&mut self, path: &str) -> Result<Value, Error> {
+        self.0
+            .shift_remove(path)
+            .ok_or_else(|| Error::ProfileFieldMissing(path.to_string()))
+    }
+
+    fn take_integer(&mut self, path: &str, min: u32, max: u32) -> Result<u32, Error> {
+        match self.take(path)? {
+            Value::Integer(value) if value >= min as i64 && value <= max as i64 => Ok(value as u32),
+            Value::Integer(_) => Err(Error::ProfileFieldOutOfRange {
+                field: path.to_string(),
+                min,
+                max,
+            }),
+            _ => Err(Error::ProfileFieldInvalid(path.to_string())),
+        }
+    }
+
+    fn take_enum(&mut self, path: &str, names: &[&str]) -> Result<u32, Error> {
+        match self.take(path)? {
+            Value::String(s) => names
+                .iter()
+                .position(|&name| name == s)
+                .map(|i| i as u32)
+                .ok_or_else(|| Error::ProfileFieldInvalid(path.to_string())),
+            _ => Err(Error::ProfileFieldInvalid(path.to_string())),
+        }
+    }
+
+    fn take_name(&mut self, path: &str) -> Result<[u16; 10], Error> {
+        let invalid = || Error::ProfileFieldInvalid(path.to_string());
+        let units: Vec<u16> = match self.take(path)? {
+            Value::String(s) => s.encode_utf16().collect(),
+            Value::Units(values) => values
+                .iter()
+                .map(|&c| match c {
+                    0..=0xFFFF => Ok(c as u16),
+                    _ => Err(invalid()),
+                })
+                .collect::<Result<_, _>>()?,
+            _ => return Err(invalid()),
+        };
+        if units.len() > 10 {
+            return Err(invalid());
+        }
+        let mut name = [0; 10];
+        name[0..units.len()].copy_from_slice(&units);
+        Ok(name)
+    }
+
+    fn take_hex(&mut self, path: &str, result: &mut [u8]) -> Result<(), Error> {
+        let invalid = || Error::ProfileFieldInvalid(path.to_string());
+        let s = match self.take(path)? {
+            Value::String(s) => s.replace(':', ""),
+            _ => return Err(invalid()),
+        };
+        if s.len() != result.len() * 2 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        for (i, byte) in result.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(())
+    }
+}
+
+fn from_document(document: Document) -> Result<ProfileFull, Error> {
+    let mut fields = Fields::new(document);
+    let mut profile = ProfileFull::default();
+    profile.main.name = fields.take_name("name")?;
+    profile.author = fields.take_name("author")?;
+    for &field in PROFILE_FIELDS {
+        let value = match enum_names(field) {
+            Some(names) => fields.take_enum(field, names)?,
+            None => {
+                let param = schema::param(field).unwrap();
+                fields.take_integer(field, param.min, param.max)?
+            }
+        };
+        profile.main.set_field(field, value);
+    }
+    for &field in PROFILE_INTERNAL_FIELDS {
+        let bits = Profile::internal_field_bits(field).unwrap();
+        let value = fields.take_integer(field, 0, ((1u64 << bits) - 1) as u32)?;
+        profile.main.set_internal_field(field, value);
+    }
+    fields.take_hex("id.mac", &mut profile.main.id.mac)?;
+    fields.take_hex("id.system_id", &mut profile.main.system_id)?;
+    if let Some((path, _)) = fields.0.first() {
+        return Err(Error::ProfileFieldUnknown(path.clone()));
+    }
+    Ok(profile)
+}
+
+impl ProfileFull {
+    pub fn to_json(&self) -> String {
+        let mut result = serde_json::to_string_pretty(&to_document(self)).unwrap();
+        result.push('\n');
+        result
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(&to_document(self)).unwrap()
+    }
+
+    pub fn from_json(text: &str) -> Result<ProfileFull, Error> {
+        let document =
+            serde_json::from_str(text).map_err(|e| Error::ProfileTextCorrupt(e.line()))?;
+        from_document(document)
+    }
+
+    pub fn from_toml(text: &str) -> Result<ProfileFull, Error> {
+        let document = toml::from_str(text)
+            .map_err(|e| Error::ProfileTextCorrupt(e.line_col().map_or(0, |(line, _)| line + 1)))?;
+        from_document(document)
+    }
+}
+
+#[test]
+fn profile_text_test() {
+    use byte_struct::*;
+
+    let time = chrono::NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0);
+    let mut profile = ProfileFull::from_profile(Profile::new([1; 6], [2; 8], time, 42));
+    profile.main.name = name_from_str("Mii \"1\"\\é");
+    // A lone surrogate followed by data after the terminator can only be kept as an array
+    profile.author = [0xD800, 0x41, 0, 0x42, 0, 0, 0, 0, 0, 0];
+    profile.main.general.sex = 0;
+    profile.main.header.region_lock = 2;
+    for &field in PROFILE_INTERNAL_FIELDS {
+        let bits = Profile::internal_field_bits(field).unwrap();
+        profile.main.set_internal_field(field, (1 << bits) - 1);
+    }
+    let mut bytes = vec![0; ProfileFull::BYTE_LEN];
+    profile.write_bytes(&mut bytes[..]);
+
+    let json = profile.to_json();
+    let toml = profile.to_toml();
+    assert!(json.contains("\"name\": \"Mii \\\"1\\\"\\\\é\""));
+    assert!(toml.contains("author = [55296, 65, 0, 66, 0, 0, 0, 0, 0, 0]"));
+    assert!(json.contains("\"sex\": \"male\""));
+    assert!(toml.contains("region_lock = \"usa\""));
+    assert!(toml.contains("\n[eye]\nstyle = 4\n"));
+    for parsed in [
+        ProfileFull::from_json(&json).unwrap(),
+        ProfileFull::from_toml(&toml).unwrap(),
+    ] {
+        let mut parsed_bytes = vec![0; ProfileFull::BYTE_LEN];
+        parsed.write_bytes(&mut parsed_bytes[..]);
+        assert_eq!(parsed_bytes, bytes);
+    }
+
+    // The same profile as a hand-written TOML file, with the hair as dotted keys and the beauty
+    // mark as an inline table
+    let mut top = String::new();
+    let mut tables = String::new();
+    let mut mole = vec![];
+    let mut table = "";
+    for line in toml.lines() {
+        if line.starts_with('[') {
+            table = line.trim_matches(|c| c == '[' || c == ']');
+            if table == "hair" || table == "mole" {
+                continue;
+            }
+        }
+        match table {
+            "" => top += &format!("{}\n", line),
+            "hair" if !line.is_empty() => top += &format!("hair.{}\n", line),
+            "mole" if !line.is_empty() => mole.push(line),
+            "hair" | "mole" => (),
+            _ => tables += &format!("{}\n", line),
+        }
+    }
+    let edited = format!("{}mole = {{ {} }}\n{}", top, mole.join(", "), tables);
+    assert!(!edited.contains("[hair]"));
+    let parsed = ProfileFull::from_toml(&edited).unwrap();
+    let mut parsed_bytes = vec![0; ProfileFull::BYTE_LEN];
+    parsed.write_bytes(&mut parsed_bytes[..]);
+    assert_eq!(parsed_bytes, bytes);
+
+    let commented = format!("# comment\n{}", toml.replace("\n[eye]", "  # eye\n[eye]"));
+    assert!(ProfileFull::from_toml(&commented).is_ok());
+    assert!(matches!(
+        ProfileFull::from_toml(&toml.replace("name = ", "name = # no value\n")),
+        Err(Error::ProfileTextCorrupt(1))
+    ));
+    assert!(matches!(
+        ProfileFull::from_toml(&toml.replace("\n[eye]\nstyle = 4", "\n[eye]\nstyle = 62")),
+        Err(Error::ProfileFieldOutOfRange {
+            min: 0,
+            max: 61,
+            ..
+        })
+    ));
+    assert!(matches!(
+        ProfileFull::from_json(&json.replace("\"male\"", "\"other\"")),
+        Err(Error::ProfileFieldInvalid(f)) if f == "sex"
+    ));
+    assert!(matches!(
+        ProfileFull::from_json(&json.replace("\"width\"", "\"breadth\"")),
+        Err(Error::ProfileFieldMissing(f)) if f == "width"
+    ));
+    assert!(matches!(
+        ProfileFull::from_toml(&(toml.clone() + "extra = 1\n")),
+        Err(Error::ProfileFieldUnknown(f)) if f == "internal.extra"
+    ));
+    assert!(ProfileFull::from_json(&json.replace(",\n  \"author\"", "\n  \"author\"")).is_err());
+}
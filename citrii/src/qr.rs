@@ -0,0 +1,144 @@
+// Mii QR codes as used by the Mii Maker. The QR payload is a .cfsd profile encrypted with
+// AES-128-CCM. The 8 bytes at 0xC..0x14 of the profile (the random part of the profile ID and the
+// creation time) are left in plain text at the front as the nonce, followed by the rest of the
+// profile encrypted, followed by the 16-byte MAC.
+//
+// The key is not included. It must be supplied by the user, dumped from their own console.
+
+use crate::database::*;
+use crate::error::*;
+use crate::qr_reader;
+use ccm::aead::generic_array::GenericArray;
+use ccm::aead::{AeadInPlace, KeyInit};
+use ccm::consts::{U12, U16};
+
+type MiiCcm = ccm::Ccm<aes::Aes128, U16, U12>;
+
+pub type QrKey = [u8; 16];
+
+const NONCE_BEGIN: usize = 0xC;
+const NONCE_END: usize = 0x14;
+const NONCE_LEN: usize = NONCE_END - NONCE_BEGIN;
+const TAG_LEN: usize = 16;
+pub const QR_PAYLOAD_LEN: usize = CFSD_LEN + TAG_LEN;
+
+// Accepts a key file with either 16 raw bytes or 32 hexadecimal digits.
+pub fn parse_key(data: &[u8]) -> Result<QrKey, Error> {
+    let mut key = [0u8; 16];
+    if data.len() == key.len() {
+        key.copy_from_slice(data);
+        return Ok(key);
+    }
+    let text = std::str::from_utf8(data)
+        .map_err(|_| Error::BadQrKey)?
+        .trim();
+    if text.len() != key.len() * 2 {
+        return Err(Error::BadQrKey);
+    }
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = text
+            .get(i * 2..i * 2 + 2)
+            .and_then(|s| u8::from_str_radix(s, 16).ok())
+            .ok_or(Error::BadQrKey)?;
+    }
+    Ok(key)
+}
+
+fn ccm_nonce(nonce: &[u8]) -> [u8; 12] {
+    let mut result = [0u8; 12];
+    result[0..NONCE_LEN].copy_from_slice(nonce);
+    result
+}
+
+pub fn encrypt_profile(profile: &ProfileFull, key: &QrKey) -> Vec<u8> {
+    let cfsd = profile.to_cfsd();
+    let nonce = ccm_nonce(&cfsd[NONCE_BEGIN..NONCE_END]);
+    let mut buffer = [&cfsd[0..NONCE_BEGIN], &cfsd[NONCE_END..]].concat();
+    let cipher = MiiCcm::new(GenericArray::from_slice(key));
+    let tag = cipher
+        .encrypt_in_place_detached(GenericArray::from_slice(&nonce), &[], &mut buffer)
+        .unwrap();
+
+    let mut result = Vec::with_capacity(QR_PAYLOAD_LEN);
+    result.extend_from_slice(&cfsd[NONCE_BEGIN..NONCE_END]);
+    result.extend_from_slice(&buffer);
+    result.extend_from_slice(&tag);
+    result
+}
+
+pub fn decrypt_profile(payload: &[u8], key: &QrKey) -> Result<ProfileFull, Error> {
+    if payload.len() != QR_PAYLOAD_LEN {
+        return Err(Error::QrNotMii);
+    }
+    let nonce = ccm_nonce(&payload[0..NONCE_LEN]);
+    let mut buffer = payload[NONCE_LEN..QR_PAYLOAD_LEN - TAG_LEN].to_vec();
+    let tag = GenericArray::from_slice(&payload[QR_PAYLOAD_LEN - TAG_LEN..]);
+    let cipher = MiiCcm::new(GenericArray::from_slice(key));
+    cipher
+        .decrypt_in_place_detached(GenericArray::from_slice(&nonce), &[], &mut buffer, tag)
+        .map_err(|_| Error::QrNotMii)?;
+
+    let cfsd = [
+        &buffer[0..NONCE_BEGIN],
+        &payload[0..NONCE_LEN],
+        &buffer[NONCE_BEGIN..],
+    ]
+    .concat();
+    ProfileFull::from_cfsd(&cfsd)
+}
+
+// Renders the profile as a QR code with a 4-module quiet zone, `scale` pixels per module.
+pub fn render_profile(profile: &ProfileFull, key: &QrKey, scale: u32) -> image::GrayImage {
+    let code = qrcode::QrCode::with_error_correction_level(
+        encrypt_profile(profile, key),
+        qrcode::EcLevel::M,
+    )
+    .unwrap();
+    let width = code.width() as u32;
+    let colors = code.to_colors();
+    let quiet = 4;
+    let size = (width + quiet * 2) * scale;
+    image::GrayImage::from_fn(size, size, |x, y| {
+        let mx = (x / scale) as i64 - quiet as i64;
+        let my = (y / scale) as i64 - quiet as i64;
+        let dark = mx >= 0
+            && my >= 0
+            && mx < width as i64
+            && my < width as i64
+            && colors[(my * width as i64 + mx) as usize] == qrcode::Color::Dark;
+        image::Luma([if dark { 0 } else { 255 }])
+    })
+}
+
+// Only reads clean, upright images such as those of render_profile. See qr_reader.
+pub fn decode_profile(image: &image::GrayImage, key: &QrKey) -> Result<ProfileFull, Error> {
+    decrypt_profile(&qr_reader::decode_image(image)?, key)
+}
+
+#[test]
+fn qr_round_trip_test() {
+    let time = chrono::NaiveDate::from_ymd(2020, 1, 1).and_hms(12, 34, 56);
+    let mut profile = ProfileFull::from_profile(Profile::new([1; 6], [2; 8], time, 1));
+    profile.main.name = name_from_str("QR Tester");
+    assert!(profile.main.set_field("hair.style", 42));
+    let key = parse_key(b"000102030405060708090a0b0c0d0e0f\n").unwrap();
+    assert_eq!(key[15], 0x0F);
+
+    let payload = encrypt_profile(&profile, &key);
+    assert_eq!(payload.len(), QR_PAYLOAD_LEN);
+    assert_eq!(
+        decrypt_profile(&payload, &key).unwrap().to_cfsd(),
+        profile.to_cfsd()
+    );
+    assert!(decrypt_profile(&payload, &[0; 16]).is_err());
+
+    let mut image = render_profile(&profile, &key, 3);
+    // Scratches a few modules, which should be recovered by error correction
+    for x in 40..52 {
+        for y in 60..63 {
+            image.put_pixel(x, y, image::Luma([0]));
+        }
+    }
+    let decoded = decode_profile(&image, &key).unwrap();
+    assert_eq!(decoded.to_cfsd(), profile.to_cfsd());
+}
@@ -0,0 +1,719 @@
+// A minimal QR code reader for images produced by citrii (qr::render_profile) or screenshots of
+// the same kind. It uses a single global threshold and expects an upright, unrotated and
+// undistorted symbol, so photos taken with a camera are not supported. Only the byte mode is
+// read, which is what Mii QR codes use. Errors are corrected with the Reed-Solomon code embedded
+// in the symbol.
+
+use crate::error::*;
+
+const ECC_CODEWORDS_PER_BLOCK: [[u8; 41]; 4] = [
+    [
+        0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28,
+        30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ], // L
+    [
+        0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28,
+        28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    ], // M
+    [
+        0, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30,
+        30, 30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ], // Q
+    [
+        0, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24,
+        30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ], // H
+];
+
+const NUM_ERROR_CORRECTION_BLOCKS: [[u8; 41]; 4] = [
+    [
+        0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13,
+        14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25,
+    ], // L
+    [
+        0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21,
+        23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+    ], // M
+    [
+        0, 1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29,
+        34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68,
+    ], // Q
+    [
+        0, 1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32,
+        35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81,
+    ], // H
+];
+
+struct Gf256 {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+impl Gf256 {
+    fn new() -> Gf256 {
+        let mut exp = [0u8; 512];
+        let mut log = [0u8; 256];
+        let mut x = 1u16;
+        for (i, e) in exp.iter_mut().take(255).enumerate() {
+            *e = x as u8;
+            log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= 0x11D;
+            }
+        }
+        for i in 255..512 {
+            exp[i] = exp[i - 255];
+        }
+        Gf256 { exp, log }
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            0
+        } else {
+            self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+        }
+    }
+
+    fn div(&self, a: u8, b: u8) -> u8 {
+        assert!(b != 0);
+        if a == 0 {
+            0
+        } else {
+            self.exp[self.log[a as usize] as usize + 255 - self.log[b as usize] as usize]
+        }
+    }
+
+    fn pow_alpha(&self, e: usize) -> u8 {
+        self.exp[e % 255]
+    }
+
+    // Evaluates a polynomial with coefficients in ascending order
+    fn eval(&self, poly: &[u8], x: u8) -> u8 {
+        poly.iter().rev().fold(0, |acc, &c| self.mul(acc, x) ^ c)
+    }
+}
+
+// Corrects a block of data codewords followed by `ecc_len` ECC codewords in place.
+fn correct_block(gf: &Gf256, block: &mut [u8], ecc_len: usize) -> Result<(), Error> {
+    let n = block.len();
+    // The first codeword is the coefficient of the highest power
+    let syndromes: Vec<u8> = (0..ecc_len)
+        .map(|j| {
+            block
+                .iter()
+                .fold(0, |acc, &c| gf.mul(acc, gf.pow_alpha(j)) ^ c)
+        })
+        .collect();
+    if syndromes.iter().all(|&s| s == 0) {
+        return Ok(());
+    }
+
+    // Berlekamp-Massey
+    let mut locator = vec![1u8];
+    let mut prev = vec![1u8];
+    let mut l = 0;
+    let mut m = 1;
+    let mut b = 1u8;
+    for i in 0..ecc_len {
+        let mut d = syndromes[i];
+        for k in 1..=l {
+            d ^= gf.mul(*locator.get(k).unwrap_or(&0), syndromes[i - k]);
+        }
+        if d == 0 {
+            m += 1;
+            continue;
+        }
+        let coef = gf.div(d, b);
+        let mut next = locator.clone();
+        next.resize(std::cmp::max(next.len(), prev.len() + m), 0);
+        for (k, &p) in prev.iter().enumerate() {
+            next[k + m] ^= gf.mul(coef, p);
+        }
+        if 2 * l <= i {
+            l = i + 1 - l;
+            prev = locator;
+            b = d;
+            m = 1;
+        } else {
+            m += 1;
+        }
+        locator = next;
+    }
+    if 2 * l > ecc_len {
+        return Err(Error::QrCorrupt);
+    }
+
+    // Chien search. The codeword at index k corresponds to power n - 1 - k.
+    let positions: Vec<usize> = (0..n)
+        .filter(|&k| gf.eval(&locator, gf.pow_alpha(255 - (n - 1 - k) % 255)) == 0)
+        .collect();
+    if positions.len() != l {
+        return Err(Error::QrCorrupt);
+    }
+
+    // Forney. evaluator = syndromes * locator mod x^ecc_len
+    let mut evaluator = vec![0u8; ecc_len];
+    for (i, &s) in syndromes.iter().enumerate() {
+        for (j, &c) in locator.iter().enumerate() {
+            if i + j < ecc_len {
+                evaluator[i + j] ^= gf.mul(s, c);
+            }
+        }
+    }
+    let derivative: Vec<u8> = locator
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, &c)| if i % 2 == 1 { c } else { 0 })
+        .collect();
+    for k in positions {
+        let x = gf.pow_alpha(n - 1 - k);
+        let x_inv = gf.div(1, x);
+        let denominator = gf.eval(&derivative, x_inv);
+        if denominator == 0 {
+            return Err(Error::QrCorrupt);
+        }
+        block[k] ^= gf.mul(x, gf.div(gf.eval(&evaluator, x_inv), denominator));
+    }
+    Ok(())
+}
+
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return vec![];
+    }
+    let size = version * 4 + 17;
+    let count = version / 7 + 2;
+    let step = if version == 32 {
+        26
+    } else {
+        (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2
+    };
+    let mut result: Vec<usize> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    result.push(6);
+    result.reverse();
+    result
+}
+
+fn raw_data_modules(version: usize) -> usize {
+    let mut result = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let count = version / 7 + 2;
+        result -= (25 * count - 10) * count - 55;
+        if version >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+fn function_modules(version: usize) -> Vec<Vec<bool>> {
+    let size = version * 4 + 17;
+    let mut function = vec![vec![false; size]; size];
+    let mut fill = |x0: usize, y0: usize, w: usize, h: usize| {
+        for row in function.iter_mut().skip(y0).take(h) {
+            for cell in row.iter_mut().skip(x0).take(w) {
+                *cell = true;
+            }
+        }
+    };
+    // Finder patterns, separators and format information
+    fill(0, 0, 9, 9);
+    fill(size - 8, 0, 8, 9);
+    fill(0, size - 8, 9, 8);
+    // Timing patterns
+    fill(6, 0, 1, size);
+    fill(0, 6, size, 1);
+    let alignment = alignment_positions(version);
+    let last = alignment.len().saturating_sub(1);
+    for (i, &x) in alignment.iter().enumerate() {
+        for (j, &y) in alignment.iter().enumerate() {
+            // Skips the corners overlapping with finder patterns
+            if (i == 0 || j == 0) && (i + j == 0 || i + j == last) {
+                continue;
+            }
+            fill(x - 2, y - 2, 5, 5);
+        }
+    }
+    if version >= 7 {
+        fill(size - 11, 0, 3, 6);
+        fill(0, size - 11, 6, 3);
+    }
+    function
+}
+
+fn mask_bit(mask: u32, x: usize, y: usize) -> bool {
+    match mask {
+        0 => (x + y) % 2 == 0,
+        1 => y % 2 == 0,
+        2 => x % 3 == 0,
+        3 => (x + y) % 3 == 0,
+        4 => (x / 3 + y / 2) % 2 == 0,
+        5 => x * y % 2 + x * y % 3 == 0,
+        6 => (x * y % 2 + x * y % 3) % 2 == 0,
+        _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+    }
+}
+
+// Returns (error correction level index in L, M, Q, H order, mask)
+fn decode_format(bits: u32) -> Option<(usize, u32)> {
+    let mut best = None;
+    let mut best_distance = 4;
+    for data in 0..32u32 {
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let code = (data << 10 | rem) ^ 0x5412;
+        let distance = (code ^ bits).count_ones();
+        if distance < best_distance {
+            best_distance = distance;
+            best = Some(data);
+        }
+    }
+    best.map(|data| {
+        let level = match data >> 3 {
+            1 => 0,
+            0 => 1,
+            3 => 2,
+            _ => 3,
+        };
+        (level, data & 7)
+    })
+}
+
+// Decodes the module grid (true for dark) of a QR symbol into its payload.
+pub fn decode_modules(modules: &[Vec<bool>]) -> Result<Vec<u8>, Error> {
+    let size = modules.len();
+    if size < 21 || (size - 17) % 4 != 0 || size > 177 {
+        return Err(Error::QrNotFound);
+    }
+    if modules.iter().any(|row| row.len() != size) {
+        return Err(Error::QrNotFound);
+    }
+    let version = (size - 17) / 4;
+    let get = |x: usize, y: usize| modules[y][x];
+
+    let mut format_a = 0;
+    let mut format_b = 0;
+    for i in 0..15 {
+        let (xa, ya) = match i {
+            0..=5 => (8, i),
+            6 => (8, 7),
+            7 => (8, 8),
+            8 => (7, 8),
+            _ => (14 - i, 8),
+        };
+        let (xb, yb) = if i < 8 {
+            (size - 1 - i, 8)
+        } else {
+            (8, size - 15 + i)
+        };
+        format_a |= (get(xa, ya) as u32) << i;
+        format_b |= (get(xb, yb) as u32) << i;
+    }
+    let (level, mask) = decode_format(format_a)
+        .or_else(|| decode_format(format_b))
+        .ok_or(Error::QrCorrupt)?;
+
+    let function = function_modules(version);
+    let raw_codewords = raw_data_modules(version) / 8;
+    let mut codewords = vec![0u8; raw_codewords];
+    let mut i = 0;
+    let mut right = size - 1;
+    while right >= 1 {
+        if right == 6 {
+            right = 5;
+        }
+        for vert in 0..size {
+            for j in 0..2 {
+                let x = right - j;
+                let upward = (right + 1) & 2 == 0;
+                let y = if upward { size - 1 - vert } else { vert };
+                if !function[y][x] && i < raw_codewords * 8 {
+                    if get(x, y) != mask_bit(mask, x, y) {
+                        codewords[i >> 3] |= 1 << (7 - (i & 7));
+                    }
+                    i += 1;
+                }
+            }
+        }
+        if right < 2 {
+            break;
+        }
+        right -= 2;
+    }
+
+    let block_count = NUM_ERROR_CORRECTION_BLOCKS[level][version] as usize;
+    let ecc_len = ECC_CODEWORDS_PER_BLOCK[level][version] as usize;
+    let short_block_count = block_count - raw_codewords % block_count;
+    let short_block_len = raw_codewords / block_count;
+    let mut blocks: Vec<Vec<u8>> = (0..block_count)
+        .map(|j| {
+            let len = short_block_len + if j < short_block_count { 0 } else { 1 };
+            Vec::with_capacity(len)
+        })
+        .collect();
+    let mut k = 0;
+    for i in 0..=short_block_len {
+        for (j, block) in blocks.iter_mut().enumerate() {
+            // Short blocks have one less data codeword, so they skip the last data column
+            if i == short_block_len - ecc_len && j < short_block_count {
+                continue;
+            }
+            block.push(codewords[k]);
+            k += 1;
+        }
+    }
+
+    let gf = Gf256::new();
+    let mut data = vec![];
+    for mut block in blocks {
+        correct_block(&gf, &mut block, ecc_len)?;
+        let data_len = block.len() - ecc_len;
+        data.extend_from_slice(&block[0..data_len]);
+    }
+
+    parse_segments(&data, version)
+}
+
+fn parse_segments(data: &[u8], version: usize) -> Result<Vec<u8>, Error> {
+    let mut bit = 0;
+    let total_bits = data.len() * 8;
+    let mut read = |count: usize| -> Option<usize> {
+        if bit + count > total_bits {
+            return None;
+        }
+        let mut value = 0;
+        for _ in 0..count {
+            value = value << 1 | ((data[bit >> 3] >> (7 - (bit & 7))) & 1) as usize;
+            bit += 1;
+        }
+        Some(value)
+    };
+
+    let mut result = vec![];
+    loop {
+        match read(4) {
+            None | Some(0) => break,
+            Some(4) => {
+                let count_bits = if version <= 9 { 8 } else { 16 };
+                let count = read(count_bits).ok_or(Error::QrCorrupt)?;
+                for _ in 0..count {
+                    result.push(read(8).ok_or(Error::QrCorrupt)? as u8);
+                }
+            }
+            Some(7) => {
+                // ECI designator, which doesn't matter for binary data
+                read(8).ok_or(Error::QrCorrupt)?;
+            }
+            Some(_) => return Err(Error::QrUnsupported),
+        }
+    }
+    Ok(result)
+}
+
+struct FinderPattern {
+    x: f32,
+    y: f32,
+    module: f32,
+    hits: usize,
+}
+
+fn check_ratio(runs: &[usize]) -> Option<f32> {
+    let total: usize = runs.iter().sum();
+    if total < 7 {
+        return None;
+    }
+    let module = total as f32 / 7.0;
+    let expected = [1.0, 1.0, 3.0, 1.0, 1.0];
+    for (&run, &e) in runs.iter().zip(expected.iter()) {
+        if (run as f32 - module * e).abs() > module * e * 0.5 + 0.5 {
+            return None;
+        }
+    }
+    Some(module)
+}
+
+// Looks for the 1:1:3:1:1 pattern vertically through (x, y) and returns the center y.
+fn cross_check_vertical(dark: &[Vec<bool>], x: usize, y: usize) -> Option<f32> {
+    let height = dark.len();
+    let run = |from: usize, up: bool, color: bool| -> usize {
+        let mut count = 0;
+        let mut cur = from;
+        loop {
+            if dark[cur][x] != color {
+                break;
+            }
+            count += 1;
+            if up {
+                if cur == 0 {
+                    break;
+                }
+                cur -= 1;
+            } else {
+                if cur + 1 >= height {
+                    break;
+                }
+                cur += 1;
+            }
+        }
+        count
+    };
+    if !dark[y][x] {
+        return None;
+    }
+    let center_up = run(y, true, true);
+    let center_down = run(y, false, true);
+    let top = y + 1 - center_up;
+    let bottom = y + center_down - 1;
+    let light_up = if top == 0 {
+        0
+    } else {
+        run(top - 1, true, false)
+    };
+    let light_down = if bottom + 1 >= height {
+        0
+    } else {
+        run(bottom + 1, false, false)
+    };
+    if light_up == 0 || light_down == 0 || top < light_up + 1 || bottom + light_down + 1 >= height {
+        return None;
+    }
+    let dark_up = run(top - light_up - 1, true, true);
+    let dark_down = run(bottom + light_down + 1, false, true);
+    check_ratio(&[
+        dark_up,
+        light_up,
+        center_up + center_down - 1,
+        light_down,
+        dark_down,
+    ])?;
+    Some((top + bottom) as f32 * 0.5 + 0.5)
+}
+
+fn find_finder_patterns(dark: &[Vec<bool>]) -> Vec<FinderPattern> {
+    let mut patterns: Vec<FinderPattern> = vec![];
+    for (y, row) in dark.iter().enumerate() {
+        let mut runs: Vec<(bool, usize, usize)> = vec![]; // (color, start, length)
+        for (x, &d) in row.iter().enumerate() {
+            match runs.last_mut() {
+                Some((color, _, len)) if *color == d => *len += 1,
+                _ => runs.push((d, x, 1)),
+            }
+        }
+        for window in runs.windows(5) {
+            if !window[0].0 {
+                continue;
+            }
+            let lens: Vec<usize> = window.iter().map(|r| r.2).collect();
+            let module = match check_ratio(&lens) {
+                Some(module) => module,
+                None => continue,
+            };
+            let center_x = window[2].1 as f32 + window[2].2 as f32 * 0.5;
+            let center_y = match cross_check_vertical(dark, center_x as usize, y) {
+                Some(center_y) => center_y,
+                None => continue,
+            };
+            match patterns.iter_mut().find(|p| {
+                (p.x - center_x).abs() < p.module * 2.0 && (p.y - center_y).abs() < p.module * 2.0
+            }) {
+                Some(p) => {
+                    let n = p.hits as f32;
+                    p.x = (p.x * n + center_x) / (n + 1.0);
+                    p.y = (p.y * n + center_y) / (n + 1.0);
+                    p.module = (p.module * n + module) / (n + 1.0);
+                    p.hits += 1;
+                }
+                None => patterns.push(FinderPattern {
+                    x: center_x,
+                    y: center_y,
+                    module,
+                    hits: 1,
+                }),
+            }
+        }
+    }
+    patterns
+}
+
+// Finds and decodes a QR symbol in a grayscale image.
+pub fn decode_image(image: &image::GrayImage) -> Result<Vec<u8>, Error> {
+    let (width, height) = image.dimensions();
+    let (min, max) = image
+        .pixels()
+        .fold((255u8, 0u8), |(min, max), p| (min.min(p[0]), max.max(p[0])));
+    if min == max {
+        return Err(Error::QrNotFound);
+    }
+    let threshold = (min as u16 + max as u16) / 2;
+    let dark: Vec<Vec<bool>> = (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| (image.get_pixel(x, y)[0] as u16) < threshold)
+                .collect()
+        })
+        .collect();
+
+    let patterns = find_finder_patterns(&dark);
+
+    // Looks for top-left, top-right and bottom-left patterns forming an upright square
+    let mut best: Option<(usize, usize, usize)> = None;
+    let mut best_hits = 0;
+    for (a, pa) in patterns.iter().enumerate() {
+        for (b, pb) in patterns.iter().enumerate() {
+            for (c, pc) in patterns.iter().enumerate() {
+                let tolerance = pa.module * 3.0;
+                let dx = pb.x - pa.x;
+                let dy = pc.y - pa.y;
+                if dx < pa.module * 14.0
+                    || dy < pa.module * 14.0
+                    || (pb.y - pa.y).abs() > tolerance
+                    || (pc.x - pa.x).abs() > tolerance
+                    || (dx - dy).abs() > tolerance
+                {
+                    continue;
+                }
+                let hits = pa.hits + pb.hits + pc.hits;
+                if hits > best_hits {
+                    best_hits = hits;
+                    best = Some((a, b, c));
+                }
+            }
+        }
+    }
+    let (a, b, c) = best.ok_or(Error::QrNotFound)?;
+    let (pa, pb, pc) = (&patterns[a], &patterns[b], &patterns[c]);
+    let module = (pa.module + pb.module + pc.module) / 3.0;
+    let estimated = ((pb.x - pa.x + pc.y - pa.y) * 0.5 / module + 7.0 - 17.0) / 4.0;
+
+    // Tries the closest versions first in case the module size estimation is a bit off
+    let mut candidates: Vec<i32> = (1..=40).collect();
+    candidates.sort_by(|&u, &v| {
+        (u as f32 - estimated)
+            .abs()
+            .partial_cmp(&(v as f32 - estimated).abs())
+            .unwrap()
+    });
+    let mut error = Error::QrNotFound;
+    for version in candidates.into_iter().take(3) {
+        let size = version as usize * 4 + 17;
+        let pitch_x = (pb.x - pa.x) / (size - 7) as f32;
+        let pitch_y = (pc.y - pa.y) / (size - 7) as f32;
+        let modules: Vec<Vec<bool>> = (0..size)
+            .map(|y| {
+                (0..size)
+                    .map(|x| {
+                        let px = pa.x + (x as f32 - 3.0) * pitch_x;
+                        let py = pa.y + (y as f32 - 3.0) * pitch_y;
+                        let px = (px.max(0.0) as usize).min(width as usize - 1);
+                        let py = (py.max(0.0) as usize).min(height as usize - 1);
+                        dark[py][px]
+                    })
+                    .collect()
+            })
+            .collect();
+        match decode_modules(&modules) {
+            Ok(data) => return Ok(data),
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+// Draws `data` as a symbol with the given mask, which qrcode::QrCode would otherwise choose itself.
+// The codewords can be changed before they are drawn.
+#[cfg(test)]
+fn encode_test_symbol(
+    data: &[u8],
+    version: i16,
+    ec_level: qrcode::EcLevel,
+    mask: qrcode::canvas::MaskPattern,
+    corrupt: impl Fn(&mut Vec<u8>),
+) -> Vec<Vec<bool>> {
+    let version = qrcode::Version::Normal(version);
+    let mut bits = qrcode::bits::Bits::new(version);
+    bits.push_byte_data(data).unwrap();
+    bits.push_terminator(ec_level).unwrap();
+    let (mut codewords, ecc) =
+        qrcode::ec::construct_codewords(&bits.into_bytes(), version, ec_level).unwrap();
+    corrupt(&mut codewords);
+    let mut canvas = qrcode::canvas::Canvas::new(version, ec_level);
+    canvas.draw_all_functional_patterns();
+    canvas.draw_data(&codewords, &ecc);
+    canvas.apply_mask(mask);
+    let size = version.width() as usize;
+    let modules = canvas.into_colors();
+    modules
+        .chunks(size)
+        .map(|row| row.iter().map(|&c| c == qrcode::Color::Dark).collect())
+        .collect()
+}
+
+#[test]
+fn qr_reader_test() {
+    use qrcode::canvas::MaskPattern::*;
+    use qrcode::EcLevel;
+
+    let data = b"citrii QR reader";
+    let masks = [
+        Checkerboard,
+        HorizontalLines,
+        VerticalLines,
+        DiagonalLines,
+        LargeCheckerboard,
+        Fields,
+        Diamonds,
+        Meadow,
+    ];
+    for &mask in &masks {
+        for &(version, ec_level) in &[(1, EcLevel::L), (5, EcLevel::Q), (10, EcLevel::H)] {
+            let modules = encode_test_symbol(data, version, ec_level, mask, |_| ());
+            assert_eq!(decode_modules(&modules).unwrap(), data, "{:?}", mask);
+        }
+    }
+
+    // Each mask also read from an image, with a quiet zone of 4 modules of 3 pixels
+    for &mask in &masks {
+        let modules = encode_test_symbol(data, 3, EcLevel::M, mask, |_| ());
+        let image = image::GrayImage::from_fn(29 * 3 + 24, 29 * 3 + 24, |x, y| {
+            let (x, y) = ((x / 3) as usize, (y / 3) as usize);
+            let dark = x >= 4
+                && y >= 4
+                && modules.get(y - 4).and_then(|row| row.get(x - 4)) == Some(&true);
+            image::Luma([if dark { 0 } else { 255 }])
+        });
+        assert_eq!(decode_image(&image).unwrap(), data, "{:?}", mask);
+    }
+
+    // Version 5-Q has 4 blocks of 18 error correction codewords, which correct 9 errors each
+    let corrupt_some = |codewords: &mut Vec<u8>| {
+        for codeword in codewords.iter_mut().step_by(3) {
+            *codeword ^= 0x5A;
+        }
+    };
+    let modules = encode_test_symbol(data, 5, EcLevel::Q, Meadow, corrupt_some);
+    assert_eq!(decode_modules(&modules).unwrap(), data);
+    let corrupt_all = |codewords: &mut Vec<u8>| codewords.iter_mut().for_each(|c| *c ^= 0xFF);
+    let modules = encode_test_symbol(data, 5, EcLevel::Q, Meadow, corrupt_all);
+    assert!(decode_modules(&modules).is_err());
+
+    // Malformed input is rejected rather than read out of bounds
+    let mut modules = encode_test_symbol(data, 1, EcLevel::L, Meadow, |_| ());
+    assert!(decode_modules(&modules[..20]).is_err());
+    modules[3].pop();
+    assert!(decode_modules(&modules).is_err());
+    assert!(decode_modules(&vec![vec![false; 25]; 25]).is_err());
+    assert!(decode_modules(&[]).is_err());
+    for &(width, height) in &[(1, 1), (3, 7), (50, 2), (100, 100)] {
+        let blank = image::GrayImage::new(width, height);
+        assert!(decode_image(&blank).is_err());
+        let noise = image::GrayImage::from_fn(width, height, |x, y| {
+            image::Luma([((x * 7 + y * 13) % 5 * 60) as u8])
+        });
+        assert!(decode_image(&noise).is_err());
+    }
+}
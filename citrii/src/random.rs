@@ -0,0 +1,169 @@
+// Generation of random Miis. The same seed and constraints always give the same Mii.
+
+use crate::database::*;
+use crate::schema::*;
+
+// SplitMix64
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in min..=max
+    fn range(&mut self, min: u32, max: u32) -> u32 {
+        min + (self.next() % (u64::from(max - min) + 1)) as u32
+    }
+
+    fn chance(&mut self, percent: u32) -> bool {
+        self.range(0, 99) < percent
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RandomConstraints {
+    pub sex: Option<u32>,
+    pub skin_color: Option<u32>,
+    pub no_glasses: bool,
+}
+
+// Fields picked from their whole range
+const UNIFORM_FIELDS: &[&str] = &[
+    "favorite_color",
+    "face.style",
+    "face.color",
+    "hair.style",
+    "hair.color",
+    "hair.flip",
+    "eye.style",
+    "eye.color",
+    "eyebrow.style",
+    "nose.style",
+    "lip.style",
+    "lip.color",
+    "glass.color",
+];
+
+// Fields picked around the value of a new profile, with the largest distance from it
+const NEAR_DEFAULT_FIELDS: &[(&str, u32)] = &[
+    ("height", 40),
+    ("width", 40),
+    ("eye.scale", 2),
+    ("eye.y_scale", 2),
+    ("eye.rotation", 2),
+    ("eye.x", 2),
+    ("eye.y", 3),
+    ("eyebrow.scale", 2),
+    ("eyebrow.y_scale", 2),
+    ("eyebrow.rotation", 2),
+    ("eyebrow.x", 2),
+    ("eyebrow.y", 2),
+    ("nose.scale", 2),
+    ("nose.y", 3),
+    ("lip.scale", 2),
+    ("lip.y_scale", 2),
+    ("lip.y", 3),
+    ("mustache.scale", 2),
+    ("mustache.y", 2),
+    ("glass.scale", 2),
+    ("glass.y", 2),
+    ("mole.scale", 2),
+    ("mole.x", 8),
+    ("mole.y", 10),
+];
+
+// Parts that most Miis don't have, with the chance in percent for (male, female)
+const OPTIONAL_FIELDS: &[(&str, u32, u32)] = &[
+    ("face.wrinkle", 15, 15),
+    ("face.makeup", 5, 30),
+    ("glass.style", 25, 25),
+    ("mustache.style", 30, 0),
+    ("beard.style", 25, 0),
+    ("mole.style", 15, 15),
+];
+
+// Replaces the appearance of the profile with a random one. The ID, name, birthday and the other
+// settings are kept.
+pub fn randomize(profile: &mut Profile, seed: u64, constraints: &RandomConstraints) {
+    let mut rng = Rng(seed);
+    let epoch = chrono::NaiveDate::from_ymd(2010, 1, 1).and_hms(0, 0, 0);
+    let defaults = Profile::new([0; 6], [0; 8], epoch, 0);
+    let set =
+        |profile: &mut Profile, field: &str, value: u32| param(field).unwrap().set(profile, value);
+
+    let sex = match constraints.sex {
+        Some(sex) => sex,
+        None => rng.range(0, 1),
+    };
+    set(profile, "sex", sex);
+
+    for &field in UNIFORM_FIELDS {
+        let param = param(field).unwrap();
+        param.set(profile, rng.range(param.min, param.max));
+    }
+    if let Some(color) = constraints.skin_color {
+        set(profile, "face.color", color);
+    }
+    profile.eyebrow.color = profile.hair.color as u32;
+    profile.beard.color = profile.hair.color;
+
+    for &(field, distance) in NEAR_DEFAULT_FIELDS {
+        let default = defaults.get_field(field).unwrap();
+        let value = (default + rng.range(0, distance * 2)).saturating_sub(distance);
+        set(profile, field, value);
+    }
+
+    for &(field, male, female) in OPTIONAL_FIELDS {
+        let chance = if sex == 0 { male } else { female };
+        let max = param(field).unwrap().max;
+        let value = if rng.chance(chance) {
+            rng.range(1, max)
+        } else {
+            0
+        };
+        set(profile, field, value);
+    }
+    if constraints.no_glasses {
+        profile.glass.style = 0;
+    }
+}
+
+#[test]
+fn random_test() {
+    let time = chrono::NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0);
+    let original = Profile::new([1; 6], [2; 8], time, 0);
+    let constraints = RandomConstraints {
+        sex: Some(1),
+        skin_color: Some(4),
+        no_glasses: true,
+    };
+    let mut a = original.clone();
+    randomize(&mut a, 42, &constraints);
+    let mut b = original.clone();
+    randomize(&mut b, 42, &constraints);
+    for field in PROFILE_FIELDS {
+        assert_eq!(a.get_field(field), b.get_field(field));
+    }
+    assert_eq!(a.id, original.id);
+
+    let mut differs = false;
+    for seed in 0..100 {
+        let mut profile = original.clone();
+        randomize(&mut profile, seed, &constraints);
+        assert_eq!(profile.general.sex, 1);
+        assert_eq!(profile.face.color, 4);
+        assert_eq!(profile.glass.style, 0);
+        assert_eq!(profile.misc.mustache_style, 0);
+        for param in PARAMS {
+            let value = param.get(&profile);
+            assert!(param.contains(value), "{} = {}", param.field, value);
+        }
+        differs |= profile.hair.style != a.hair.style;
+    }
+    assert!(differs);
+}
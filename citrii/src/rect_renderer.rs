@@ -1,6 +1,6 @@
-use crate::model;
-use crate::shader;
-use crate::texture;
+use citrii::model;
+use citrii::shader;
+use citrii::texture;
 
 pub struct RectRenderer {
     rect_shader: shader::Shader,
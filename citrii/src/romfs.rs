@@ -1,4 +1,6 @@
+use crate::error::*;
 use byte_struct::*;
+use std::convert::TryFrom;
 
 #[derive(ByteStruct)]
 #[byte_struct_le]
@@ -37,49 +39,501 @@ struct FileMetadata {
     name_length: u32,
 }
 
-pub fn get_romfs_file<'a>(romfs: &'a [u8], path: &[String]) -> Option<&'a [u8]> {
-    const INVALID_FIELD: u32 = 0xFFFFFFFF;
-    let dir_names = &path[0..path.len() - 1];
-    let file_name = path.last().unwrap();
+const INVALID_FIELD: u32 = 0xFFFFFFFF;
 
-    let header = Header::read_bytes(&romfs[0..Header::BYTE_LEN]);
-    let root = header.dir_table_offset as usize;
-    let mut dir = DirectoryMetadata::read_bytes(&romfs[root..root + DirectoryMetadata::BYTE_LEN]);
-    for dir_name in dir_names {
-        let dir_name_utf16: Vec<[u8; 2]> =
-            dir_name.encode_utf16().map(|c| c.to_le_bytes()).collect();
-        let dir_name_bytes: Vec<u8> = dir_name_utf16.iter().flatten().cloned().collect();
-        let mut child_dir_offset = dir.first_child_dir_offset;
-        loop {
-            if child_dir_offset == INVALID_FIELD {
-                return None;
+fn name_to_utf16(name: &str) -> Vec<u16> {
+    name.encode_utf16().collect()
+}
+
+fn utf16_to_bytes(name: &[u16]) -> Vec<u8> {
+    name.iter().flat_map(|c| c.to_le_bytes().to_vec()).collect()
+}
+
+fn path_hash(parent_offset: u32, name: &[u16]) -> u32 {
+    let mut hash = parent_offset ^ 123456789;
+    for c in name {
+        hash = hash.rotate_right(5) ^ *c as u32;
+    }
+    hash
+}
+
+fn hash_table_len(entry_count: usize) -> usize {
+    if entry_count < 3 {
+        3
+    } else if entry_count < 19 {
+        entry_count | 1
+    } else {
+        let mut len = entry_count;
+        while [2, 3, 5, 7, 11, 13, 17].iter().any(|p| len % *p == 0) {
+            len += 1;
+        }
+        len
+    }
+}
+
+fn align(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+fn utf16_to_name(bytes: &[u8]) -> Result<String, Error> {
+    if bytes.len() % 2 != 0 {
+        return Err(Error::RomfsCorrupt);
+    }
+    let name: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16(&name).map_err(|_| Error::RomfsCorrupt)
+}
+
+// The path of a file split into directory names and file name, with the file content
+pub type RomfsEntry<'a> = (Vec<String>, &'a [u8]);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirectoryEntryKind {
+    Directory,
+    File { size: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryEntry {
+    pub name: String,
+    pub kind: DirectoryEntryKind,
+}
+
+// A read-only view of a RomFS image (the level 3 data). All offsets are checked against the
+// image, so a truncated or malicious image results in Error::RomfsCorrupt instead of a panic or
+// an endless loop.
+pub struct RomFs<'a> {
+    dir_hash_table: &'a [u8],
+    dir_table: &'a [u8],
+    file_hash_table: &'a [u8],
+    file_table: &'a [u8],
+    file_data: &'a [u8],
+}
+
+fn get_slice(romfs: &[u8], begin: usize, len: usize) -> Result<&[u8], Error> {
+    begin
+        .checked_add(len)
+        .and_then(|end| romfs.get(begin..end))
+        .ok_or(Error::RomfsCorrupt)
+}
+
+fn read_u32(table: &[u8], index: usize) -> Result<u32, Error> {
+    let bytes = get_slice(table, index * 4, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+impl<'a> RomFs<'a> {
+    pub fn new(data: &'a [u8]) -> Result<RomFs<'a>, Error> {
+        let header = Header::read_bytes(get_slice(data, 0, Header::BYTE_LEN)?);
+        if header.header_length as usize != Header::BYTE_LEN {
+            return Err(Error::RomfsCorrupt);
+        }
+        let region = |offset: u32, len: u32| get_slice(data, offset as usize, len as usize);
+        let dir_hash_table = region(header.dir_hash_table_offset, header.dir_hash_table_length)?;
+        let dir_table = region(header.dir_table_offset, header.dir_table_length)?;
+        let file_hash_table = region(header.file_hash_table_offset, header.file_hash_table_length)?;
+        let file_table = region(header.file_table_offset, header.file_table_length)?;
+        let file_data = data
+            .get(header.data_offset as usize..)
+            .ok_or(Error::RomfsCorrupt)?;
+        for table in &[dir_hash_table, file_hash_table] {
+            if table.is_empty() || table.len() % 4 != 0 {
+                return Err(Error::RomfsCorrupt);
             }
-            let current_child_dir = (header.dir_table_offset + child_dir_offset) as usize;
-            let name_begin = current_child_dir + DirectoryMetadata::BYTE_LEN;
-            dir = DirectoryMetadata::read_bytes(&romfs[current_child_dir..name_begin]);
-            if dir_name_bytes == romfs[name_begin..name_begin + dir.name_length as usize] {
-                break;
+        }
+        Ok(RomFs {
+            dir_hash_table,
+            dir_table,
+            file_hash_table,
+            file_table,
+            file_data,
+        })
+    }
+
+    fn dir(&self, offset: u32) -> Result<(DirectoryMetadata, &'a [u8]), Error> {
+        let offset = offset as usize;
+        let metadata = DirectoryMetadata::read_bytes(get_slice(
+            self.dir_table,
+            offset,
+            DirectoryMetadata::BYTE_LEN,
+        )?);
+        let name = get_slice(
+            self.dir_table,
+            offset + DirectoryMetadata::BYTE_LEN,
+            metadata.name_length as usize,
+        )?;
+        Ok((metadata, name))
+    }
+
+    fn file(&self, offset: u32) -> Result<(FileMetadata, &'a [u8]), Error> {
+        let offset = offset as usize;
+        let metadata =
+            FileMetadata::read_bytes(get_slice(self.file_table, offset, FileMetadata::BYTE_LEN)?);
+        let name = get_slice(
+            self.file_table,
+            offset + FileMetadata::BYTE_LEN,
+            metadata.name_length as usize,
+        )?;
+        Ok((metadata, name))
+    }
+
+    fn file_content(&self, metadata: &FileMetadata) -> Result<&'a [u8], Error> {
+        let begin = usize::try_from(metadata.data_offset).map_err(|_| Error::RomfsCorrupt)?;
+        let len = usize::try_from(metadata.data_length).map_err(|_| Error::RomfsCorrupt)?;
+        get_slice(self.file_data, begin, len)
+    }
+
+    // Follows a same-hash chain from the bucket of (parent, name). The chain can't be longer
+    // than the number of entries that fit into the table.
+    fn hash_lookup(
+        &self,
+        hash_table: &[u8],
+        table_len: usize,
+        entry_len: usize,
+        parent: u32,
+        name: &str,
+        read: impl Fn(u32) -> Result<(u32, u32, &'a [u8]), Error>,
+    ) -> Result<Option<u32>, Error> {
+        let name_utf16 = name_to_utf16(name);
+        let name_bytes = utf16_to_bytes(&name_utf16);
+        let bucket_count = hash_table.len() / 4;
+        let bucket = path_hash(parent, &name_utf16) as usize % bucket_count;
+        let mut offset = read_u32(hash_table, bucket)?;
+        let mut steps = 0;
+        while offset != INVALID_FIELD {
+            steps += 1;
+            if steps > table_len / entry_len + 1 {
+                return Err(Error::RomfsCorrupt);
+            }
+            let (entry_parent, next, entry_name) = read(offset)?;
+            if entry_parent == parent && entry_name == &name_bytes[..] {
+                return Ok(Some(offset));
             }
-            child_dir_offset = dir.next_dir_offset;
+            offset = next;
+        }
+        Ok(None)
+    }
+
+    fn find_dir(&self, parent: u32, name: &str) -> Result<Option<u32>, Error> {
+        self.hash_lookup(
+            self.dir_hash_table,
+            self.dir_table.len(),
+            DirectoryMetadata::BYTE_LEN,
+            parent,
+            name,
+            |offset| {
+                let (dir, name) = self.dir(offset)?;
+                Ok((dir.parent_dir_offset, dir.same_hash_next_dir_offet, name))
+            },
+        )
+    }
+
+    fn find_file(&self, parent: u32, name: &str) -> Result<Option<u32>, Error> {
+        self.hash_lookup(
+            self.file_hash_table,
+            self.file_table.len(),
+            FileMetadata::BYTE_LEN,
+            parent,
+            name,
+            |offset| {
+                let (file, name) = self.file(offset)?;
+                Ok((
+                    file.parent_dir_offset,
+                    file.same_hash_next_file_offset,
+                    name,
+                ))
+            },
+        )
+    }
+
+    // Offset of the directory in the directory table. The root directory has offset 0.
+    fn dir_offset(&self, path: &[String]) -> Result<u32, Error> {
+        let mut offset = 0;
+        for (i, name) in path.iter().enumerate() {
+            offset = self
+                .find_dir(offset, name)?
+                .ok_or_else(|| Error::RomfsPathNotFound(path[..=i].join("/")))?;
         }
+        Ok(offset)
     }
 
-    let file_name_utf16: Vec<[u8; 2]> = file_name.encode_utf16().map(|c| c.to_le_bytes()).collect();
-    let file_name_bytes: Vec<u8> = file_name_utf16.iter().flatten().cloned().collect();
+    // Looks the file up through the hash tables
+    pub fn get_file(&self, path: &[String]) -> Result<&'a [u8], Error> {
+        let not_found = || Error::RomfsPathNotFound(path.join("/"));
+        let (file_name, dir_names) = path.split_last().ok_or_else(not_found)?;
+        let dir = self.dir_offset(dir_names)?;
+        let offset = self.find_file(dir, file_name)?.ok_or_else(not_found)?;
+        let (file, _) = self.file(offset)?;
+        self.file_content(&file)
+    }
 
-    let mut file_offset = dir.first_file_offset;
-    while file_offset != INVALID_FIELD {
-        let current_file = (header.file_table_offset + file_offset) as usize;
-        let name_begin = current_file + FileMetadata::BYTE_LEN;
-        let file = FileMetadata::read_bytes(&romfs[current_file..name_begin]);
-        if file_name_bytes == romfs[name_begin..name_begin + file.name_length as usize] {
-            let file_offset = (header.data_offset as u64 + file.data_offset) as usize;
-            return Some(&romfs[file_offset..file_offset + file.data_length as usize]);
+    // Lists the subdirectories and then the files of a directory in the stored order
+    pub fn read_dir(&self, path: &[String]) -> Result<Vec<DirectoryEntry>, Error> {
+        let mut result = vec![];
+        let (dir, _) = self.dir(self.dir_offset(path)?)?;
+
+        let mut visited = std::collections::HashSet::new();
+        let mut child_offset = dir.first_child_dir_offset;
+        while child_offset != INVALID_FIELD {
+            if !visited.insert(child_offset) {
+                return Err(Error::RomfsCorrupt);
+            }
+            let (child, name) = self.dir(child_offset)?;
+            result.push(DirectoryEntry {
+                name: utf16_to_name(name)?,
+                kind: DirectoryEntryKind::Directory,
+            });
+            child_offset = child.next_dir_offset;
+        }
+
+        visited.clear();
+        let mut file_offset = dir.first_file_offset;
+        while file_offset != INVALID_FIELD {
+            if !visited.insert(file_offset) {
+                return Err(Error::RomfsCorrupt);
+            }
+            let (file, name) = self.file(file_offset)?;
+            result.push(DirectoryEntry {
+                name: utf16_to_name(name)?,
+                kind: DirectoryEntryKind::File {
+                    size: file.data_length,
+                },
+            });
+            file_offset = file.next_file_offset;
         }
-        file_offset = file.next_file_offset;
+        Ok(result)
     }
 
-    None
+    // Every file with its path, in depth-first order. Empty directories are not reported.
+    pub fn files(&self) -> Result<Vec<RomfsEntry<'a>>, Error> {
+        let mut files = vec![];
+        let mut visited = std::collections::HashSet::new();
+        visited.insert((true, 0));
+        // Directories to visit with their path
+        let mut stack = vec![(0u32, vec![])];
+        while let Some((dir_offset, path)) = stack.pop() {
+            let (dir, _) = self.dir(dir_offset)?;
+
+            let mut file_offset = dir.first_file_offset;
+            while file_offset != INVALID_FIELD {
+                if !visited.insert((false, file_offset)) {
+                    return Err(Error::RomfsCorrupt);
+                }
+                let (file, name) = self.file(file_offset)?;
+                let mut file_path: Vec<String> = path.clone();
+                file_path.push(utf16_to_name(name)?);
+                files.push((file_path, self.file_content(&file)?));
+                file_offset = file.next_file_offset;
+            }
+
+            let mut children = vec![];
+            let mut child_offset = dir.first_child_dir_offset;
+            while child_offset != INVALID_FIELD {
+                if !visited.insert((true, child_offset)) {
+                    return Err(Error::RomfsCorrupt);
+                }
+                let (child, name) = self.dir(child_offset)?;
+                let mut child_path = path.clone();
+                child_path.push(utf16_to_name(name)?);
+                children.push((child_offset, child_path));
+                child_offset = child.next_dir_offset;
+            }
+            stack.extend(children.into_iter().rev());
+        }
+        Ok(files)
+    }
+}
+
+pub fn get_romfs_file<'a>(romfs: &'a [u8], path: &[String]) -> Result<&'a [u8], Error> {
+    RomFs::new(romfs)?.get_file(path)
+}
+
+// Builds a RomFS image (the level 3 data, as read by get_romfs_file) containing the given
+// files. Directories are created from the paths.
+pub fn build_romfs(files: &[RomfsEntry]) -> Vec<u8> {
+    struct Directory {
+        name: Vec<u16>,
+        parent: usize,
+        children: Vec<usize>,
+        files: Vec<usize>,
+        offset: u32,
+    }
+    struct File<'a> {
+        name: Vec<u16>,
+        parent: usize,
+        data: &'a [u8],
+        offset: u32,
+        data_offset: u64,
+    }
+
+    let mut dirs = vec![Directory {
+        name: vec![],
+        parent: 0,
+        children: vec![],
+        files: vec![],
+        offset: 0,
+    }];
+    let mut file_list = Vec::<File>::with_capacity(files.len());
+    for (path, data) in files {
+        let (file_name, dir_names) = match path.split_last() {
+            None => continue,
+            Some(split) => split,
+        };
+        let mut dir = 0;
+        for dir_name in dir_names {
+            let name = name_to_utf16(dir_name);
+            dir = match dirs[dir].children.iter().find(|&&c| dirs[c].name == name) {
+                Some(child) => *child,
+                None => {
+                    dirs.push(Directory {
+                        name,
+                        parent: dir,
+                        children: vec![],
+                        files: vec![],
+                        offset: 0,
+                    });
+                    let child = dirs.len() - 1;
+                    dirs[dir].children.push(child);
+                    child
+                }
+            };
+        }
+        dirs[dir].files.push(file_list.len());
+        file_list.push(File {
+            name: name_to_utf16(file_name),
+            parent: dir,
+            data,
+            offset: 0,
+            data_offset: 0,
+        });
+    }
+
+    // Lay out the metadata tables in the order the directories were created
+    let mut dir_table_len = 0;
+    for dir in dirs.iter_mut() {
+        dir.offset = dir_table_len as u32;
+        dir_table_len += DirectoryMetadata::BYTE_LEN + align(dir.name.len() * 2, 4);
+    }
+    let mut file_table_len = 0;
+    let mut data_len = 0;
+    for dir in &dirs {
+        for &f in &dir.files {
+            let file = &mut file_list[f];
+            file.offset = file_table_len as u32;
+            file_table_len += FileMetadata::BYTE_LEN + align(file.name.len() * 2, 4);
+            file.data_offset = data_len as u64;
+            data_len = align(data_len + file.data.len(), 0x10);
+        }
+    }
+
+    let mut dir_hash_table = vec![INVALID_FIELD; hash_table_len(dirs.len())];
+    let mut dir_same_hash_next = vec![INVALID_FIELD; dirs.len()];
+    for (i, dir) in dirs.iter().enumerate() {
+        let bucket = path_hash(dirs[dir.parent].offset, &dir.name) as usize % dir_hash_table.len();
+        dir_same_hash_next[i] = dir_hash_table[bucket];
+        dir_hash_table[bucket] = dir.offset;
+    }
+    let mut file_hash_table = vec![INVALID_FIELD; hash_table_len(file_list.len())];
+    let mut file_same_hash_next = vec![INVALID_FIELD; file_list.len()];
+    for (i, file) in file_list.iter().enumerate() {
+        let bucket =
+            path_hash(dirs[file.parent].offset, &file.name) as usize % file_hash_table.len();
+        file_same_hash_next[i] = file_hash_table[bucket];
+        file_hash_table[bucket] = file.offset;
+    }
+
+    let dir_hash_table_offset = Header::BYTE_LEN;
+    let dir_table_offset = dir_hash_table_offset + dir_hash_table.len() * 4;
+    let file_hash_table_offset = dir_table_offset + dir_table_len;
+    let file_table_offset = file_hash_table_offset + file_hash_table.len() * 4;
+    let data_offset = align(file_table_offset + file_table_len, 0x10);
+    let header = Header {
+        header_length: Header::BYTE_LEN as u32,
+        dir_hash_table_offset: dir_hash_table_offset as u32,
+        dir_hash_table_length: (dir_hash_table.len() * 4) as u32,
+        dir_table_offset: dir_table_offset as u32,
+        dir_table_length: dir_table_len as u32,
+        file_hash_table_offset: file_hash_table_offset as u32,
+        file_hash_table_length: (file_hash_table.len() * 4) as u32,
+        file_table_offset: file_table_offset as u32,
+        file_table_length: file_table_len as u32,
+        data_offset: data_offset as u32,
+    };
+
+    let mut result = vec![0; data_offset + data_len];
+    header.write_bytes(&mut result[0..Header::BYTE_LEN]);
+    let write_table = |result: &mut [u8], offset: usize, table: &[u32]| {
+        for (i, v) in table.iter().enumerate() {
+            result[offset + i * 4..offset + i * 4 + 4].copy_from_slice(&v.to_le_bytes());
+        }
+    };
+    write_table(&mut result, dir_hash_table_offset, &dir_hash_table);
+    write_table(&mut result, file_hash_table_offset, &file_hash_table);
+
+    let first_or_invalid = |offset: Option<u32>| offset.unwrap_or(INVALID_FIELD);
+    for (i, dir) in dirs.iter().enumerate() {
+        let siblings = &dirs[dir.parent].children;
+        let next_dir_offset = siblings
+            .iter()
+            .position(|&s| s == i)
+            .and_then(|p| siblings.get(p + 1))
+            .map(|&s| dirs[s].offset);
+        let metadata = DirectoryMetadata {
+            parent_dir_offset: dirs[dir.parent].offset,
+            next_dir_offset: first_or_invalid(next_dir_offset),
+            first_child_dir_offset: first_or_invalid(dir.children.first().map(|&c| dirs[c].offset)),
+            first_file_offset: first_or_invalid(dir.files.first().map(|&f| file_list[f].offset)),
+            same_hash_next_dir_offet: dir_same_hash_next[i],
+            name_length: (dir.name.len() * 2) as u32,
+        };
+        let begin = dir_table_offset + dir.offset as usize;
+        metadata.write_bytes(&mut result[begin..begin + DirectoryMetadata::BYTE_LEN]);
+        let name = utf16_to_bytes(&dir.name);
+        let name_begin = begin + DirectoryMetadata::BYTE_LEN;
+        result[name_begin..name_begin + name.len()].copy_from_slice(&name);
+    }
+
+    for (i, file) in file_list.iter().enumerate() {
+        let siblings = &dirs[file.parent].files;
+        let next_file_offset = siblings
+            .iter()
+            .position(|&s| s == i)
+            .and_then(|p| siblings.get(p + 1))
+            .map(|&s| file_list[s].offset);
+        let metadata = FileMetadata {
+            parent_dir_offset: dirs[file.parent].offset,
+            next_file_offset: first_or_invalid(next_file_offset),
+            data_offset: file.data_offset,
+            data_length: file.data.len() as u64,
+            same_hash_next_file_offset: file_same_hash_next[i],
+            name_length: (file.name.len() * 2) as u32,
+        };
+        let begin = file_table_offset + file.offset as usize;
+        metadata.write_bytes(&mut result[begin..begin + FileMetadata::BYTE_LEN]);
+        let name = utf16_to_bytes(&file.name);
+        let name_begin = begin + FileMetadata::BYTE_LEN;
+        result[name_begin..name_begin + name.len()].copy_from_slice(&name);
+
+        let data_begin = data_offset + file.data_offset as usize;
+        result[data_begin..data_begin + file.data.len()].copy_from_slice(file.data);
+    }
+
+    result
+}
+
+// Rebuilds the image with the content of one existing file replaced
+pub fn replace_romfs_file(romfs: &[u8], path: &[String], data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut files = RomFs::new(romfs)?.files()?;
+    match files
+        .iter_mut()
+        .find(|(file_path, _)| file_path[..] == path[..])
+    {
+        None => return Err(Error::RomfsPathNotFound(path.join("/"))),
+        Some(file) => file.1 = data,
+    }
+    Ok(build_romfs(&files))
 }
 
 #[test]
@@ -88,3 +542,91 @@ fn struct_size_test() {
     assert_eq!(DirectoryMetadata::BYTE_LEN, 0x18);
     assert_eq!(FileMetadata::BYTE_LEN, 0x20);
 }
+
+#[test]
+fn build_test() {
+    let path = |p: &str| -> Vec<String> { p.split('/').map(|s| s.to_string()).collect() };
+    let files: Vec<RomfsEntry> = vec![
+        (path("CFL_Res.dat"), b"asset"),
+        (path("a/b/c.bin"), b"abc"),
+        (path("a/d.bin"), b""),
+        (path("a/b/e.bin"), b"e"),
+    ];
+    let romfs = build_romfs(&files);
+    for (file_path, data) in &files {
+        assert_eq!(get_romfs_file(&romfs, file_path).unwrap(), *data);
+    }
+    assert!(get_romfs_file(&romfs, &path("a/c.bin")).is_err());
+
+    let listed = RomFs::new(&romfs).unwrap().files().unwrap();
+    assert_eq!(listed.len(), files.len());
+    for file in &files {
+        assert!(listed.contains(file));
+    }
+
+    let romfs_view = RomFs::new(&romfs).unwrap();
+    assert_eq!(
+        romfs_view.read_dir(&path("a")).unwrap(),
+        vec![
+            DirectoryEntry {
+                name: "b".to_string(),
+                kind: DirectoryEntryKind::Directory
+            },
+            DirectoryEntry {
+                name: "d.bin".to_string(),
+                kind: DirectoryEntryKind::File { size: 0 }
+            }
+        ]
+    );
+    assert!(matches!(
+        romfs_view.read_dir(&path("a/x")),
+        Err(Error::RomfsPathNotFound(p)) if p == "a/x"
+    ));
+
+    let replaced = replace_romfs_file(&romfs, &path("a/b/c.bin"), b"new data").unwrap();
+    assert_eq!(
+        get_romfs_file(&replaced, &path("a/b/c.bin")).unwrap(),
+        b"new data"
+    );
+    assert_eq!(
+        get_romfs_file(&replaced, &path("CFL_Res.dat")).unwrap(),
+        b"asset"
+    );
+}
+
+#[test]
+fn corrupt_test() {
+    let path = |p: &str| -> Vec<String> { p.split('/').map(|s| s.to_string()).collect() };
+    let files: Vec<RomfsEntry> = vec![(path("a/b.bin"), b"bb"), (path("c.bin"), b"c")];
+    let romfs = build_romfs(&files);
+
+    // Truncated images and every single-byte corruption must fail cleanly
+    for len in 0..romfs.len() {
+        let _ = RomFs::new(&romfs[..len]).and_then(|r| r.files());
+    }
+    for i in 0..romfs.len() {
+        for value in &[0x00, 0x01, 0x80, 0xFF] {
+            let mut corrupted = romfs.clone();
+            corrupted[i] = *value;
+            if let Ok(r) = RomFs::new(&corrupted) {
+                let _ = r.files();
+                let _ = r.read_dir(&path("a"));
+                let _ = r.get_file(&path("a/b.bin"));
+            }
+        }
+    }
+
+    // The first file of the root directory linking to itself as the next file
+    let header = Header::read_bytes(&romfs[0..Header::BYTE_LEN]);
+    let root_begin = header.dir_table_offset as usize;
+    let root =
+        DirectoryMetadata::read_bytes(&romfs[root_begin..root_begin + DirectoryMetadata::BYTE_LEN]);
+    let next_file_field = (header.file_table_offset + root.first_file_offset) as usize + 4;
+    let mut cyclic = romfs.clone();
+    cyclic[next_file_field..next_file_field + 4]
+        .copy_from_slice(&root.first_file_offset.to_le_bytes());
+    assert!(matches!(
+        RomFs::new(&cyclic).unwrap().files(),
+        Err(Error::RomfsCorrupt)
+    ));
+}
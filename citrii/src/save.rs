@@ -0,0 +1,138 @@
+// Saves files without leaving them truncated when interrupted, and keeps timestamped backups of
+// the previous content next to them, such as CFL_DB.dat.20200101-120000-000.bak.
+
+use crate::error::*;
+use chrono::NaiveDateTime;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Number of backups kept by default
+pub const BACKUP_COUNT: usize = 5;
+
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+
+fn file_name(path: &Path) -> Result<String, Error> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| {
+            Error::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not a file path", path.display()),
+            ))
+        })
+}
+
+// Writes `data` to a temporary file next to `path`, then renames it over `path`
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let temp = path.with_file_name(format!("{}.tmp", file_name(path)?));
+    let result = std::fs::File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    Ok(result?)
+}
+
+// Backups of `path`, oldest first
+pub fn list_backups(path: &Path) -> Vec<PathBuf> {
+    let name = match file_name(path) {
+        Ok(name) => name,
+        Err(_) => return vec![],
+    };
+    let directory = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let prefix = format!("{}.", name);
+    let mut result: Vec<PathBuf> = match std::fs::read_dir(&directory) {
+        Err(_) => return vec![],
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                let entry_name = e.file_name().to_string_lossy().to_string();
+                entry_name
+                    .strip_prefix(&prefix)
+                    .and_then(|rest| rest.strip_suffix(".bak"))
+                    .map_or(false, |time| {
+                        NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT).is_ok()
+                    })
+            })
+            .map(|e| path.with_file_name(e.file_name()))
+            .collect(),
+    };
+    result.sort();
+    result
+}
+
+fn save_with_backup_at(
+    path: &Path,
+    data: &[u8],
+    keep: usize,
+    time: NaiveDateTime,
+) -> Result<Option<PathBuf>, Error> {
+    let backup = match std::fs::read(path) {
+        Ok(previous) if previous != data && keep != 0 => {
+            let backup = path.with_file_name(format!(
+                "{}.{}.bak",
+                file_name(path)?,
+                time.format(BACKUP_TIME_FORMAT)
+            ));
+            write_atomic(&backup, &previous)?;
+            Some(backup)
+        }
+        Ok(_) => None,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    write_atomic(path, data)?;
+
+    // The database is saved at this point, so an old backup that can't be removed is left behind
+    let backups = list_backups(path);
+    for old in backups.iter().take(backups.len().saturating_sub(keep)) {
+        let _ = std::fs::remove_file(old);
+    }
+    Ok(backup)
+}
+
+// Saves `data` to `path`, keeping the previous content as a backup if it differs. Only the newest
+// `keep` backups are kept. Returns the new backup, if any.
+pub fn save_with_backup(path: &Path, data: &[u8], keep: usize) -> Result<Option<PathBuf>, Error> {
+    save_with_backup_at(path, data, keep, chrono::Local::now().naive_local())
+}
+
+#[test]
+fn save_test() {
+    let root = std::env::temp_dir().join(format!("citrii-save-test-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let path = root.join("CFL_DB.dat");
+    let time = |second| chrono::NaiveDate::from_ymd(2020, 1, 1).and_hms(12, 0, second);
+
+    assert_eq!(save_with_backup_at(&path, b"0", 2, time(0)).unwrap(), None);
+    assert_eq!(std::fs::read(&path).unwrap(), b"0");
+    assert_eq!(save_with_backup_at(&path, b"0", 2, time(1)).unwrap(), None);
+    for (i, data) in [b"1", b"2", b"3"].iter().enumerate() {
+        let backup = save_with_backup_at(&path, *data, 2, time(2 + i as u32)).unwrap();
+        assert!(backup.is_some());
+    }
+    std::fs::write(root.join("CFL_DB.dat.other.bak"), b"").unwrap();
+
+    let backups = list_backups(&path);
+    assert_eq!(
+        backups,
+        vec![
+            root.join("CFL_DB.dat.20200101-120003-000.bak"),
+            root.join("CFL_DB.dat.20200101-120004-000.bak")
+        ]
+    );
+    assert_eq!(std::fs::read(&backups[0]).unwrap(), b"1");
+    assert_eq!(std::fs::read(&backups[1]).unwrap(), b"2");
+    assert_eq!(std::fs::read(&path).unwrap(), b"3");
+    assert!(!root.join("CFL_DB.dat.tmp").exists());
+
+    assert!(write_atomic(&root.join("missing/CFL_DB.dat"), b"").is_err());
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,238 @@
+// Description of each field of PROFILE_FIELDS: its name, its valid range, how the editor steps
+// through it, and the editor pages and controls that change it. The editor, validation, the text
+// formats, the conversions and the random generator all take their ranges from here.
+
+use crate::color::*;
+use crate::database::*;
+
+// Pages of the editor, in the order of their buttons
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Page {
+    Face,
+    Makeup,
+    Wrinkle,
+    Hair,
+    Eyebrow,
+    Eye,
+    Nose,
+    Lip,
+    Glass,
+    Mustache,
+    Beard,
+    Mole,
+}
+
+impl Page {
+    pub const ALL: [Page; 12] = [
+        Page::Face,
+        Page::Makeup,
+        Page::Wrinkle,
+        Page::Hair,
+        Page::Eyebrow,
+        Page::Eye,
+        Page::Nose,
+        Page::Lip,
+        Page::Glass,
+        Page::Mustache,
+        Page::Beard,
+        Page::Mole,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Page::Face => "Face",
+            Page::Makeup => "Makeup",
+            Page::Wrinkle => "Wrinkle",
+            Page::Hair => "Hairstyle",
+            Page::Eyebrow => "Eyebrows",
+            Page::Eye => "Eyes",
+            Page::Nose => "Nose",
+            Page::Lip => "Mouth",
+            Page::Glass => "Glasses",
+            Page::Mustache => "Mustache",
+            Page::Beard => "Goatee",
+            Page::Mole => "Beauty mark",
+        }
+    }
+}
+
+// Controls of an editor page
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    Style,
+    Color,
+    Flip,
+    Scale,
+    YScale,
+    Rotation,
+    // Distance between the left and the right part
+    Spacing,
+    X,
+    Y,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    // Going past one end continues from the other end
+    Wrap,
+    // Going past either end keeps the value
+    Clamp,
+}
+
+pub struct Param {
+    pub field: &'static str,
+    pub name: &'static str,
+    pub min: u32,
+    pub max: u32,
+    pub step: Step,
+    pub controls: &'static [(Page, Control)],
+    // Colors of the values, for color fields
+    pub colors: Option<&'static [(u8, u8, u8)]>,
+}
+
+impl Param {
+    pub fn get(&self, profile: &Profile) -> u32 {
+        profile.get_field(self.field).unwrap()
+    }
+
+    // Sets the value clamped to the range
+    pub fn set(&self, profile: &mut Profile, value: u32) {
+        profile.set_field(self.field, value.clamp(self.min, self.max));
+    }
+
+    pub fn contains(&self, value: u32) -> bool {
+        value >= self.min && value <= self.max
+    }
+
+    // The value after `value` stepped up or down by one
+    pub fn step(&self, value: u32, up: bool) -> u32 {
+        match (self.step, up) {
+            (Step::Wrap, true) if value >= self.max => self.min,
+            (Step::Wrap, false) if value <= self.min => self.max,
+            (Step::Clamp, true) if value >= self.max => value,
+            (Step::Clamp, false) if value <= self.min => value,
+            (_, true) => value + 1,
+            (_, false) => value - 1,
+        }
+    }
+}
+
+macro_rules! params {
+    ($(
+        $field:literal, $name:literal, $min:literal..=$max:literal, $step:ident,
+        [$(($page:ident, $control:ident)),*] $(, $colors:ident)?;
+    )*) => {
+        pub const PARAMS: &[Param] = &[$(
+            Param {
+                field: $field,
+                name: $name,
+                min: $min,
+                max: $max,
+                step: Step::$step,
+                controls: &[$((Page::$page, Control::$control)),*],
+                colors: params!(@colors $($colors)?),
+            },
+        )*];
+    };
+    (@colors) => { None };
+    (@colors $colors:ident) => { Some(&$colors) };
+}
+
+params!(
+    "allow_copying", "Allow copying", 0..=1, Clamp, [];
+    "private_name", "Private name", 0..=1, Clamp, [];
+    "region_lock", "Region lock", 0..=3, Clamp, [];
+    "char_set", "Character set", 0..=3, Clamp, [];
+    "sex", "Sex", 0..=1, Clamp, [];
+    "birth_month", "Birth month", 0..=12, Clamp, [];
+    "birth_day", "Birth day", 0..=31, Clamp, [];
+    "favorite_color", "Favorite color", 0..=11, Wrap, [], WEARING_COLOR_TABLE;
+    "favorite", "Favorite", 0..=1, Clamp, [];
+    "height", "Height", 0..=127, Clamp, [];
+    "width", "Width", 0..=127, Clamp, [];
+    "disable_sharing", "Disable sharing", 0..=1, Clamp, [];
+    "face.style", "Face shape", 0..=11, Wrap, [(Face, Style)];
+    "face.color", "Skin color", 0..=5, Wrap, [(Face, Color)], SKIN_COLOR_TABLE;
+    "face.wrinkle", "Wrinkles", 0..=11, Wrap, [(Wrinkle, Style)];
+    "face.makeup", "Makeup", 0..=11, Wrap, [(Makeup, Style)];
+    "hair.style", "Hairstyle", 0..=131, Wrap, [(Hair, Style)];
+    "hair.color", "Hair color", 0..=7, Wrap, [(Hair, Color)], HAIR_COLOR_TABLE;
+    "hair.flip", "Flipped hair", 0..=1, Wrap, [(Hair, Flip)];
+    "eye.style", "Eye style", 0..=61, Wrap, [(Eye, Style)];
+    "eye.color", "Eye color", 0..=5, Wrap, [(Eye, Color)], EYE_COLOR_TABLE;
+    "eye.scale", "Eye size", 0..=7, Clamp, [(Eye, Scale)];
+    "eye.y_scale", "Eye stretch", 0..=6, Clamp, [(Eye, YScale)];
+    "eye.rotation", "Eye rotation", 0..=7, Clamp, [(Eye, Rotation)];
+    "eye.x", "Eye spacing", 0..=12, Clamp, [(Eye, Spacing)];
+    "eye.y", "Eye position", 0..=18, Clamp, [(Eye, Y)];
+    "eyebrow.style", "Eyebrow style", 0..=23, Wrap, [(Eyebrow, Style)];
+    "eyebrow.color", "Eyebrow color", 0..=7, Wrap, [(Eyebrow, Color)], HAIR_COLOR_TABLE;
+    "eyebrow.scale", "Eyebrow size", 0..=8, Clamp, [(Eyebrow, Scale)];
+    "eyebrow.y_scale", "Eyebrow stretch", 0..=6, Clamp, [(Eyebrow, YScale)];
+    "eyebrow.rotation", "Eyebrow rotation", 0..=11, Clamp, [(Eyebrow, Rotation)];
+    "eyebrow.x", "Eyebrow spacing", 0..=12, Clamp, [(Eyebrow, Spacing)];
+    "eyebrow.y", "Eyebrow position", 3..=18, Clamp, [(Eyebrow, Y)];
+    "nose.style", "Nose style", 0..=17, Wrap, [(Nose, Style)];
+    "nose.scale", "Nose size", 0..=8, Clamp, [(Nose, Scale)];
+    "nose.y", "Nose position", 0..=18, Clamp, [(Nose, Y)];
+    "lip.style", "Mouth style", 0..=36, Wrap, [(Lip, Style)];
+    "lip.color", "Mouth color", 0..=4, Wrap, [(Lip, Color)], LIP_COLOR_TABLE;
+    "lip.scale", "Mouth size", 0..=8, Clamp, [(Lip, Scale)];
+    "lip.y_scale", "Mouth stretch", 0..=6, Clamp, [(Lip, YScale)];
+    "lip.y", "Mouth position", 0..=18, Clamp, [(Lip, Y)];
+    "mustache.style", "Mustache style", 0..=5, Wrap, [(Mustache, Style)];
+    "mustache.scale", "Mustache size", 0..=8, Clamp, [(Mustache, Scale)];
+    "mustache.y", "Mustache position", 0..=16, Clamp, [(Mustache, Y)];
+    "beard.style", "Goatee style", 0..=5, Wrap, [(Beard, Style)];
+    "beard.color", "Facial hair color", 0..=7, Wrap, [(Mustache, Color), (Beard, Color)],
+        HAIR_COLOR_TABLE;
+    "glass.style", "Glasses style", 0..=8, Wrap, [(Glass, Style)];
+    "glass.color", "Glasses color", 0..=5, Wrap, [(Glass, Color)], GLASS_COLOR_TABLE;
+    "glass.scale", "Glasses size", 0..=7, Clamp, [(Glass, Scale)];
+    "glass.y", "Glasses position", 0..=20, Clamp, [(Glass, Y)];
+    "mole.style", "Beauty mark", 0..=1, Wrap, [(Mole, Style)];
+    "mole.scale", "Beauty mark size", 0..=8, Clamp, [(Mole, Scale)];
+    "mole.x", "Beauty mark horizontal position", 0..=16, Clamp, [(Mole, X)];
+    "mole.y", "Beauty mark vertical position", 0..=30, Clamp, [(Mole, Y)];
+);
+
+pub fn param(field: &str) -> Option<&'static Param> {
+    PARAMS.iter().find(|p| p.field == field)
+}
+
+// The field changed by a control of a page, if the page has the control
+pub fn page_param(page: Page, control: Control) -> Option<&'static Param> {
+    PARAMS
+        .iter()
+        .find(|p| p.controls.contains(&(page, control)))
+}
+
+#[test]
+fn schema_test() {
+    assert_eq!(PARAMS.len(), PROFILE_FIELDS.len());
+    for (param, &field) in PARAMS.iter().zip(PROFILE_FIELDS) {
+        assert_eq!(param.field, field);
+        assert!(param.min <= param.max);
+        assert!(u64::from(param.max) < 1u64 << Profile::field_bits(field).unwrap());
+        if let Some(colors) = param.colors {
+            assert_eq!(colors.len() as u32, param.max + 1);
+        }
+    }
+
+    let time = chrono::NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0);
+    let profile = Profile::new([1; 6], [2; 8], time, 0);
+    assert!(PARAMS.iter().all(|p| p.contains(p.get(&profile))));
+
+    let hair = page_param(Page::Hair, Control::Style).unwrap();
+    assert_eq!(hair.step(131, true), 0);
+    assert_eq!(hair.step(0, false), 131);
+    let eyebrow_y = page_param(Page::Eyebrow, Control::Y).unwrap();
+    assert_eq!(eyebrow_y.step(3, false), 3);
+    assert_eq!(eyebrow_y.step(18, true), 18);
+    assert_eq!(eyebrow_y.step(10, true), 11);
+    assert_eq!(
+        page_param(Page::Beard, Control::Color).unwrap().field,
+        "beard.color"
+    );
+    assert!(page_param(Page::Nose, Control::Color).is_none());
+}
@@ -0,0 +1,418 @@
+// A CPU implementation of the head renderer for machines without a GPU or a display. It draws
+// the same models as HeadRenderer and does the same layer compositing and lighting as
+// head.v.glsl and head.f.glsl.
+
+use crate::asset::WrapMode;
+use crate::asset::*;
+use crate::error::*;
+use crate::head_renderer::*;
+use cgmath::prelude::*;
+
+#[derive(Debug)]
+struct Vertex {
+    coord: cgmath::Vector3<f32>,
+    normal: cgmath::Vector3<f32>,
+    texcoord: cgmath::Vector2<f32>,
+}
+
+#[derive(Debug)]
+pub struct SoftwareModel {
+    vertices: Vec<Vertex>,
+    triangles: Vec<[usize; 3]>,
+}
+
+impl SoftwareModel {
+    pub fn new(raw: &RawModel) -> SoftwareModel {
+        let vertices = raw
+            .vertices()
+            .into_iter()
+            .map(|v| {
+                let normal = v.normal.unwrap_or((0.0, 0.0, 0.0));
+                let texcoord = v.texcoord.unwrap_or((0.0, 0.0));
+                Vertex {
+                    coord: cgmath::Vector3::new(v.coord.0, v.coord.1, v.coord.2),
+                    normal: cgmath::Vector3::new(normal.0, normal.1, normal.2),
+                    texcoord: cgmath::Vector2::new(texcoord.0, texcoord.1),
+                }
+            })
+            .collect();
+        SoftwareModel {
+            vertices,
+            triangles: raw.triangles(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SoftwareTexture {
+    width: usize,
+    height: usize,
+    wrap_u: WrapMode,
+    wrap_v: WrapMode,
+    pixels: Vec<u8>,
+}
+
+impl SoftwareTexture {
+    pub fn new(raw: &RawTexture) -> SoftwareTexture {
+        SoftwareTexture {
+            width: raw.width as usize,
+            height: raw.height as usize,
+            wrap_u: raw.wrap_u,
+            wrap_v: raw.wrap_v,
+            pixels: raw.decode(),
+        }
+    }
+
+    // Bilinear filtering like GL_LINEAR. The first row is at t = 0.
+    fn sample(&self, coord: cgmath::Vector2<f32>) -> cgmath::Vector4<f32> {
+        fn wrap(i: i64, len: usize, mode: WrapMode) -> usize {
+            let len = len as i64;
+            (match mode {
+                WrapMode::Edge => i.clamp(0, len - 1),
+                WrapMode::Repeat => i.rem_euclid(len),
+                WrapMode::Mirror => {
+                    let m = i.rem_euclid(len * 2);
+                    if m >= len {
+                        len * 2 - 1 - m
+                    } else {
+                        m
+                    }
+                }
+            }) as usize
+        }
+
+        let x = coord.x * self.width as f32 - 0.5;
+        let y = coord.y * self.height as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let texel = |dx: i64, dy: i64| {
+            let tx = wrap(x0 as i64 + dx, self.width, self.wrap_u);
+            let ty = wrap(y0 as i64 + dy, self.height, self.wrap_v);
+            let p = &self.pixels[(ty * self.width + tx) * 4..][..4];
+            cgmath::Vector4::new(p[0] as f32, p[1] as f32, p[2] as f32, p[3] as f32) / 255.0
+        };
+        (texel(0, 0) * (1.0 - fx) + texel(1, 0) * fx) * (1.0 - fy)
+            + (texel(0, 1) * (1.0 - fx) + texel(1, 1) * fx) * fy
+    }
+}
+
+pub type SoftwareAsset = AssetData<SoftwareModel, SoftwareTexture>;
+
+impl SoftwareAsset {
+    pub fn from_decoded(decoded: &DecodedAsset) -> SoftwareAsset {
+        decoded.map(SoftwareModel::new, SoftwareTexture::new)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<SoftwareAsset, Error> {
+        Ok(SoftwareAsset::from_decoded(&DecodedAsset::from_bytes(
+            bytes,
+        )?))
+    }
+}
+
+// Vertex shader output
+struct ShadedVertex {
+    clip: cgmath::Vector4<f32>,
+    coord_world: cgmath::Vector3<f32>,
+    normal_world: cgmath::Vector3<f32>,
+    texcoord: cgmath::Vector2<f32>,
+}
+
+struct Target {
+    width: usize,
+    height: usize,
+    color: Vec<cgmath::Vector4<f32>>,
+    depth: Vec<f32>,
+}
+
+pub struct SoftwareHeadRenderer {
+    asset: SoftwareAsset,
+}
+
+impl SoftwareHeadRenderer {
+    pub fn with_asset(asset: SoftwareAsset) -> SoftwareHeadRenderer {
+        SoftwareHeadRenderer { asset }
+    }
+
+    pub fn render_head_offscreen(
+        &self,
+        info: &HeadRenderInfo,
+        params: &OffscreenParams,
+    ) -> image::RgbaImage {
+        let width = params.width as usize;
+        let height = params.height as usize;
+        let background = match params.background {
+            Some((r, g, b)) => cgmath::Vector4::new(r, g, b, 1.0),
+            None => cgmath::Vector4::zero(),
+        };
+        let mut target = Target {
+            width,
+            height,
+            color: vec![background; width * height],
+            depth: vec![1.0; width * height],
+        };
+
+        let environment = Environment::new(params.width as f32 / params.height as f32);
+        for call in head_draw_calls(&self.asset.face_configs, info, &params.object_tran()) {
+            if let Some(model) = self.asset.model(call.model.0, call.model.1) {
+                self.draw(&mut target, &environment, &call, model);
+            }
+        }
+
+        let mut image = image::RgbaImage::from_fn(params.width, params.height, |x, y| {
+            let c = target.color[y as usize * width + x as usize];
+            let convert = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+            image::Rgba([convert(c.x), convert(c.y), convert(c.z), convert(c.w)])
+        });
+        if params.background.is_none() {
+            unpremultiply(&mut image);
+        }
+        image
+    }
+
+    fn draw(
+        &self,
+        target: &mut Target,
+        environment: &Environment,
+        call: &DrawCall,
+        model: &SoftwareModel,
+    ) {
+        let normal_tran = match call.object_tran.invert() {
+            Some(inv) => inv.transpose(),
+            None => return,
+        };
+        let shaded: Vec<ShadedVertex> = model
+            .vertices
+            .iter()
+            .map(|v| {
+                let world = call.object_tran * v.coord.extend(1.0);
+                ShadedVertex {
+                    clip: environment.camera_tran * world,
+                    coord_world: world.truncate(),
+                    normal_world: (normal_tran * v.normal.extend(0.0)).truncate(),
+                    texcoord: v.texcoord,
+                }
+            })
+            .collect();
+
+        for triangle in &model.triangles {
+            let v = [
+                &shaded[triangle[0]],
+                &shaded[triangle[1]],
+                &shaded[triangle[2]],
+            ];
+            // The head is always far from the near plane, so triangles crossing it are dropped
+            // instead of clipped
+            if v.iter().any(|v| v.clip.w < 1e-6) {
+                continue;
+            }
+            let ndc: Vec<cgmath::Vector3<f32>> =
+                v.iter().map(|v| v.clip.truncate() / v.clip.w).collect();
+
+            let area = (ndc[1].x - ndc[0].x) * (ndc[2].y - ndc[0].y)
+                - (ndc[2].x - ndc[0].x) * (ndc[1].y - ndc[0].y);
+            if area == 0.0 || (call.cull_face && (area > 0.0) == call.flip) {
+                continue;
+            }
+
+            let screen: Vec<(f32, f32, f32)> = ndc
+                .iter()
+                .map(|p| {
+                    (
+                        (p.x + 1.0) * 0.5 * target.width as f32,
+                        (1.0 - p.y) * 0.5 * target.height as f32,
+                        (p.z + 1.0) * 0.5,
+                    )
+                })
+                .collect();
+            let min_x = screen.iter().map(|p| p.0).fold(f32::INFINITY, f32::min);
+            let max_x = screen.iter().map(|p| p.0).fold(f32::NEG_INFINITY, f32::max);
+            let min_y = screen.iter().map(|p| p.1).fold(f32::INFINITY, f32::min);
+            let max_y = screen.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max);
+            let x_begin = (min_x - 0.5).ceil().max(0.0) as usize;
+            let x_end = ((max_x - 0.5).floor() + 1.0).clamp(0.0, target.width as f32) as usize;
+            let y_begin = (min_y - 0.5).ceil().max(0.0) as usize;
+            let y_end = ((max_y - 0.5).floor() + 1.0).clamp(0.0, target.height as f32) as usize;
+
+            let edge = |a: (f32, f32, f32), b: (f32, f32, f32), x: f32, y: f32| {
+                (b.0 - a.0) * (y - a.1) - (b.1 - a.1) * (x - a.0)
+            };
+            let screen_area = edge(screen[0], screen[1], screen[2].0, screen[2].1);
+
+            for y in y_begin..y_end {
+                for x in x_begin..x_end {
+                    let px = x as f32 + 0.5;
+                    let py = y as f32 + 0.5;
+                    let b = [
+                        edge(screen[1], screen[2], px, py) / screen_area,
+                        edge(screen[2], screen[0], px, py) / screen_area,
+                        edge(screen[0], screen[1], px, py) / screen_area,
+                    ];
+                    if b.iter().any(|&b| b < 0.0) {
+                        continue;
+                    }
+                    let depth = b[0] * screen[0].2 + b[1] * screen[1].2 + b[2] * screen[2].2;
+                    let index = y * target.width + x;
+                    if !(0.0..=1.0).contains(&depth) || depth >= target.depth[index] {
+                        continue;
+                    }
+
+                    // Perspective-correct interpolation
+                    let pw = [b[0] / v[0].clip.w, b[1] / v[1].clip.w, b[2] / v[2].clip.w];
+                    let sum = pw[0] + pw[1] + pw[2];
+                    let pw = [pw[0] / sum, pw[1] / sum, pw[2] / sum];
+                    let coord_world = v[0].coord_world * pw[0]
+                        + v[1].coord_world * pw[1]
+                        + v[2].coord_world * pw[2];
+                    let normal_world = v[0].normal_world * pw[0]
+                        + v[1].normal_world * pw[1]
+                        + v[2].normal_world * pw[2];
+                    let texcoord =
+                        v[0].texcoord * pw[0] + v[1].texcoord * pw[1] + v[2].texcoord * pw[2];
+
+                    let color =
+                        match self.shade(environment, call, coord_world, normal_world, texcoord) {
+                            Some(color) => color,
+                            None => continue,
+                        };
+
+                    // Blending with (SRC_ALPHA, ONE_MINUS_SRC_ALPHA) for color and
+                    // (ONE, ONE_MINUS_SRC_ALPHA) for alpha
+                    let dest = target.color[index];
+                    let a = color.w;
+                    target.color[index] = (color.truncate() * a + dest.truncate() * (1.0 - a))
+                        .extend(a + dest.w * (1.0 - a));
+                    target.depth[index] = depth;
+                }
+            }
+        }
+    }
+
+    // The fragment shader. Returns None if the fragment is discarded.
+    fn shade(
+        &self,
+        environment: &Environment,
+        call: &DrawCall,
+        coord_world: cgmath::Vector3<f32>,
+        normal_world: cgmath::Vector3<f32>,
+        texcoord: cgmath::Vector2<f32>,
+    ) -> Option<cgmath::Vector4<f32>> {
+        let mut mixed_color = call.base_color;
+        for config in call.layers.iter().flatten() {
+            let texture = match self.asset.texture(config.texture.0, config.texture.1) {
+                Some(texture) => texture,
+                None => continue,
+            };
+            let mut coord = texcoord;
+            if let Some(window) = &config.window {
+                if window.mirrored && coord.x > 0.5 {
+                    coord.x = 1.0 - coord.x;
+                }
+                coord = (window.tran * coord.extend(1.0)).truncate();
+                if coord.x < 0.0 || coord.y < 0.0 || coord.x > 1.0 || coord.y > 1.0 {
+                    continue;
+                }
+            }
+            let tex_origin = texture.sample(coord);
+            let mut tex_color = config.tran * tex_origin.truncate().extend(1.0);
+            tex_color.w = tex_color.w.min(tex_origin.w);
+            mixed_color = (mixed_color.truncate() * (1.0 - tex_color.w)
+                + tex_color.truncate() * tex_color.w)
+                .extend(mixed_color.w.max(tex_color.w));
+        }
+
+        if mixed_color.w < 0.01 {
+            return None;
+        }
+
+        let v = (environment.camera_pos.to_vec() - coord_world).normalize();
+        let l = (environment.light_source.to_vec() - coord_world).normalize();
+        let n = normal_world.normalize();
+        let h = (l + v).normalize();
+        let diffuse = n.dot(l).max(0.0) * 0.2 + 0.8;
+        let specular = 0.3 * n.dot(h).max(0.0).powi(5);
+        let rgb = mixed_color.truncate() * diffuse + cgmath::Vector3::new(1.0, 1.0, 1.0) * specular;
+        Some(cgmath::Vector4::new(
+            rgb.x.clamp(0.0, 1.0),
+            rgb.y.clamp(0.0, 1.0),
+            rgb.z.clamp(0.0, 1.0),
+            mixed_color.w,
+        ))
+    }
+}
+
+#[test]
+fn software_render_test() {
+    // A square facing the camera as the face model
+    let vertex_list: Vec<u8> = [(-40i16, -10i16), (40, -10), (40, 70), (-40, 70)]
+        .iter()
+        .flat_map(|&(x, y)| {
+            [x * 256, y * 256, 0]
+                .iter()
+                .flat_map(|v| v.to_le_bytes().to_vec())
+                .collect::<Vec<u8>>()
+        })
+        .collect();
+    let face = RawModel {
+        normal_mode: AttributeMode::Common,
+        texcoord_mode: AttributeMode::None,
+        vertex_list,
+        default_normal: (0, 0, 256),
+        default_texcoord: (0, 0),
+        index_list: vec![0, 1, 2, 0, 2, 3],
+        header: vec![],
+        trailer: vec![],
+    };
+    let asset = AssetData {
+        version: 0,
+        buffer_sizes: vec![],
+        beard_models: vec![],
+        accessory_models: vec![],
+        face_models: vec![Some(SoftwareModel::new(&face))],
+        scalp_models: vec![],
+        glass_models: vec![],
+        hair_models: vec![],
+        face_canvas_models: vec![],
+        nose_canvas_models: vec![],
+        nose_models: vec![],
+        accessory_textures: vec![],
+        eye_textures: vec![],
+        eyebrow_textures: vec![],
+        beard_textures: vec![],
+        wrinkle_textures: vec![],
+        makeup_textures: vec![],
+        glass_textures: vec![],
+        mole_textures: vec![],
+        lip_textures: vec![],
+        mustache_textures: vec![],
+        nose_textures: vec![],
+        face_configs: vec![Some(FaceConfig {
+            hair_pos: (0.0, 0.0, 0.0),
+            nose_pos: (0.0, 0.0, 0.0),
+            beard_pos: (0.0, 0.0, 0.0),
+        })],
+    };
+    let renderer = SoftwareHeadRenderer::with_asset(asset);
+    let mut info = crate::database::Profile::default().to_render_info();
+    info.face_color = (1.0, 0.0, 0.0);
+    let mut params = OffscreenParams {
+        width: 32,
+        height: 32,
+        yaw: 0.0,
+        pitch: 0.0,
+        background: None,
+    };
+    let image = renderer.render_head_offscreen(&info, &params);
+    let center = image.get_pixel(16, 16);
+    assert_eq!(center[3], 255);
+    assert!(center[0] > 200 && center[1] < 100 && center[2] < 100);
+    assert_eq!(image.get_pixel(0, 0)[3], 0);
+
+    // The back face is culled
+    params.yaw = 180.0;
+    params.background = Some((0.0, 0.0, 1.0));
+    let image = renderer.render_head_offscreen(&info, &params);
+    assert_eq!(*image.get_pixel(16, 16), image::Rgba([0, 0, 255, 255]));
+}
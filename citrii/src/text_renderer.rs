@@ -1,5 +1,5 @@
 use crate::rect_renderer;
-use crate::texture;
+use citrii::texture;
 
 pub struct TextRenderer {
     rect_renderer: std::rc::Rc<rect_renderer::RectRenderer>,
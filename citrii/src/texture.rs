@@ -1,14 +1,8 @@
+pub use crate::asset::WrapMode;
 use gl::types::*;
 
-#[derive(Debug)]
-pub enum WrapMode {
-    Edge,
-    Mirror,
-    Repeat,
-}
-
 impl WrapMode {
-    fn to_gl(&self) -> GLenum {
+    fn to_gl(self) -> GLenum {
         match self {
             WrapMode::Edge => gl::CLAMP_TO_EDGE,
             WrapMode::Mirror => gl::MIRRORED_REPEAT,
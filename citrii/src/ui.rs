@@ -8,10 +8,10 @@
 // (1, 1) is at top-right
 // aspect = window_width / window_height
 
-use crate::color;
 use crate::rect_renderer;
 use crate::text_renderer;
-use crate::texture;
+use citrii::color;
+use citrii::texture;
 
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -75,6 +75,10 @@ impl Label {
             text_renderer,
         }))
     }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text = String::from(text);
+    }
 }
 
 impl UIElement for Label {
@@ -614,6 +618,7 @@ pub struct GridLayout {
 }
 
 impl GridLayout {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         x_count: usize,
         y_count: usize,
@@ -656,6 +661,7 @@ impl GridLayout {
         self.visible
     }
 
+    #[allow(clippy::needless_range_loop)]
     fn get_grid_size(&self) -> (Vec<f32>, Vec<f32>) {
         let mut ws = vec![0.0; self.x_count];
         let mut hs = vec![0.0; self.y_count];
@@ -688,6 +694,7 @@ impl UIElement for GridLayout {
             h_base + hs.iter().fold(0.0, |acc, a| acc + a),
         )
     }
+    #[allow(clippy::needless_range_loop)]
     fn render(&self, (gl_x0, gl_y0): (f32, f32), (gl_x1, gl_y1): (f32, f32)) {
         if !self.visible {
             return;
@@ -723,6 +730,7 @@ impl UIElement for GridLayout {
         }
     }
 
+    #[allow(clippy::needless_range_loop)]
     fn on_mouse_event(&mut self, event: MouseEvent) -> Vec<UIEvent> {
         let mut ui_event = vec![];
         match event {
@@ -0,0 +1,357 @@
+use crate::database::*;
+use crate::error::*;
+use crate::schema::*;
+use byte_struct::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileList {
+    Owned,
+    Invited,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    BadCrc {
+        region: CrcRegion,
+        expected: u16,
+        found: u16,
+    },
+    MissingPersonalProfile,
+    NullProfileNotEmpty {
+        list: ProfileList,
+        index: usize,
+    },
+    InvalidSlot {
+        index: usize,
+    },
+    DuplicateSlot {
+        slot: usize,
+        index: usize,
+        other: usize,
+    },
+    FieldOutOfRange {
+        list: ProfileList,
+        index: usize,
+        field: &'static str,
+        value: u32,
+    },
+    InvitedOrderMismatch,
+    CfheBrokenLink {
+        node: usize,
+    },
+    CfheTailMismatch {
+        expected: usize,
+        found: usize,
+    },
+    CfheUnreachableNode {
+        node: usize,
+    },
+}
+
+impl Issue {
+    // Whether Database::repair is able to fix the issue. CRC mismatches are fixed simply by
+    // saving the database again.
+    pub fn is_repairable(&self) -> bool {
+        !matches!(self, Issue::MissingPersonalProfile)
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let list_name = |list: &ProfileList| match list {
+            ProfileList::Owned => "owned",
+            ProfileList::Invited => "invited",
+        };
+        match self {
+            Issue::BadCrc {
+                region,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}",
+                Error::BadCrc {
+                    region: *region,
+                    expected: *expected,
+                    found: *found
+                }
+            ),
+            Issue::MissingPersonalProfile => write!(f, "the personal profile is empty"),
+            Issue::NullProfileNotEmpty { list, index } => write!(
+                f,
+                "{} profile #{} has no ID but contains data",
+                list_name(list),
+                index
+            ),
+            Issue::InvalidSlot { index } => {
+                write!(f, "owned profile #{} has an invalid slot", index)
+            }
+            Issue::DuplicateSlot { slot, index, other } => write!(
+                f,
+                "owned profiles #{} and #{} are both in slot {}",
+                other, index, slot
+            ),
+            Issue::FieldOutOfRange {
+                list,
+                index,
+                field,
+                value,
+            } => write!(
+                f,
+                "{} profile #{} has {} out of range: {}",
+                list_name(list),
+                index,
+                field,
+                value
+            ),
+            Issue::InvitedOrderMismatch => {
+                write!(f, "the invited order doesn't match the invited profiles")
+            }
+            Issue::CfheBrokenLink { node } => write!(f, "CFHE node {} has a broken link", node),
+            Issue::CfheTailMismatch { expected, found } => write!(
+                f,
+                "CFHE tail is {} but the list ends at {}",
+                found, expected
+            ),
+            Issue::CfheUnreachableNode { node } => {
+                write!(f, "CFHE node {} is not reachable from the head", node)
+            }
+        }
+    }
+}
+
+fn check_profile(profile: &Profile, list: ProfileList, index: usize, issues: &mut Vec<Issue>) {
+    for param in PARAMS {
+        let value = param.get(profile);
+        if !param.contains(value) {
+            issues.push(Issue::FieldOutOfRange {
+                list,
+                index,
+                field: param.field,
+                value,
+            });
+        }
+    }
+}
+
+fn clamp_profile(profile: &mut Profile) {
+    for param in PARAMS {
+        let value = param.get(profile);
+        param.set(profile, value);
+    }
+}
+
+fn is_all_zero<T: ByteStruct>(value: &T) -> bool {
+    let mut bytes = vec![0u8; T::BYTE_LEN];
+    value.write_bytes(&mut bytes[..]);
+    bytes.iter().all(|&b| b == 0)
+}
+
+// Returns all issues that are found in the raw database data, including the CRC mismatches.
+pub fn validate_bytes(data: &[u8]) -> Result<Vec<Issue>, Error> {
+    let database = Database::from_bytes_unchecked(data)?;
+    let mut issues: Vec<Issue> = Database::crc_mismatches(data)
+        .into_iter()
+        .filter_map(|e| match e {
+            Error::BadCrc {
+                region,
+                expected,
+                found,
+            } => Some(Issue::BadCrc {
+                region,
+                expected,
+                found,
+            }),
+            _ => None,
+        })
+        .collect();
+    issues.append(&mut database.validate());
+    Ok(issues)
+}
+
+impl Database {
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = vec![];
+
+        if self.owned[0].main.is_null() {
+            issues.push(Issue::MissingPersonalProfile);
+        }
+
+        let mut slot_map: [Option<usize>; 100] = [None; 100];
+        for (index, profile) in self.owned.iter().enumerate() {
+            if profile.main.is_null() {
+                if !is_all_zero(profile) {
+                    issues.push(Issue::NullProfileNotEmpty {
+                        list: ProfileList::Owned,
+                        index,
+                    });
+                }
+                continue;
+            }
+            match profile.main.try_get_slot() {
+                None => issues.push(Issue::InvalidSlot { index }),
+                Some(slot) => match slot_map[slot] {
+                    Some(other) => issues.push(Issue::DuplicateSlot { slot, index, other }),
+                    None => slot_map[slot] = Some(index),
+                },
+            }
+            check_profile(&profile.main, ProfileList::Owned, index, &mut issues);
+        }
+
+        for (index, profile) in self.invited.iter().enumerate() {
+            if profile.is_null() {
+                if !is_all_zero(profile) {
+                    issues.push(Issue::NullProfileNotEmpty {
+                        list: ProfileList::Invited,
+                        index,
+                    });
+                }
+                continue;
+            }
+            check_profile(profile, ProfileList::Invited, index, &mut issues);
+        }
+        if !self.invited_order_is_consistent() {
+            issues.push(Issue::InvitedOrderMismatch);
+        }
+
+        let order = self.cfhe_order();
+        if let Some(&head) = order.first() {
+            if self.cfhe_objects[head].list_node.pf != 0 {
+                issues.push(Issue::CfheBrokenLink { node: head });
+            }
+        }
+        for pair in order.windows(2) {
+            let node = &self.cfhe_objects[pair[1]].list_node;
+            if node.pf == 0 || node.prev as usize != pair[0] {
+                issues.push(Issue::CfheBrokenLink { node: pair[1] });
+            }
+        }
+        if let Some(&last) = order.last() {
+            if self.cfhe_objects[last].list_node.nf != 0 {
+                issues.push(Issue::CfheBrokenLink { node: last });
+            }
+            if self.cfhe_tail as usize != last {
+                issues.push(Issue::CfheTailMismatch {
+                    expected: last,
+                    found: self.cfhe_tail as usize,
+                });
+            }
+        }
+        let mut linked = vec![false; self.cfhe_objects.len()];
+        for &node in &order {
+            linked[node] = true;
+        }
+        for (node, object) in self.cfhe_objects.iter().enumerate() {
+            if !linked[node] && !object.profile_id.is_null() {
+                issues.push(Issue::CfheUnreachableNode { node });
+            }
+        }
+
+        issues
+    }
+
+    // Fixes all repairable issues reported by validate() and returns them. The CRCs are
+    // recomputed when the database is written back with to_bytes().
+    pub fn repair(&mut self) -> Vec<Issue> {
+        let issues = self.validate();
+        let mut fixed = vec![];
+        let mut relink_cfhe = false;
+        let mut fix_invited_order = false;
+        for issue in issues {
+            match &issue {
+                Issue::MissingPersonalProfile | Issue::BadCrc { .. } => continue,
+                Issue::NullProfileNotEmpty {
+                    list: ProfileList::Owned,
+                    index,
+                } => self.owned[*index] = ProfileFull::default(),
+                Issue::NullProfileNotEmpty {
+                    list: ProfileList::Invited,
+                    index,
+                } => self.invited[*index] = Profile::default(),
+                Issue::InvalidSlot { index } | Issue::DuplicateSlot { index, .. } => {
+                    // The profile is detached first so that it doesn't occupy its own slot
+                    let profile = std::mem::take(&mut self.owned[*index]);
+                    match self.free_owned_slot() {
+                        Some(slot) => {
+                            self.owned[*index] = profile;
+                            self.owned[*index].main.set_slot(slot);
+                        }
+                        None => {
+                            self.owned[*index] = profile;
+                            continue;
+                        }
+                    }
+                }
+                Issue::FieldOutOfRange {
+                    list: ProfileList::Owned,
+                    index,
+                    ..
+                } => clamp_profile(&mut self.owned[*index].main),
+                Issue::FieldOutOfRange {
+                    list: ProfileList::Invited,
+                    index,
+                    ..
+                } => clamp_profile(&mut self.invited[*index]),
+                Issue::InvitedOrderMismatch => fix_invited_order = true,
+                Issue::CfheBrokenLink { .. }
+                | Issue::CfheTailMismatch { .. }
+                | Issue::CfheUnreachableNode { .. } => relink_cfhe = true,
+            }
+            fixed.push(issue);
+        }
+
+        if fix_invited_order {
+            self.fix_invited_order();
+        }
+        if relink_cfhe {
+            self.relink_cfhe();
+        }
+
+        fixed
+    }
+
+    // Rebuilds the CFHE list. Nodes reachable from the head keep their order, and unreachable
+    // nodes are appended after them ordered by their encounter timestamp.
+    fn relink_cfhe(&mut self) {
+        let mut order = self.cfhe_order();
+        let mut linked = vec![false; self.cfhe_objects.len()];
+        for &node in &order {
+            linked[node] = true;
+        }
+        let mut unreachable: Vec<usize> = (0..self.cfhe_objects.len())
+            .filter(|&node| !linked[node] && !self.cfhe_objects[node].profile_id.is_null())
+            .collect();
+        unreachable.sort_by_key(|&node| self.cfhe_profiles[node].timestamp);
+        order.append(&mut unreachable);
+
+        self.set_cfhe_order(&order);
+    }
+}
+
+#[test]
+fn repair_test() {
+    let mut database = test_database();
+    database.owned[0].main = test_profile(1, 0, 0);
+    database.owned[1].main = test_profile(1, 0, 0);
+    database.owned[1].main.hair.style = 200;
+    assert_eq!(
+        database.validate(),
+        vec![
+            Issue::DuplicateSlot {
+                slot: 0,
+                index: 1,
+                other: 0
+            },
+            Issue::FieldOutOfRange {
+                list: ProfileList::Owned,
+                index: 1,
+                field: "hair.style",
+                value: 200
+            }
+        ]
+    );
+    assert_eq!(database.repair().len(), 2);
+    assert_eq!(database.validate(), vec![]);
+    assert_eq!(database.owned[1].main.get_slot(), 1);
+    assert_eq!(database.owned[1].main.hair.style, 131);
+}